@@ -1,57 +1,867 @@
-use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 
-use crate::lambda::recursive::LambdaSoup;
-use crate::utils::HeapObject;
+use crate::lambda::recursive::{reduce_within, term_size, LambdaSoup, ReductionLimits};
 
-use lambda_calculus::Term;
+use lambda_calculus::{app, combinators, IntoChurchNum, Term, NOR};
+use serde::{Deserialize, Serialize};
+
+/// What an expression does to one probe term: the canonical normal form of the expression
+/// applied to the probe, or `Bottom` if that application did not normalize within the limits.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ProbeOutput {
+    Normal(Term),
+    Bottom,
+}
+
+impl fmt::Display for ProbeOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeOutput::Normal(term) => write!(f, "{}", term),
+            ProbeOutput::Bottom => write!(f, "⊥"),
+        }
+    }
+}
+
+/// How many expressions in a soup are Church numerals. See `church_numeral_census`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NumeralCensus {
+    /// The number of expressions that normalize to each numeral. Numerals with no expressions
+    /// are left out.
+    pub counts: HashMap<usize, usize>,
+
+    /// The number of expressions that did not normalize within the reduction limits, so might
+    /// or might not be numerals
+    pub unknown: usize,
+}
+
+/// The expressions in a soup that pass some test: how many there are, counting copies, and one
+/// canonical example of each distinct expression, in order of first appearance.
+///
+/// The identity passes both `count_self_reproducers` and `count_copiers`; their
+/// `exclude_identity` flag leaves it out of the tally.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpressionTally {
+    pub count: usize,
+    pub examples: Vec<Term>,
+}
+
+/// The standard combinators that `combinator_census` looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Combinator {
+    S,
+    K,
+    I,
+    B,
+    C,
+    W,
+
+    /// Any fixed-point combinator, not just Curry's
+    Y,
+}
+
+impl Combinator {
+    /// The combinators that have a normal form, with their encodings.
+    fn normal_forms() -> [(Term, Combinator); 6] {
+        [
+            (combinators::S(), Combinator::S),
+            (combinators::K(), Combinator::K),
+            (combinators::I(), Combinator::I),
+            (combinators::B(), Combinator::B),
+            (combinators::C(), Combinator::C),
+            (combinators::W(), Combinator::W),
+        ]
+    }
+}
+
+/// How many reduction steps of `e f` to search for a term of the form `f t`, where `e f`
+/// reduces to `t`, when checking whether `e` is a fixed-point combinator.
+const FIXED_POINT_STEPS: usize = 64;
+
+/// The outputs of an expression on each of a list of probe terms, in the order of the probes.
+/// Expressions with equal fingerprints behave the same on those probes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint(pub Vec<ProbeOutput>);
+
+/// Counts of values in buckets with fixed edges. With edges `e_0 < e_1 < ... < e_k`, the first
+/// bucket holds values below `e_0`, the next ones `[e_0, e_1)` up to `[e_(k-1), e_k)`, and the
+/// last one values of at least `e_k`. Histograms with the same edges can be merged, for
+/// instance to pool the polls of many replicate soups.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Histogram {
+    edges: Vec<usize>,
+    counts: Vec<usize>,
+}
+
+impl Histogram {
+    /// An empty histogram with the given bucket edges, which must be strictly increasing.
+    pub fn new(edges: Vec<usize>) -> Self {
+        assert!(
+            edges.windows(2).all(|w| w[0] < w[1]),
+            "histogram edges must be strictly increasing"
+        );
+        let counts = vec![0; edges.len() + 1];
+        Histogram { edges, counts }
+    }
+
+    /// An empty histogram with edges `1, 2, 4, ...` up to `2^(k - 1)`. Panics if `k` is more
+    /// than `usize::BITS`, since the last edge would not fit in a `usize`.
+    pub fn powers_of_two(k: u32) -> Self {
+        assert!(
+            k <= usize::BITS,
+            "at most {} powers of two fit in a usize",
+            usize::BITS
+        );
+        Histogram::new((0..k).map(|i| 1usize << i).collect())
+    }
+
+    pub fn add(&mut self, value: usize) {
+        let bucket = self.edges.partition_point(|&e| e <= value);
+        self.counts[bucket] += 1;
+    }
+
+    pub fn edges(&self) -> &[usize] {
+        &self.edges
+    }
+
+    /// The number of values in each bucket, from the lowest to the highest.
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    /// Add the counts of `other`, which must have the same edges, to this histogram.
+    pub fn merge(&mut self, other: &Histogram) {
+        assert_eq!(
+            self.edges, other.edges,
+            "only histograms with the same edges can be merged"
+        );
+        for (c, o) in self.counts.iter_mut().zip(&other.counts) {
+            *c += o;
+        }
+    }
+
+    /// A label for each bucket in Rust range notation, such as `..1`, `1..2` and `4..`.
+    pub fn labels(&self) -> Vec<String> {
+        let lower = [None].into_iter().chain(self.edges.iter().map(Some));
+        let upper = self.edges.iter().map(Some).chain([None]);
+        lower
+            .zip(upper)
+            .map(|(lo, hi)| {
+                let bound = |b: Option<&usize>| b.map(|b| b.to_string()).unwrap_or_default();
+                format!("{}..{}", bound(lo), bound(hi))
+            })
+            .collect()
+    }
+}
+
+/// How different the populations of two soups are. See `LambdaSoup::divergence_from`. Two
+/// empty soups are identical, and an empty soup is as different as can be from any other.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DivergenceReport {
+    /// Jensen-Shannon divergence between the class distributions, in bits, from `0.0` for equal
+    /// distributions to `1.0` for soups with no class in common
+    pub jensen_shannon: f64,
+
+    /// Number of classes in both soups divided by the number in either
+    pub jaccard: f64,
+
+    /// Total variation distance between the class distributions: the largest difference in
+    /// the share of the population any set of classes can have in the two soups, from `0.0`
+    /// to `1.0`
+    pub total_variation: f64,
+}
+
+/// Diversity of a soup's population, all computed from the same class counts. See
+/// `LambdaSoup::diversity`. An empty soup has every measure `0.0`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DiversityMetrics {
+    /// Shannon entropy in bits
+    pub shannon_bits: f64,
+
+    /// Shannon entropy in nats
+    pub shannon_nats: f64,
+
+    /// Probability that two expressions drawn with replacement are the same
+    pub simpson_index: f64,
+
+    /// Number of alpha-equivalence classes
+    pub richness: usize,
+
+    /// Pielou's evenness: the entropy divided by its largest possible value, `ln(richness)`.
+    /// `0.0` when there are fewer than two classes.
+    pub evenness: f64,
+
+    /// Hill number of order 0, which is the richness
+    pub hill_0: f64,
+
+    /// Hill number of order 1, `exp(shannon_nats)`
+    pub hill_1: f64,
+
+    /// Hill number of order 2, `1 / simpson_index`
+    pub hill_2: f64,
+}
 
 impl LambdaSoup {
-    // This is expensive, quadratic in the number of expressions. It can
-    // probably be written to be faster, but it's not a bottleneck right now.
+    /// One canonical representative of each alpha-equivalence class in the soup. Terms are
+    /// stored with de Bruijn indices, so alpha-variants such as `\x.x` and `\y.y` are already
+    /// one class, and the entropy and diversity measures count functions rather than spellings.
+    /// Use `lambda::canonical::canonical_hash` to hash a class without building its form.
     pub fn unique_expressions(&self) -> HashSet<Term> {
-        HashSet::<Term>::from_iter(self.lambda_expressions().cloned())
+        self.expression_counts().into_keys().collect()
     }
 
+    /// The number of expressions in each alpha-equivalence class in the soup, keyed by the
+    /// canonical form of the class.
     pub fn expression_counts(&self) -> HashMap<Term, u32> {
         let mut map = HashMap::<Term, u32>::new();
-        for expr in self.lambda_expressions().cloned() {
-            *map.entry(expr).or_default() += 1
+        for (particle, count) in self.live_counts() {
+            *map.entry(particle.get_underlying_term().clone())
+                .or_default() += count as u32
         }
         map
     }
 
-    // The use of HeapObject is a code smell, refactor later
+    /// Get the `k` most common expressions in the soup, most common first. Ties are broken by
+    /// the `Classic` notation of the terms, so the result is the same across runs. If there are
+    /// fewer than `k` distinct expressions, all of them are returned.
     pub fn k_most_frequent_exprs(&self, k: usize) -> Vec<Term> {
-        let mut map = HashMap::<&Term, u32>::new();
-        for x in self.lambda_expressions() {
-            *map.entry(x).or_default() += 1;
+        let mut counts = self.counts_with_notation();
+        counts.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        counts
+            .into_iter()
+            .take(k)
+            .map(|(_, _, term)| term)
+            .collect()
+    }
+
+    /// Get the `k` least common expressions in the soup, least common first. Ties, including
+    /// ties at the `k`th position, are broken by the `Classic` notation of the terms, so exactly
+    /// `k` expressions are returned if there are at least `k` distinct ones.
+    pub fn k_least_frequent_exprs(&self, k: usize) -> Vec<Term> {
+        let mut counts = self.counts_with_notation();
+        counts.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+        counts
+            .into_iter()
+            .take(k)
+            .map(|(_, _, term)| term)
+            .collect()
+    }
+
+    fn counts_with_notation(&self) -> Vec<(u32, String, Term)> {
+        self.expression_counts()
+            .into_iter()
+            .map(|(term, count)| (count, term.to_string(), term))
+            .collect()
+    }
+
+    /// Count the expressions in the soup by their depth, as given by `Term::max_depth`.
+    pub fn depth_histogram(&self) -> BTreeMap<u32, usize> {
+        let mut histogram = BTreeMap::new();
+        for entry in &self.expressions {
+            *histogram.entry(entry.depth).or_default() += 1;
+        }
+        histogram
+    }
+
+    /// Count the expressions in the soup by their size, in nodes, as given by `term_size`.
+    pub fn size_histogram(&self) -> BTreeMap<usize, usize> {
+        let mut histogram = BTreeMap::new();
+        for entry in &self.expressions {
+            *histogram.entry(entry.size).or_default() += 1;
+        }
+        histogram
+    }
+
+    /// Count the expressions in the soup by their size, in nodes, into buckets with the given
+    /// `edges`. See `Histogram`.
+    pub fn size_distribution(&self, edges: &[usize]) -> Histogram {
+        let mut histogram = Histogram::new(edges.to_vec());
+        for entry in &self.expressions {
+            histogram.add(entry.size);
+        }
+        histogram
+    }
+
+    /// Count the expressions in the soup by their depth into buckets with the given `edges`.
+    /// See `Histogram`.
+    pub fn depth_distribution(&self, edges: &[usize]) -> Histogram {
+        let mut histogram = Histogram::new(edges.to_vec());
+        for entry in &self.expressions {
+            histogram.add(entry.depth as usize);
+        }
+        histogram
+    }
+
+    /// `(depth, count)` pairs for every depth present in the soup, sorted by depth. The same
+    /// data as `depth_histogram`, flattened for plotting and CSV output.
+    pub fn expression_complexity_distribution(&self) -> Vec<(usize, usize)> {
+        self.depth_histogram()
+            .into_iter()
+            .map(|(depth, count)| (depth as usize, count))
+            .collect()
+    }
+
+    /// `(size, count)` pairs for every size in nodes present in the soup, sorted by size.
+    pub fn expression_size_distribution(&self) -> Vec<(usize, usize)> {
+        self.size_histogram().into_iter().collect()
+    }
+
+    /// Total number of `Var`, `Abs` and `App` nodes over all expressions in the soup. Unlike
+    /// the population size, this is not held constant by the reactor, so it shows whether the
+    /// reactions are growing or shrinking the expressions.
+    pub fn total_mass(&self) -> usize {
+        self.expressions.iter().map(|e| e.size).sum()
+    }
+
+    /// Count the expressions in the soup that are isomorphic to `target`, so alpha-equivalent
+    /// spellings of the same term are counted together. An empty soup has a population of `0`.
+    pub fn population_of(&self, target: &Term) -> usize {
+        self.expression_multiplicities()
+            .into_iter()
+            .filter(|(p, _)| p.get_underlying_term() == target)
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// The size of each alpha-equivalence class in the soup, in no particular order. Every
+    /// diversity and entropy measure is computed from these.
+    fn class_sizes(&self) -> Vec<u32> {
+        self.expression_counts().into_values().collect()
+    }
+
+    /// Every diversity measure of `DiversityMetrics`, from a single pass over the class counts.
+    /// `population_entropy`, `normalized_entropy` and `simpson_index` read their values from
+    /// here, and return them as `f32`s.
+    pub fn diversity(&self) -> DiversityMetrics {
+        let n = self.len() as f64;
+        if n == 0.0 {
+            return DiversityMetrics::default();
+        }
+        let counts = self.class_sizes();
+        let (mut shannon_nats, mut simpson_index) = (0.0, 0.0);
+        for &count in &counts {
+            let p = count as f64 / n;
+            shannon_nats -= p * p.ln();
+            simpson_index += p * p;
+        }
+        let richness = counts.len();
+        let evenness = if richness < 2 {
+            0.0
+        } else {
+            shannon_nats / (richness as f64).ln()
+        };
+        DiversityMetrics {
+            shannon_bits: shannon_nats / std::f64::consts::LN_2,
+            shannon_nats,
+            simpson_index,
+            richness,
+            evenness,
+            hill_0: richness as f64,
+            hill_1: shannon_nats.exp(),
+            hill_2: simpson_index.recip(),
+        }
+    }
+
+    /// Fraction of the expressions in the soup that are already in beta normal form, or `0.0`
+    /// for an empty soup. Each alpha-equivalence class is checked once, by a walk over its
+    /// canonical form rather than by reducing it, so this is cheap enough to poll often.
+    pub fn normal_form_fraction(&self) -> f64 {
+        let counts = self.expression_counts();
+        let total = counts.values().sum::<u32>();
+        if total == 0 {
+            return 0.0;
+        }
+        let normal = counts
+            .iter()
+            .filter(|(expr, _)| is_beta_normal(expr))
+            .map(|(_, &count)| count)
+            .sum::<u32>();
+        normal as f64 / total as f64
+    }
+
+    /// Shannon entropy of the population, in base 10 and as an `f32` for compatibility with
+    /// earlier results; `diversity` gives it in bits and nats. See `population_entropy_base`.
+    pub fn population_entropy(&self) -> f32 {
+        self.population_entropy_base(10.0) as f32
+    }
+
+    /// Shannon entropy of the population, using logarithms of the given `base`: `2.0` for bits,
+    /// `std::f64::consts::E` for nats. Returns `NaN` if `base` is not greater than `1.0`.
+    pub fn population_entropy_base(&self, base: f64) -> f64 {
+        if base.is_nan() || base <= 1.0 {
+            return f64::NAN;
+        }
+        self.diversity().shannon_nats / base.ln()
+    }
+
+    /// End `simulate_for` and `simulate_and_poll` early once the population entropy settles:
+    /// when its standard deviation over the last `window` samples falls below `tolerance`.
+    /// Use `simulate_for_until_stable` or `simulate_and_poll_until_stable` to learn whether a
+    /// run converged.
+    pub fn halt_on_convergence(&mut self, window: usize, tolerance: f32) {
+        self.halt_when_stable(Self::population_entropy, window, tolerance);
+    }
+
+    /// Pielou's evenness of the population: its entropy divided by the largest entropy possible
+    /// with as many distinct expressions. Ranges from `0.0` to `1.0`, and is `0.0` when there
+    /// are fewer than two distinct expressions. This is `DiversityMetrics::evenness`.
+    pub fn normalized_entropy(&self) -> f32 {
+        self.diversity().evenness as f32
+    }
+
+    /// Simpson index of the population: the probability that two expressions drawn with
+    /// replacement are the same. Returns `0.0` for an empty soup. This is
+    /// `DiversityMetrics::simpson_index`.
+    pub fn simpson_index(&self) -> f32 {
+        self.diversity().simpson_index as f32
+    }
+
+    /// Gini-Simpson index of the population: the probability that two expressions drawn with
+    /// replacement differ. Returns `0.0` for an empty soup.
+    pub fn gini_simpson_index(&self) -> f32 {
+        if self.len() == 0 {
+            return 0.0;
         }
+        1.0 - self.simpson_index()
+    }
 
-        let mut heap = BinaryHeap::with_capacity(k + 1);
-        for (x, count) in map.into_iter() {
-            heap.push(Reverse(HeapObject::new(count, x)));
-            if heap.len() > k {
-                heap.pop();
+    /// Simpson's index of the population, computed without replacement: the probability that
+    /// two distinct expressions drawn from the soup are the same,
+    /// `sum(n_i * (n_i - 1)) / (N * (N - 1))`. Ranges from `0.0`, when every expression is
+    /// distinct, to `1.0`, when they are all the same. Returns `0.0` for a soup with fewer
+    /// than two expressions.
+    pub fn population_simpson_index(&self) -> f64 {
+        let n = self.len() as f64;
+        if n < 2.0 {
+            return 0.0;
+        }
+        let pairs = self
+            .class_sizes()
+            .into_iter()
+            .map(|c| c as f64 * (c as f64 - 1.0))
+            .sum::<f64>();
+        pairs / (n * (n - 1.0))
+    }
+
+    /// Simpson's diversity, `1 - D`, where `D` is `population_simpson_index`: the probability
+    /// that two distinct expressions drawn from the soup differ.
+    pub fn simpson_diversity(&self) -> f64 {
+        1.0 - self.population_simpson_index()
+    }
+
+    /// The reciprocal `1 / D` of `population_simpson_index`, which can be read as the effective
+    /// number of species in the soup. Where `D` is `0.0` this is finite instead: `0.0` for an
+    /// empty soup, `1.0` for a single expression, and the number of expressions when every
+    /// expression is distinct.
+    pub fn effective_species(&self) -> f64 {
+        match self.len() {
+            0 => 0.0,
+            1 => 1.0,
+            n => match self.population_simpson_index() {
+                0.0 => n as f64,
+                d => d.recip(),
+            },
+        }
+    }
+
+    /// Variance of the number of copies of each distinct expression, `E[(n_i - mean)^2]` over
+    /// the alpha-equivalence classes in the soup. A soup dominated by a few expressions has a
+    /// high variance even when its entropy is moderate. Returns `0.0` for an empty soup.
+    pub fn population_variance(&self) -> f64 {
+        let (_, variance, _) = self.count_moments();
+        variance
+    }
+
+    /// Skewness, the third standardized moment, of the number of copies of each distinct
+    /// expression. Positive when a few expressions have many more copies than the rest.
+    /// Returns `0.0` when every expression has the same number of copies.
+    pub fn population_skewness(&self) -> f64 {
+        let (_, variance, third) = self.count_moments();
+        if variance == 0.0 {
+            return 0.0;
+        }
+        third / variance.powf(1.5)
+    }
+
+    /// The mean of the counts of the classes, and their second and third central moments.
+    fn count_moments(&self) -> (f64, f64, f64) {
+        let counts = self.expression_counts();
+        if counts.is_empty() {
+            return (0.0, 0.0, 0.0);
+        }
+        let k = counts.len() as f64;
+        let mean = self.len() as f64 / k;
+        let moment = |p| {
+            counts
+                .values()
+                .map(|&c| (c as f64 - mean).powi(p))
+                .sum::<f64>()
+                / k
+        };
+        (mean, moment(2), moment(3))
+    }
+
+    /// L1 distance between the normalized count distributions `prev_counts` and `curr_counts`,
+    /// typically two consecutive results of `expression_counts`. Ranges from `0.0` (identical
+    /// composition) to `2.0` (no expressions in common). Terms missing from one of the
+    /// snapshots count as zero there.
+    pub fn population_flux(
+        prev_counts: &HashMap<Term, u32>,
+        curr_counts: &HashMap<Term, u32>,
+    ) -> f32 {
+        let prev_total = prev_counts.values().sum::<u32>().max(1) as f32;
+        let curr_total = curr_counts.values().sum::<u32>().max(1) as f32;
+
+        let mut flux = 0.0;
+        for (term, prev) in prev_counts {
+            let p = (*prev as f32) / prev_total;
+            let q = (*curr_counts.get(term).unwrap_or(&0) as f32) / curr_total;
+            flux += (p - q).abs();
+        }
+        for (term, curr) in curr_counts {
+            if !prev_counts.contains_key(term) {
+                flux += (*curr as f32) / curr_total;
             }
         }
-        heap.into_sorted_vec()
+        flux
+    }
+
+    /// Simulate the soup for `n` collisions, polling `expression_counts` every `interval`
+    /// collisions, and return the flux between each pair of consecutive polls.
+    pub fn simulate_and_poll_flux(&mut self, n: usize, interval: usize) -> Vec<f32> {
+        let counts = self.simulate_and_poll(n, interval, false, |s| s.expression_counts());
+        counts
+            .windows(2)
+            .map(|w| Self::population_flux(&w[0], &w[1]))
+            .collect()
+    }
+
+    /// Group the distinct expressions in the soup by what they do to each of the `probes`. Each
+    /// expression is applied to each probe and reduced within `limits`; expressions with the
+    /// same outputs on every probe land in the same class. Classes, and the expressions within
+    /// them, are in order of first appearance in the soup.
+    pub fn behavioral_classes(
+        &self,
+        probes: &[Term],
+        limits: &ReductionLimits,
+    ) -> Vec<(Vec<Term>, Fingerprint)> {
+        let mut classes: Vec<(Vec<Term>, Fingerprint)> = Vec::new();
+        let mut index = HashMap::<Fingerprint, usize>::new();
+        let mut seen = HashSet::new();
+        for (particle, _) in self.expression_multiplicities() {
+            let expr = particle.get_underlying_term();
+            if !seen.insert(expr) {
+                continue;
+            }
+            let fingerprint = Fingerprint(
+                probes
+                    .iter()
+                    .map(|probe| apply_to_probe(expr, probe, limits))
+                    .collect(),
+            );
+            match index.get(&fingerprint) {
+                Some(&i) => classes[i].0.push(expr.clone()),
+                None => {
+                    index.insert(fingerprint.clone(), classes.len());
+                    classes.push((vec![expr.clone()], fingerprint));
+                }
+            }
+        }
+        classes
+    }
+
+    /// Count the expressions in the soup that are beta-equivalent to the Church numerals from
+    /// `0` to `max_n`. Each distinct expression is reduced within the reduction limits of the
+    /// soup's collider before it is compared with the numerals.
+    pub fn church_numeral_census(&self, max_n: usize) -> NumeralCensus {
+        let numerals = (0..=max_n)
+            .map(|n| (n.into_church(), n))
+            .collect::<HashMap<_, _>>();
+        let limits = self.collider.reduction_limits();
+        let mut census = NumeralCensus::default();
+        for (particle, count) in self.expression_multiplicities() {
+            let mut expr = particle.get_underlying_term().clone();
+            if !reduce_within(&mut expr, &limits).is_normalized() {
+                census.unknown += count;
+            } else if let Some(&n) = numerals.get(&expr) {
+                *census.counts.entry(n).or_default() += count;
+            }
+        }
+        census
+    }
+
+    /// Count the expressions in the soup that behave as each of the standard combinators.
+    /// Expressions are reduced within the reduction limits of the soup's collider and compared
+    /// with the encodings of S, K, I, B, C and W. Fixed-point combinators have no normal form,
+    /// so a closed expression `e` that does not normalize counts as Y if `e f`, for a free
+    /// variable `f`, reduces to `f t` for some `t` that `e f` also reduces to. Combinators with
+    /// no expressions are left out.
+    pub fn combinator_census(&self) -> HashMap<Combinator, usize> {
+        let normal_forms = Combinator::normal_forms()
             .into_iter()
-            .map(|r| {
-                let tup = r.0.to_tuple();
-                tup.1.clone()
+            .collect::<HashMap<_, _>>();
+        let limits = self.collider.reduction_limits();
+        let mut census = HashMap::new();
+        for (particle, count) in self.expression_multiplicities() {
+            let term = particle.get_underlying_term();
+            let mut expr = term.clone();
+            let combinator = if reduce_within(&mut expr, &limits).is_normalized() {
+                normal_forms.get(&expr).copied()
+            } else if !term.has_free_variables() && is_fixed_point_combinator(term) {
+                Some(Combinator::Y)
+            } else {
+                None
+            };
+            if let Some(combinator) = combinator {
+                *census.entry(combinator).or_default() += count;
+            }
+        }
+        census
+    }
+
+    /// Kullback-Leibler divergence `KL(self || other)`, in nats, between the distributions of
+    /// alpha-equivalence classes in the two soups. Returns `None` if either soup is empty, or
+    /// if `self` holds an expression that `other` does not, which would make the divergence
+    /// infinite. With `smoothing` set to some `ε > 0`, `ε` is added to the count of every
+    /// expression in either soup first, so the divergence is always finite.
+    pub fn population_kl_divergence(
+        &self,
+        other: &LambdaSoup,
+        smoothing: Option<f64>,
+    ) -> Option<f64> {
+        let p_counts = self.expression_counts();
+        let q_counts = other.expression_counts();
+        let eps = smoothing.unwrap_or(0.0);
+        let support = p_counts
+            .keys()
+            .chain(q_counts.keys())
+            .collect::<HashSet<_>>();
+        let count = |counts: &HashMap<Term, u32>, t: &Term| {
+            counts.get(t).copied().unwrap_or(0) as f64 + eps
+        };
+        let p_total = self.len() as f64 + eps * support.len() as f64;
+        let q_total = other.len() as f64 + eps * support.len() as f64;
+        if p_total == 0.0 || q_total == 0.0 {
+            return None;
+        }
+
+        let mut divergence = 0.0;
+        for term in support {
+            let p = count(&p_counts, term) / p_total;
+            let q = count(&q_counts, term) / q_total;
+            if p == 0.0 {
+                continue;
+            }
+            if q == 0.0 {
+                return None;
+            }
+            divergence += p * (p / q).ln();
+        }
+        Some(divergence)
+    }
+
+    /// Compare the populations of two soups, whose expressions are grouped into
+    /// alpha-equivalence classes by canonical form.
+    pub fn divergence_from(&self, other: &LambdaSoup) -> DivergenceReport {
+        let (p_counts, q_counts) = (self.expression_counts(), other.expression_counts());
+        match (p_counts.is_empty(), q_counts.is_empty()) {
+            (true, true) => {
+                return DivergenceReport {
+                    jensen_shannon: 0.0,
+                    jaccard: 1.0,
+                    total_variation: 0.0,
+                }
+            }
+            (true, false) | (false, true) => {
+                return DivergenceReport {
+                    jensen_shannon: 1.0,
+                    jaccard: 0.0,
+                    total_variation: 1.0,
+                }
+            }
+            (false, false) => {}
+        }
+
+        let (p_total, q_total) = (self.len() as f64, other.len() as f64);
+        let support = p_counts
+            .keys()
+            .chain(q_counts.keys())
+            .collect::<HashSet<_>>();
+        let (mut jensen_shannon, mut total_variation, mut shared) = (0.0, 0.0, 0);
+        for term in &support {
+            let p = p_counts.get(*term).map_or(0.0, |&c| c as f64 / p_total);
+            let q = q_counts.get(*term).map_or(0.0, |&c| c as f64 / q_total);
+            let m = (p + q) / 2.0;
+            for x in [p, q] {
+                if x > 0.0 {
+                    jensen_shannon += x * (x / m).log2() / 2.0;
+                }
+            }
+            total_variation += (p - q).abs() / 2.0;
+            if p > 0.0 && q > 0.0 {
+                shared += 1;
+            }
+        }
+        DivergenceReport {
+            jensen_shannon,
+            jaccard: shared as f64 / support.len() as f64,
+            total_variation,
+        }
+    }
+
+    /// Count the self-reproducers in the soup: expressions `e` such that `e e` and `e` reduce
+    /// to the same normal form within `limits`. See `ExpressionTally` for `exclude_identity`.
+    pub fn count_self_reproducers(
+        &self,
+        limits: &ReductionLimits,
+        exclude_identity: bool,
+    ) -> ExpressionTally {
+        self.tally(exclude_identity, |e| {
+            reduces_to(&Term::App(Box::new((e.clone(), e.clone()))), e, limits)
+        })
+    }
+
+    /// Count the copiers in the soup: expressions `c` such that `c x` and `x` reduce to the
+    /// same normal form within `limits`, for every `x` in `probes`. See `ExpressionTally` for
+    /// `exclude_identity`.
+    pub fn count_copiers(
+        &self,
+        probes: &[Term],
+        limits: &ReductionLimits,
+        exclude_identity: bool,
+    ) -> ExpressionTally {
+        self.tally(exclude_identity, |c| {
+            probes
+                .iter()
+                .all(|x| reduces_to(&Term::App(Box::new((c.clone(), x.clone()))), x, limits))
+        })
+    }
+
+    /// The distinct expressions `t` in the soup, up to alpha-equivalence, for which `t t`
+    /// normalizes within the reduction limits of the soup's collider to a term `r` that passes
+    /// `predicate(t, r)`. Without a predicate, `r` must be isomorphic to `t` itself, which makes
+    /// `t` a self-replicating term. Terms are in order of first appearance.
+    pub fn find_fixed_points(&self, predicate: Option<fn(&Term, &Term) -> bool>) -> Vec<Term> {
+        let predicate = predicate.unwrap_or(|t, r| r.is_isomorphic_to(t));
+        let limits = self.collider.reduction_limits();
+        self.distinct_terms()
+            .into_iter()
+            .filter(|t| {
+                let mut tt = Term::App(Box::new((t.clone(), t.clone())));
+                reduce_within(&mut tt, &limits).is_normalized() && predicate(t, &tt)
             })
             .collect()
     }
 
-    pub fn population_entropy(&self) -> f32 {
-        let mut entropy = 0.0;
-        let n = self.len() as f32;
-        for (_, value) in self.expression_counts().iter() {
-            let pi = (*value as f32) / n;
-            entropy -= pi * pi.log10();
+    /// The distinct species `x` in the soup, up to alpha-equivalence, that replicate under the
+    /// first reaction rule: colliding `x` with itself normalizes, within the limits the soup
+    /// reduces that rule with, to a term isomorphic to `x`. Collisions that hit the limits are
+    /// skipped. Unlike `find_fixed_points`, which applies `x` to itself directly, this sees
+    /// replicators as the reactor does. Returns nothing if the soup has no rules.
+    pub fn find_replicators(&self) -> Vec<Term> {
+        let Some(rule) = self.rules().first() else {
+            return Vec::new();
+        };
+        let limits = self.collider.rule_limits(0);
+        self.distinct_terms()
+            .into_iter()
+            .filter(|x| {
+                let mut product = app!(rule.clone(), x.clone(), x.clone());
+                reduce_within(&mut product, &limits).is_normalized() && product.is_isomorphic_to(x)
+            })
+            .collect()
+    }
+
+    /// Find a pair of species `(a, b)` for which `test(a, b)` holds. Each alpha-equivalence
+    /// class is tried once, in order of first appearance, so duplicates don't add to the
+    /// quadratic scan. If `test` is `symmetric`, only one of `(a, b)` and `(b, a)` is tried.
+    pub fn find_interacting_pair(
+        &self,
+        test: impl Fn(&Term, &Term) -> bool,
+        symmetric: bool,
+    ) -> Option<(Term, Term)> {
+        find_pair(&self.distinct_terms(), test, symmetric)
+    }
+
+    /// Like `find_interacting_pair`, but only among the `k` most common species, as ordered by
+    /// `k_most_frequent_exprs`.
+    pub fn find_interacting_pair_in_top(
+        &self,
+        k: usize,
+        test: impl Fn(&Term, &Term) -> bool,
+        symmetric: bool,
+    ) -> Option<(Term, Term)> {
+        find_pair(&self.k_most_frequent_exprs(k), test, symmetric)
+    }
+
+    /// The closed subterms of at least `min_size` nodes that occur in the most distinct
+    /// expressions of the soup, with the number of distinct expressions each occurs in. A
+    /// subterm that refers to a binder outside it means something else on its own, so only
+    /// subterms without free variables are counted; each expression is a subterm of itself.
+    /// Ties are broken by `Classic` notation, as in `k_most_frequent_exprs`.
+    pub fn frequent_subterms(&self, min_size: usize, top_k: usize) -> Vec<(Term, usize)> {
+        let mut counts = HashMap::<Term, usize>::new();
+        for expr in self.distinct_terms() {
+            let mut found = HashSet::new();
+            closed_subterms(&expr, min_size, &mut found);
+            for subterm in found {
+                *counts.entry(subterm).or_default() += 1;
+            }
+        }
+        let mut counts = counts
+            .into_iter()
+            .map(|(term, count)| (count, term.to_string(), term))
+            .collect::<Vec<_>>();
+        counts.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        counts
+            .into_iter()
+            .take(top_k)
+            .map(|(count, _, term)| (term, count))
+            .collect()
+    }
+
+    /// The canonical forms of the distinct expressions in the soup, in order of first
+    /// appearance.
+    fn distinct_terms(&self) -> Vec<Term> {
+        let mut seen = HashSet::new();
+        self.expression_multiplicities()
+            .into_iter()
+            .map(|(particle, _)| particle.get_underlying_term())
+            .filter(|t| seen.insert(*t))
+            .cloned()
+            .collect()
+    }
+
+    /// Tally the expressions whose canonical forms pass `test`, testing each distinct one once.
+    fn tally(&self, exclude_identity: bool, test: impl Fn(&Term) -> bool) -> ExpressionTally {
+        let identity = combinators::I();
+        let mut tally = ExpressionTally::default();
+        let mut results = HashMap::new();
+        for (particle, count) in self.expression_multiplicities() {
+            let expr = particle.get_underlying_term();
+            if exclude_identity && *expr == identity {
+                continue;
+            }
+            let passed = match results.get(expr) {
+                Some(&passed) => passed,
+                None => {
+                    let passed = test(expr);
+                    if passed {
+                        tally.examples.push(expr.clone());
+                    }
+                    results.insert(expr, passed);
+                    passed
+                }
+            };
+            if passed {
+                tally.count += count;
+            }
         }
-        entropy
+        tally
     }
 
     pub fn jacard_index(&self, other: &LambdaSoup) -> f32 {
@@ -67,3 +877,614 @@ impl LambdaSoup {
         (intersection as f32) / ((self.len() + other.len()) as f32)
     }
 }
+
+/// Whether the closed term `e` is a fixed-point combinator: reducing `e f` in normal order one
+/// step at a time reaches `f t`, where `t` is `e f` or one of its earlier reducts.
+fn is_fixed_point_combinator(e: &Term) -> bool {
+    let f = Term::Var(1);
+    let mut expr = Term::App(Box::new((e.clone(), f.clone())));
+    let mut reducts = HashSet::from([expr.clone()]);
+    for _ in 0..FIXED_POINT_STEPS {
+        if expr.reduce(NOR, 1) == 0 {
+            return false;
+        }
+        if let Term::App(boxed) = &expr {
+            if boxed.0 == f && reducts.contains(&boxed.1) {
+                return true;
+            }
+        }
+        reducts.insert(expr.clone());
+    }
+    false
+}
+
+/// Add the closed subterms of `term` with at least `min_size` nodes, as given by `term_size`,
+/// to `found`. Returns the number of binders around `term` that its free variables need, which
+/// is zero if it is closed.
+fn closed_subterms(term: &Term, min_size: usize, found: &mut HashSet<Term>) -> usize {
+    let needed = match term {
+        Term::Var(i) => *i,
+        Term::Abs(body) => closed_subterms(body, min_size, found).saturating_sub(1),
+        Term::App(pair) => {
+            let left_needed = closed_subterms(&pair.0, min_size, found);
+            left_needed.max(closed_subterms(&pair.1, min_size, found))
+        }
+    };
+    if needed == 0 && term_size(term) >= min_size {
+        found.insert(term.clone());
+    }
+    needed
+}
+
+/// Whether `expr` contains no beta redex.
+fn is_beta_normal(expr: &Term) -> bool {
+    match expr {
+        Term::Var(_) => true,
+        Term::Abs(body) => is_beta_normal(body),
+        Term::App(pair) => {
+            !matches!(pair.0, Term::Abs(_)) && is_beta_normal(&pair.0) && is_beta_normal(&pair.1)
+        }
+    }
+}
+
+/// Whether `expr` and `target` both normalize within `limits`, to isomorphic terms.
+fn reduces_to(expr: &Term, target: &Term, limits: &ReductionLimits) -> bool {
+    let (mut expr, mut target) = (expr.clone(), target.clone());
+    reduce_within(&mut expr, limits).is_normalized()
+        && reduce_within(&mut target, limits).is_normalized()
+        && expr == target
+}
+
+fn apply_to_probe(expr: &Term, probe: &Term, limits: &ReductionLimits) -> ProbeOutput {
+    let mut applied = Term::App(Box::new((expr.clone(), probe.clone())));
+    if reduce_within(&mut applied, limits).is_normalized() {
+        ProbeOutput::Normal(applied)
+    } else {
+        ProbeOutput::Bottom
+    }
+}
+
+/// The first pair of `terms` that passes `test`, scanning row by row. A `symmetric` test stops
+/// each row at the diagonal.
+fn find_pair(
+    terms: &[Term],
+    test: impl Fn(&Term, &Term) -> bool,
+    symmetric: bool,
+) -> Option<(Term, Term)> {
+    for (i, t1) in terms.iter().enumerate() {
+        for (j, t2) in terms.iter().enumerate() {
+            if test(t1, t2) {
+                return Some((t1.clone(), t2.clone()));
+            }
+            if j >= i && symmetric {
+                break;
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::{HashMap, HashSet};
+    use std::time::Instant;
+
+    use lambda_calculus::{parse, Classic, IntoChurchNum, Term};
+
+    use super::{Combinator, DiversityMetrics, Fingerprint, Histogram, ProbeOutput};
+    use crate::config;
+    use crate::lambda::recursive::{term_size, LambdaSoup, ReductionLimits};
+    use crate::supercollider::Termination;
+
+    fn term(s: &str) -> Term {
+        parse(s, Classic).unwrap()
+    }
+
+    fn soup_of(exprs: &[&str]) -> LambdaSoup {
+        let mut soup = LambdaSoup::new();
+        soup.add_lambda_expressions(exprs.iter().map(|s| term(s)));
+        soup
+    }
+
+    #[test]
+    fn population_flux_ranges_from_zero_to_two() {
+        let counts = |exprs: &[(&str, u32)]| {
+            exprs
+                .iter()
+                .map(|(s, n)| (term(s), *n))
+                .collect::<HashMap<_, _>>()
+        };
+        let before = counts(&[(r"\x.x", 2), (r"\x.\y.x", 2)]);
+        let flux = |after| LambdaSoup::population_flux(&before, &after);
+        assert_eq!(flux(counts(&[(r"\x.x", 1), (r"\x.\y.x", 1)])), 0.0);
+        assert_eq!(flux(counts(&[(r"\x.x", 4)])), 1.0);
+        assert_eq!(flux(counts(&[(r"\x.x x", 3)])), 2.0);
+    }
+
+    #[test]
+    fn population_of_counts_isomorphic_terms() {
+        let soup = soup_of(&[r"\x.\y.x", r"\x.\y.x", r"\x.x x", r"\a.\b.a"]);
+        assert_eq!(soup.population_of(&term(r"\x.\y.x")), 3);
+        assert_eq!(soup.population_of(&term(r"\p.\q.p")), 3);
+        assert_eq!(soup.population_of(&term(r"\x.\y.y")), 0);
+        assert_eq!(LambdaSoup::new().population_of(&term(r"\x.x")), 0);
+    }
+
+    #[test]
+    fn alpha_variants_are_one_species() {
+        let soup = soup_of(&[r"\x.x", r"\y.y", r"\x.\y.x", r"\a.\b.a"]);
+        let renamed = soup_of(&[r"\x.x", r"\x.x", r"\x.\y.x", r"\x.\y.x"]);
+        assert_eq!(soup.unique_expressions().len(), 2);
+        assert_eq!(soup.unique_expressions(), renamed.unique_expressions());
+        assert_eq!(soup.population_entropy(), renamed.population_entropy());
+    }
+
+    #[test]
+    fn k_most_frequent_exprs_orders_by_count_then_notation() {
+        let (k, i, u, s) = (r"\x.\y.x", r"\x.x", r"\x.x x", r"\x.\y.\z.x z (y z)");
+        let soup = soup_of(&[i, k, u, s, k, u, k, i]);
+
+        let top = soup.k_most_frequent_exprs(3);
+        assert_eq!(top.len(), 3);
+        assert_eq!(top[0], term(k));
+        assert!(top[1..].contains(&term(i)) && top[1..].contains(&term(u)));
+        assert!(top[1].to_string() < top[2].to_string());
+
+        assert_eq!(soup.k_most_frequent_exprs(10).len(), 4);
+    }
+
+    #[test]
+    fn alpha_variants_are_counted_together() {
+        let (k, k2, i) = (r"\x.\y.x", r"\a.\b.a", r"\x.x");
+        let soup = soup_of(&[k, k2, i, k2]);
+        assert_eq!(soup.expression_counts()[&term(k)], 3);
+        assert_eq!(soup.unique_expressions().len(), 2);
+        assert_eq!(soup.k_most_frequent_exprs(1), [term(k2)]);
+
+        let (add, add2) = (r"\m.\n.\f.\x.m f (n f x)", r"\a.\b.\g.\y.a g (b g y)");
+        let soup = soup_of(&[add, k, add2]);
+        assert_eq!(soup.population_of(&term(add2)), 2);
+    }
+
+    #[test]
+    fn population_entropy_base_converts_units() {
+        let soup = soup_of(&[r"\x.x", r"\x.\y.x", r"\x.x", r"\x.\y.x"]);
+        assert!((soup.population_entropy_base(2.0) - 1.0).abs() < 1e-6);
+        assert!((soup.population_entropy() - 2f32.log10()).abs() < 1e-6);
+        assert!(soup.population_entropy_base(1.0).is_nan());
+    }
+
+    #[test]
+    fn settled_entropy_halts_the_simulation() {
+        let mut soup = soup_of(&[r"\x.x"; 20]);
+        soup.halt_on_convergence(3, 1e-3);
+        let (data, termination) =
+            soup.simulate_and_poll_until_stable(1000, 10, false, |s| s.population_entropy());
+        assert_eq!(termination, Termination::Converged { collisions: 21 });
+        assert_eq!(data.len(), 3);
+
+        soup.never_halt();
+        let (_, termination) = soup.simulate_for_until_stable(100, false);
+        assert_eq!(termination, Termination::Exhausted);
+    }
+
+    #[test]
+    fn behavioral_classes_merge_beta_equal_expressions() {
+        let (i, k, w) = (r"\x.x", r"\x.\y.x", r"\x.x x");
+        let soup = soup_of(&[i, k, r"\x.(\y.y) x", w, k]);
+        let probes = [term(i), term(w)];
+        let limits = ReductionLimits::new(lambda_calculus::NOR, 100, 1000);
+        let classes = soup.behavioral_classes(&probes, &limits);
+
+        let normal = |s| ProbeOutput::Normal(term(s));
+        assert_eq!(classes.len(), 3);
+        assert_eq!(classes[0].0, [term(i), term(r"\x.(\y.y) x")]);
+        assert_eq!(classes[0].1, Fingerprint(vec![normal(i), normal(w)]));
+        assert_eq!(
+            classes[1].1,
+            Fingerprint(vec![normal(r"\y.\x.x"), normal(r"\y.\x.x x")])
+        );
+        assert_eq!(classes[2].0, [term(w)]);
+        assert_eq!(
+            classes[2].1,
+            Fingerprint(vec![normal(i), ProbeOutput::Bottom])
+        );
+    }
+
+    #[test]
+    fn church_numeral_census_normalizes_first() {
+        let two = r"\f.\x.f (f x)";
+        let soup = soup_of(&[
+            two,
+            r"\g.\y.g (g y)",
+            r"(\n.\f.\x.f (n f x)) (\f.\x.f x)",
+            r"\f.\x.x",
+            r"\x.\y.y",
+            r"\f.\x.f (f (f x))",
+            r"(\x.x x) (\x.x x)",
+            r"\x.x",
+        ]);
+        let census = soup.church_numeral_census(2);
+        assert_eq!(census.counts, HashMap::from([(0, 2), (2, 3)]));
+        assert_eq!(census.unknown, 1);
+    }
+
+    #[test]
+    fn combinator_census_finds_each_combinator() {
+        let soup = soup_of(&[
+            r"\x.\y.\z.x z (y z)",
+            r"\a.\b.a",
+            r"\x.\y.x",
+            r"(\x.x) (\x.x)",
+            r"\x.\y.\z.x (y z)",
+            r"\x.\y.\z.x z y",
+            r"\x.\y.x y y",
+            r"\f.(\x.f (x x)) (\x.f (x x))",
+            r"(\x.\y.y (x x y)) (\x.\y.y (x x y))",
+            r"\x.x x",
+            r"(\x.x x) (\x.x x)",
+            r"\x.\y.y",
+        ]);
+        let census = soup.combinator_census();
+        let expected = [
+            (Combinator::S, 1),
+            (Combinator::K, 2),
+            (Combinator::I, 1),
+            (Combinator::B, 1),
+            (Combinator::C, 1),
+            (Combinator::W, 1),
+            (Combinator::Y, 2),
+        ];
+        assert_eq!(census, HashMap::from(expected));
+    }
+
+    #[test]
+    fn divergence_report_compares_class_distributions() {
+        let (k, i, u) = (r"\x.\y.x", r"\x.x", r"\x.x x");
+        let p = soup_of(&[k, i]);
+        let same = p.divergence_from(&soup_of(&[r"\a.\b.a", r"\y.y", k, i]));
+        assert_eq!(same.jensen_shannon, 0.0);
+        assert_eq!(same.jaccard, 1.0);
+        assert_eq!(same.total_variation, 0.0);
+
+        let disjoint = p.divergence_from(&soup_of(&[u]));
+        assert!((disjoint.jensen_shannon - 1.0).abs() < 1e-12);
+        assert_eq!(disjoint.jaccard, 0.0);
+        assert!((disjoint.total_variation - 1.0).abs() < 1e-12);
+
+        let overlap = p.divergence_from(&soup_of(&[k, u]));
+        assert!((overlap.jaccard - 1.0 / 3.0).abs() < 1e-12);
+        assert!((overlap.total_variation - 0.5).abs() < 1e-12);
+        assert!((overlap.jensen_shannon - 0.5).abs() < 1e-12);
+        assert_eq!(p.divergence_from(&LambdaSoup::new()).jaccard, 0.0);
+    }
+
+    #[test]
+    fn kl_divergence_is_zero_for_equal_distributions() {
+        let (k, i, u) = (r"\x.\y.x", r"\x.x", r"\x.x x");
+        let p = soup_of(&[k, i]);
+        let q = soup_of(&[k, k, i, i]);
+        assert_eq!(p.population_kl_divergence(&q, None), Some(0.0));
+
+        let r = soup_of(&[k, k, k, i]);
+        let expected = 0.5 * (0.5f64 / 0.75).ln() + 0.5 * (0.5f64 / 0.25).ln();
+        let divergence = p.population_kl_divergence(&r, None).unwrap();
+        assert!((divergence - expected).abs() < 1e-12);
+
+        let s = soup_of(&[k, u]);
+        assert_eq!(p.population_kl_divergence(&s, None), None);
+        assert!(p.population_kl_divergence(&s, Some(0.5)).unwrap() > 0.0);
+        assert_eq!(LambdaSoup::new().population_kl_divergence(&p, None), None);
+    }
+
+    #[test]
+    fn self_reproducers_and_copiers_are_tallied() {
+        let (i, k, u) = (r"\x.x", r"\x.\y.x", r"\x.x x");
+        // \x.x I applied to itself reduces to I rather than to itself
+        let soup = soup_of(&[i, i, k, u, r"\x.(\y.y) x", r"\x.x (\y.y)", k]);
+        let limits = ReductionLimits::new(lambda_calculus::NOR, 100, 1000);
+
+        let reproducers = soup.count_self_reproducers(&limits, false);
+        assert_eq!(reproducers.count, 3);
+        assert_eq!(reproducers.examples, [term(i), term(r"\x.(\y.y) x")]);
+        assert_eq!(soup.count_self_reproducers(&limits, true).count, 1);
+
+        let probes = [term(k), term(u)];
+        let copiers = soup.count_copiers(&probes, &limits, false);
+        assert_eq!(copiers.count, 3);
+        assert_eq!(copiers.examples, [term(i), term(r"\x.(\y.y) x")]);
+        assert_eq!(soup.count_copiers(&probes, &limits, true).count, 1);
+    }
+
+    #[test]
+    fn replicators_depend_on_the_first_rule() {
+        let (i, k, u) = (r"\x.x", r"\x.\y.x", r"\x.x x");
+        // \x.x x collides with itself into a divergent term, which is skipped
+        let soup = soup_of(&[k, i, u, r"\a.a"]);
+        assert_eq!(soup.find_replicators(), [term(i)]);
+
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            rules: vec![config::ReactionRule::new(r"\x.\y.x")],
+            ..config::Reactor::new()
+        });
+        soup.add_lambda_expressions([k, i, u].map(term));
+        assert_eq!(soup.find_replicators(), [k, i, u].map(term));
+    }
+
+    #[test]
+    fn normal_form_fraction_counts_expressions() {
+        assert_eq!(LambdaSoup::new().normal_form_fraction(), 0.0);
+        let soup = soup_of(&[
+            r"\x.x",
+            r"\a.a",
+            r"\x.x (\y.y)",
+            r"\x.(\y.y) x",
+            r"\x.\y.x ((\z.z) y)",
+        ]);
+        assert_eq!(soup.normal_form_fraction(), 0.6);
+    }
+
+    #[test]
+    fn frequent_subterms_are_closed() {
+        let k = r"\x.\y.x";
+        let soup = soup_of(&[r"\f.f (\x.\y.x)", r"\g.\h.h (\a.\b.a) g", k, k]);
+
+        // `\y.x` is in every expression too, but it isn't closed
+        let subterms = soup.frequent_subterms(2, 3);
+        assert_eq!(subterms[0], (term(k), 3));
+        assert_eq!(
+            subterms.iter().map(|(_, n)| n).collect::<Vec<_>>(),
+            [&3, &1, &1]
+        );
+        assert!(subterms.iter().all(|(t, _)| !t.has_free_variables()));
+
+        assert_eq!(soup.frequent_subterms(4, 10).len(), 2);
+        assert!(soup.frequent_subterms(100, 10).is_empty());
+    }
+
+    #[test]
+    fn interacting_pairs_are_searched_by_species() {
+        let (i, k) = (term(r"\x.x"), term(r"\x.\y.x"));
+        let soup = soup_of(&[r"\x.x", r"\a.a", r"\x.\y.x", r"\x.\y.x", r"\a.\b.a"]);
+
+        let tries = Cell::new(0);
+        let i_then_k = |a: &Term, b: &Term| {
+            tries.set(tries.get() + 1);
+            *a == i && *b == k
+        };
+        assert_eq!(
+            soup.find_interacting_pair(i_then_k, false),
+            Some((i.clone(), k.clone()))
+        );
+        assert_eq!(tries.get(), 2);
+
+        // A symmetric search only tries (I, I), (K, I) and (K, K)
+        tries.set(0);
+        assert_eq!(soup.find_interacting_pair(i_then_k, true), None);
+        assert_eq!(tries.get(), 3);
+
+        let distinct = |a: &Term, b: &Term| a != b;
+        assert_eq!(soup.find_interacting_pair_in_top(1, distinct, true), None);
+        assert_eq!(
+            soup.find_interacting_pair_in_top(2, distinct, true),
+            Some((i, k))
+        );
+    }
+
+    #[test]
+    fn fixed_points_are_found_once_per_class() {
+        let (i, k) = (r"\x.x", r"\x.\y.x");
+        let soup = soup_of(&[i, k, r"\a.a", r"\x.x x", r"\x.\y.y", r"\x.x (\y.y)"]);
+        assert_eq!(soup.find_fixed_points(None), [term(i)]);
+
+        // K K is \y.K, so K is a fixed point of t t = \y.t
+        let constant_of_self = |t: &Term, r: &Term| *r == Term::Abs(Box::new(t.clone()));
+        assert_eq!(soup.find_fixed_points(Some(constant_of_self)), [term(k)]);
+    }
+
+    #[test]
+    fn k_least_frequent_exprs_complements_most_frequent() {
+        let (k, i, u) = (r"\x.\y.x", r"\x.x", r"\x.x x");
+        let soup = soup_of(&[i, k, u, k, u, k]);
+
+        assert_eq!(soup.k_least_frequent_exprs(1), vec![term(i)]);
+
+        let n = soup.unique_expressions().len();
+        let covered = soup
+            .k_most_frequent_exprs(n)
+            .into_iter()
+            .chain(soup.k_least_frequent_exprs(n))
+            .collect::<HashSet<_>>();
+        assert_eq!(covered, soup.unique_expressions());
+    }
+
+    #[test]
+    fn normalized_entropy_measures_evenness() {
+        let (k, i) = (r"\x.\y.x", r"\x.x");
+        assert!((soup_of(&[k, i, k, i]).normalized_entropy() - 1.0).abs() < 1e-6);
+        assert!(soup_of(&[k, i, k, k]).normalized_entropy() < 1.0);
+        assert_eq!(soup_of(&[k, k, k]).normalized_entropy(), 0.0);
+        assert_eq!(soup_of(&[]).normalized_entropy(), 0.0);
+    }
+
+    #[test]
+    fn simpson_index_of_even_population() {
+        let (k, i, u) = (r"\x.\y.x", r"\x.x", r"\x.x x");
+        let soup = soup_of(&[k, i, u, k, i, u]);
+        assert!((soup.simpson_index() - 1.0 / 3.0).abs() < 1e-6);
+        assert!((soup.gini_simpson_index() - 2.0 / 3.0).abs() < 1e-6);
+        assert_eq!(soup_of(&[]).simpson_index(), 0.0);
+        assert_eq!(soup_of(&[]).gini_simpson_index(), 0.0);
+    }
+
+    #[test]
+    fn diversity_of_uneven_population() {
+        let (k, i) = (r"\x.\y.x", r"\x.x");
+        let soup = soup_of(&[k, k, k, i]);
+        let d = soup.diversity();
+        let nats = -(0.75f64 * 0.75f64.ln() + 0.25 * 0.25f64.ln());
+        assert!((d.shannon_nats - nats).abs() < 1e-12);
+        assert!((d.shannon_bits - nats / 2f64.ln()).abs() < 1e-12);
+        assert!((d.shannon_bits - soup.population_entropy_base(2.0)).abs() < 1e-12);
+        assert_eq!(soup.normalized_entropy(), d.evenness as f32);
+        assert_eq!(soup.simpson_index(), d.simpson_index as f32);
+        assert_eq!(d.richness, 2);
+        assert_eq!(d.hill_0, 2.0);
+        assert!((d.simpson_index - 0.625).abs() < 1e-12);
+        assert!((d.hill_2 - 1.6).abs() < 1e-12);
+        assert!(d.hill_2 < d.hill_1 && d.hill_1 < d.hill_0);
+        assert!((d.evenness - nats / 2f64.ln()).abs() < 1e-12);
+        assert_eq!(soup_of(&[]).diversity(), DiversityMetrics::default());
+    }
+
+    #[test]
+    fn count_moments_measure_skew() {
+        let (k, i, u, s) = (r"\x.\y.x", r"\x.x", r"\x.x x", r"\x.\y.\z.x z (y z)");
+        let even = soup_of(&[k, i, u, k, i, u]);
+        assert_eq!(even.population_variance(), 0.0);
+        assert_eq!(even.population_skewness(), 0.0);
+
+        // Counts 5, 1, 1, 1: mean 2, deviations 3, -1, -1, -1
+        let dominated = soup_of(&[k, k, k, k, k, i, u, s]);
+        assert!((dominated.population_variance() - 3.0).abs() < 1e-12);
+        let skewness = (27.0 - 3.0) / 4.0 / 3f64.powf(1.5);
+        assert!((dominated.population_skewness() - skewness).abs() < 1e-12);
+        assert_eq!(soup_of(&[]).population_variance(), 0.0);
+    }
+
+    #[test]
+    fn size_distribution_buckets_merge() {
+        let (k, i) = (r"\x.\y.x", r"\x.x");
+        let soup = soup_of(&[k, k, i]);
+        let edges = [term_size(&term(i)), term_size(&term(k))];
+        let mut histogram = soup.size_distribution(&edges);
+        assert_eq!(histogram.counts(), [0, 1, 2]);
+        assert_eq!(
+            histogram.labels().first().unwrap(),
+            &format!("..{}", edges[0])
+        );
+
+        histogram.merge(&soup_of(&[i]).size_distribution(&edges));
+        assert_eq!(histogram.counts(), [0, 2, 2]);
+        assert_eq!(soup.depth_distribution(&[1, 2]).total(), 3);
+
+        let mut powers = Histogram::powers_of_two(3);
+        for v in [0, 1, 3, 4, 100] {
+            powers.add(v);
+        }
+        assert_eq!(powers.counts(), [1, 1, 1, 2]);
+        assert_eq!(powers.labels(), ["..1", "1..2", "2..4", "4.."]);
+        let widest = Histogram::powers_of_two(usize::BITS);
+        assert_eq!(widest.edges().last(), Some(&(1 << (usize::BITS - 1))));
+    }
+
+    #[test]
+    fn size_histogram_buckets_by_node_count() {
+        let (k, i) = (r"\x.\y.x", r"\x.x");
+        let soup = soup_of(&[k, i, k]);
+        let histogram = soup.size_histogram();
+        assert_eq!(histogram.values().sum::<usize>(), 3);
+        assert_eq!(histogram[&term_size(&term(k))], 2);
+        assert_eq!(histogram[&term_size(&term(i))], 1);
+        assert_eq!(soup.depth_histogram().values().sum::<usize>(), 3);
+    }
+
+    #[test]
+    fn term_size_matches_hand_counts() {
+        assert_eq!(term_size(&term(r"\x.x")), 2);
+        assert_eq!(term_size(&term(r"\x.x x")), 4);
+        assert_eq!(term_size(&term(r"\x.\y.\z.x z (y z)")), 10);
+
+        let soup = soup_of(&[r"\x.x", r"\x.x x", r"\x.x"]);
+        assert_eq!(soup.total_mass(), 8);
+        assert_eq!(soup_of(&[]).total_mass(), 0);
+    }
+
+    #[test]
+    fn population_simpson_index_measures_dominance() {
+        let (k, i) = (r"\x.\y.x", r"\x.x");
+        assert_eq!(soup_of(&[k, k, k, k]).population_simpson_index(), 1.0);
+        assert_eq!(soup_of(&[k, k, k, k]).effective_species(), 1.0);
+
+        let soup = soup_of(&[k, k, i, i]);
+        assert!((soup.population_simpson_index() - 1.0 / 3.0).abs() < 1e-12);
+        assert!((soup.simpson_diversity() - 2.0 / 3.0).abs() < 1e-12);
+        assert!((soup.effective_species() - 3.0).abs() < 1e-12);
+
+        let distinct = [k, i, r"\x.x x", r"\x.\y.y", r"\x.\y.\z.x z (y z)"];
+        for n in 2..=distinct.len() {
+            assert_eq!(soup_of(&distinct[..n]).population_simpson_index(), 0.0);
+            assert_eq!(soup_of(&distinct[..n]).effective_species(), n as f64);
+        }
+        assert_eq!(soup_of(&[k]).population_simpson_index(), 0.0);
+        assert_eq!(soup_of(&[k]).effective_species(), 1.0);
+        assert_eq!(soup_of(&[]).effective_species(), 0.0);
+    }
+
+    #[test]
+    fn distributions_are_sorted_and_sum_to_the_population() {
+        let (k, i, s) = (r"\x.\y.x", r"\x.x", r"\x.\y.\z.x z (y z)");
+        let soup = soup_of(&[s, k, i, k, s, s]);
+
+        let sizes = soup.expression_size_distribution();
+        assert!(sizes.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(sizes.iter().map(|(_, c)| c).sum::<usize>(), 6);
+        assert_eq!(sizes.last(), Some(&(term_size(&term(s)), 3)));
+
+        let depths = soup.expression_complexity_distribution();
+        assert!(depths.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(depths.iter().map(|(_, c)| c).sum::<usize>(), 6);
+        assert_eq!(soup_of(&[]).expression_complexity_distribution(), []);
+    }
+
+    fn naive_counts(soup: &LambdaSoup) -> HashMap<Term, u32> {
+        let mut map = HashMap::new();
+        for expr in soup.lambda_expressions().cloned() {
+            *map.entry(expr).or_default() += 1
+        }
+        map
+    }
+
+    #[test]
+    fn expression_counts_group_tests_with_molecules() {
+        let (k, i) = (r"\x.\y.x", r"\x.x");
+        let mut soup = soup_of(&[k, i, k]);
+        soup.add_test_expressions([term(k)]);
+
+        let counts = soup.expression_counts();
+        assert_eq!(counts, naive_counts(&soup));
+        assert_eq!(counts[&term(k)], 3);
+    }
+
+    /// Compares wall-clock times, so it only runs on request, with `--ignored`.
+    #[test]
+    #[ignore = "timing-dependent"]
+    fn live_counts_make_polling_counts_faster() {
+        let mut soup = LambdaSoup::new();
+        for _ in 0..100 {
+            soup.add_lambda_expressions((0..100).map(|n| n.into_church()));
+        }
+        assert_eq!(soup.len(), 10_000);
+
+        let polls = 5;
+        let start = Instant::now();
+        for _ in 0..polls {
+            assert_eq!(naive_counts(&soup).len(), 100);
+        }
+        let naive = start.elapsed();
+
+        let start = Instant::now();
+        for _ in 0..polls {
+            assert_eq!(soup.expression_counts().len(), 100);
+        }
+        let cached = start.elapsed();
+
+        assert!(
+            cached * 10 < naive,
+            "cached: {:?}, naive: {:?}",
+            cached,
+            naive
+        );
+    }
+}
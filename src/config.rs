@@ -1,8 +1,13 @@
-use rand::{thread_rng, Rng};
+use std::{fmt, fs::read_to_string, io, path::Path, str::FromStr};
 
+use rand::{rngs::OsRng, thread_rng, Rng};
+
+use lambda_calculus::parser::ParseError;
+use lambda_calculus::reduction::Order;
+use lambda_calculus::Term;
 use serde::{Deserialize, Serialize};
 
-use crate::generators::Standardization;
+use crate::generators::{SizeDistribution, Standardization};
 
 use crate::utils::{decode_hex, encode_hex};
 
@@ -28,15 +33,42 @@ pub struct Config {
 
     /// Configuration options for the lambda reactor.
     pub reactor_config: Reactor,
+
+    /// The experiment to run instead of a plain simulation. Default: `None`.
+    #[serde(default)]
+    pub experiment: Option<Experiment>,
+}
+
+/// Selects one of the experiments in `experiments`. In a TOML config file, this is the
+/// `[experiment]` table.
+#[warn(missing_docs)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Experiment {
+    /// Name of the experiment, as passed to `--experiment` on the command line, e.g.
+    /// `"entropy-time-series"`.
+    pub name: String,
 }
 
 /// Configuration for the reactor
 #[warn(missing_docs)]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Reactor {
     /// Set of reaction rules. Each rule must always be a lambda expressions
-    /// with two arguments. Default: `["\x.\y.x y"]`.
-    pub rules: Vec<String>,
+    /// with two arguments. A rule may be written as just its expression, or as a table with a
+    /// `probability` of being applied and a `reduction_order` of its own. Default:
+    /// `["\x.\y.x y"]`.
+    pub rules: Vec<ReactionRule>,
+
+    /// How the reaction rules are applied to a pair of colliding expressions. Default: `All`.
+    #[serde(default)]
+    pub rule_application: RuleApplication,
+
+    /// Relative weights of the reaction rules, one per rule, used to pick a rule when
+    /// `rule_application` is `Random`. Every weight must be positive, and weights may only be
+    /// given along with `Random`. When set to `None`, rules are picked uniformly. Default:
+    /// `None`.
+    #[serde(default, deserialize_with = "deserialize_rule_weights")]
+    pub rule_weights: Option<Vec<f64>>,
 
     /// When set, remove all results that are structurally isomorphic to parents.
     /// Default: `true`.
@@ -53,11 +85,49 @@ pub struct Reactor {
     pub discard_parents: bool,
 
     /// When set maintain a constant population size after each reaction. If there are more
-    /// elements than the population originally started with, then remove elements randomly from
-    /// the soup until the original population remains. If there are fewer elements after a
-    /// reaction, then do nothing. This behavior may change. Default: `true`.
+    /// elements than the population originally started with, then remove elements from the soup,
+    /// as picked by `eviction_policy`, until the original population remains. If there are fewer
+    /// elements after a reaction, then do nothing. This behavior may change. Default: `true`.
     pub maintain_constant_population_size: bool,
 
+    /// When set, the expressions removed to maintain a constant population size are chosen
+    /// before the products of a reaction are added, so a fresh product is never removed by the
+    /// reaction that created it. Parents returned to the soup are always added after the
+    /// removal, so they are protected either way. Default: `false`.
+    #[serde(default)]
+    pub protect_new_products: bool,
+
+    /// When set, never collide two isomorphic expressions. The second reactant is redrawn until
+    /// it differs from the first, up to `supercollider::DISTINCT_REACTANT_ATTEMPTS` times, after
+    /// which the soup gives up on the reaction. Default: `false`.
+    #[serde(default)]
+    pub require_distinct_reactants: bool,
+
+    /// How the two reactants of each collision are chosen. Default: `Random`.
+    #[serde(default)]
+    pub selection_strategy: SelectionStrategy,
+
+    /// Which expressions are removed when maintaining a constant population size. Default:
+    /// `Random`.
+    #[serde(default)]
+    pub eviction_policy: EvictionPolicy,
+
+    /// Probability that a uniformly random expression decays, and is removed from the soup,
+    /// after each collision, whatever the outcome of the collision. Decay never shrinks the soup
+    /// below two expressions. Must be between `0.0` and `1.0`. Default: `0.0`.
+    #[serde(default, deserialize_with = "deserialize_decay_probability")]
+    pub decay_probability: f64,
+
+    /// Upper bound on the number of expressions in the soup. Whenever a reaction leaves more
+    /// expressions than this, uniformly random ones are removed until the soup is back at the
+    /// cap. Unlike `maintain_constant_population_size`, this lets the population fluctuate
+    /// below the bound. If both are set, the cap wins: a soup that starts out larger than the
+    /// cap is cut down to it after the first reaction, and then kept at that size. The cap must
+    /// be at least two, since a reaction needs two expressions. When set to `None`, the
+    /// population is unbounded. Default: `None`.
+    #[serde(default, deserialize_with = "deserialize_max_population")]
+    pub max_population: Option<usize>,
+
     ///  The number of reductions allowed before AlChemy gives up and fails the reaction. Default:
     ///  `500`.
     pub reduction_cutoff: usize,
@@ -65,9 +135,369 @@ pub struct Reactor {
     /// The largest size of any expression during a reduction step. Defaults to `1024`.
     pub size_cutoff: usize,
 
+    /// The strategy used to reduce reaction products and test expressions, written as the name
+    /// of a `lambda_calculus` reduction order: one of `"NOR"`, `"CBN"`, `"HSP"`, `"HNO"`,
+    /// `"APP"`, `"CBV"` or `"HAP"`. Default: `"HAP"`, which is what every reactor used before
+    /// this option existed.
+    #[serde(default)]
+    pub reduction_order: ReductionOrder,
+
+    /// Fail a collision whose reduction takes longer than this many milliseconds of wall-clock
+    /// time, as if it had hit `reduction_cutoff`. The clock is checked along with the size of
+    /// the term, so a collision may overrun slightly. When set to `None`, collisions have no
+    /// time limit. Default: `None`.
+    #[serde(default)]
+    pub collision_timeout_ms: Option<u64>,
+
+    /// Check a term against `size_cutoff` only once every this many reduction steps. Checking
+    /// less often makes reduction faster, but lets a growing term overshoot the cutoff by up to
+    /// this many steps before the collision fails. When set to `None`, check after every step.
+    /// Default: `None`.
+    #[serde(default)]
+    pub size_check_interval: Option<usize>,
+
+    /// Remember the outcomes of up to this many reductions, keyed by the rule and both
+    /// reactants, and reuse them when the same collision comes up again. This pays off once a
+    /// soup has collapsed into a few classes of expressions. Filters still apply to cached
+    /// products, and reductions that time out are never cached. When set to `None`, nothing is
+    /// cached. Default: `None`.
+    #[serde(default)]
+    pub collision_cache_size: Option<usize>,
+
     /// The seed for the reactor. If set to `None`, then a seed is chosen
     /// randomly. Default: `None`
     pub seed: ConfigSeed,
+
+    /// When set, record every successful reaction on the soup's `ReactionTape`. This is
+    /// expensive for long runs. Default: `false`.
+    #[serde(default)]
+    pub record_tape: bool,
+
+    /// When set, remember the parents and rule of every expression made in the soup, so its
+    /// ancestry can be traced with `lineage_of`. This costs memory for every reaction. Default:
+    /// `false`.
+    #[serde(default)]
+    pub record_lineage: bool,
+
+    /// Forget lineage records more than this many generations away from every expression in the
+    /// soup. When set to `None`, keep all records. Default: `None`.
+    #[serde(default)]
+    pub lineage_depth: Option<usize>,
+
+    /// When set, remember how each distinct expression was first made, so it can be looked up
+    /// with `LambdaSoup::genealogy`. The record grows with the number of distinct products and
+    /// is never pruned. Default: `false`.
+    #[serde(default)]
+    pub record_genealogy: bool,
+
+    /// Remember the ages of this many of the expressions most recently removed from the soup
+    /// by eviction, decay, the population cap or `discard_parents`, for
+    /// `Soup::term_lifetime_statistics`. Default: `10000`.
+    #[serde(default = "default_removed_age_buffer")]
+    pub removed_age_buffer: usize,
+}
+
+fn default_removed_age_buffer() -> usize {
+    10000
+}
+
+/// A reaction rule, and how often it is applied.
+#[warn(missing_docs)]
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ReactionRule {
+    /// A lambda expression with two arguments, in `Classic` notation.
+    pub rule: String,
+
+    /// Probability that the rule is applied to a collision it would otherwise take part in,
+    /// decided by an independent trial for each collision. Under `RuleApplication::Random` the
+    /// trial is made for the chosen rule only, and a collision whose rule is skipped has no
+    /// products. Must lie in `[0, 1]`. Default: `1.0`, which never touches the RNG.
+    pub probability: f64,
+
+    /// The strategy used to reduce the products of this rule, in place of
+    /// `Reactor::reduction_order`. A rule that selects one of its arguments might use `"CBN"`,
+    /// so the argument it drops is never evaluated. Default: `None`, which uses the reactor's.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reduction_order: Option<ReductionOrder>,
+}
+
+impl ReactionRule {
+    /// A rule that is always applied, and reduced with the reactor's reduction order.
+    pub fn new(rule: impl Into<String>) -> Self {
+        ReactionRule {
+            rule: rule.into(),
+            probability: 1.0,
+            reduction_order: None,
+        }
+    }
+}
+
+/// Rules read from a config file may be bare expressions.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ReactionRuleRepr {
+    Bare(String),
+    Full {
+        rule: String,
+        #[serde(default = "always")]
+        probability: f64,
+        #[serde(default)]
+        reduction_order: Option<ReductionOrder>,
+    },
+}
+
+fn always() -> f64 {
+    1.0
+}
+
+impl<'de> Deserialize<'de> for ReactionRule {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let (rule, probability, reduction_order) =
+            match ReactionRuleRepr::deserialize(deserializer)? {
+                ReactionRuleRepr::Bare(rule) => (rule, 1.0, None),
+                ReactionRuleRepr::Full {
+                    rule,
+                    probability,
+                    reduction_order,
+                } => (rule, probability, reduction_order),
+            };
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(serde::de::Error::custom(format!(
+                "probability {probability} of reaction rule `{rule}` must lie between 0 and 1"
+            )));
+        }
+        Ok(ReactionRule {
+            rule,
+            probability,
+            reduction_order,
+        })
+    }
+}
+
+/// Reject rule weights that are zero, negative, or NaN when parsing the config.
+fn deserialize_rule_weights<'de, D>(deserializer: D) -> Result<Option<Vec<f64>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let weights: Option<Vec<f64>> = Deserialize::deserialize(deserializer)?;
+    if let Some(ref weights) = weights {
+        if let Some((i, w)) = weights
+            .iter()
+            .enumerate()
+            .find(|(_, w)| w.is_nan() || **w <= 0.0)
+        {
+            return Err(serde::de::Error::custom(format!(
+                "weight {w} of reaction rule {i} must be positive"
+            )));
+        }
+    }
+    Ok(weights)
+}
+
+/// Reject a `lambda_probability` outside `[0, 1]`, or NaN, when parsing the config.
+fn deserialize_lambda_probability<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let p: Option<f64> = Deserialize::deserialize(deserializer)?;
+    match p {
+        Some(p) if !(0.0..=1.0).contains(&p) => Err(serde::de::Error::custom(format!(
+            "lambda probability {p} must lie between 0 and 1"
+        ))),
+        _ => Ok(p),
+    }
+}
+
+/// Reject a `decay_probability` outside `[0, 1]`, or NaN, when parsing the config.
+fn deserialize_decay_probability<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let p = f64::deserialize(deserializer)?;
+    if !(0.0..=1.0).contains(&p) {
+        return Err(serde::de::Error::custom(format!(
+            "decay probability {p} must lie between 0 and 1"
+        )));
+    }
+    Ok(p)
+}
+
+/// Reject a `max_population` below two when parsing the config.
+fn deserialize_max_population<'de, D>(deserializer: D) -> Result<Option<usize>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let cap: Option<usize> = Deserialize::deserialize(deserializer)?;
+    match cap {
+        Some(cap) if cap < 2 => Err(serde::de::Error::custom(format!(
+            "max population {cap} must be at least 2"
+        ))),
+        _ => Ok(cap),
+    }
+}
+
+/// Reject a `size_distribution` that could draw a size of zero, or that can't be sampled at
+/// all, when parsing the config.
+fn deserialize_size_distribution<'de, D>(
+    deserializer: D,
+) -> Result<Option<SizeDistribution>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let distribution: Option<SizeDistribution> = Deserialize::deserialize(deserializer)?;
+    if let Some(d) = &distribution {
+        d.validate().map_err(serde::de::Error::custom)?;
+    }
+    Ok(distribution)
+}
+
+/// How the reactor applies its reaction rules to a pair of colliding expressions.
+#[warn(missing_docs)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RuleApplication {
+    /// Apply every rule to the pair. The collision fails if any one of the rules fails, and
+    /// produces one product per rule otherwise.
+    #[default]
+    All,
+
+    /// Apply a single rule, chosen uniformly at random, to the pair. This is what Fontana's
+    /// AlChemy does.
+    Random,
+
+    /// Try the rules in order and keep the product of the first one that succeeds.
+    FirstSuccess,
+}
+
+/// How a soup picks the expressions it removes to keep its population size constant. Every
+/// policy other than `Random` is deterministic. `Oldest` breaks ties in favour of the expression
+/// added first, and the others in favour of the expression that comes first in the soup.
+#[warn(missing_docs)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// Remove an expression chosen uniformly at random.
+    #[default]
+    Random,
+
+    /// Remove the expression that has been in the soup for the longest time. Of expressions
+    /// added at the same step, the one added first goes first.
+    Oldest,
+
+    /// Remove the expression with the most nodes.
+    Largest,
+
+    /// Remove a member of the most populous class of isomorphic expressions.
+    MostCommon,
+}
+
+/// How a soup picks the two expressions that collide in each reaction.
+#[warn(missing_docs)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionStrategy {
+    /// Pick two distinct expressions uniformly at random.
+    #[default]
+    #[serde(alias = "Uniform")]
+    Random,
+
+    /// Collide the expressions at positions `c` and `c + 1` of the soup, wrapping around at the
+    /// end, and then advance the cursor `c` by two. The cursor is a position rather than a
+    /// particular expression: the reaction removes both reactants with `swap_remove`, which
+    /// moves expressions from the end of the soup into their slots, and the cursor is reduced
+    /// modulo the population size before each use, so it always points into the soup however
+    /// the population changes. Selection never uses the RNG, though eviction and decay still
+    /// may.
+    RoundRobin,
+
+    /// Pick each reactant with probability proportional to the number of copies of it in the
+    /// soup, so common expressions collide more often than under `Random`.
+    FrequencyWeighted,
+
+    /// Pick each reactant with probability inversely proportional to the number of copies of
+    /// it in the soup. Every class of equal expressions is then equally likely to react,
+    /// however large it is.
+    InverseFrequencyWeighted,
+}
+
+/// A reduction strategy from `lambda_calculus::reduction::Order`, in a form that can be read from
+/// a config file. See the `lambda_calculus` documentation for what each strategy does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReductionOrder(pub Order);
+
+impl Default for ReductionOrder {
+    fn default() -> Self {
+        ReductionOrder(Order::HAP)
+    }
+}
+
+const REDUCTION_ORDERS: [(&str, Order); 7] = [
+    ("NOR", Order::NOR),
+    ("CBN", Order::CBN),
+    ("HSP", Order::HSP),
+    ("HNO", Order::HNO),
+    ("APP", Order::APP),
+    ("CBV", Order::CBV),
+    ("HAP", Order::HAP),
+];
+
+/// Error returned when a string doesn't name a reduction order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseReductionOrderError(String);
+
+impl fmt::Display for ParseReductionOrderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = REDUCTION_ORDERS.map(|(name, _)| name).join(", ");
+        write!(
+            f,
+            "unknown reduction order `{}`, expected one of {}",
+            self.0, names
+        )
+    }
+}
+
+impl std::error::Error for ParseReductionOrderError {}
+
+impl FromStr for ReductionOrder {
+    type Err = ParseReductionOrderError;
+
+    /// Parse the name of a reduction order, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        REDUCTION_ORDERS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|&(_, order)| ReductionOrder(order))
+            .ok_or_else(|| ParseReductionOrderError(s.to_string()))
+    }
+}
+
+impl fmt::Display for ReductionOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, _) = REDUCTION_ORDERS
+            .iter()
+            .find(|(_, order)| *order == self.0)
+            .unwrap();
+        name.fmt(f)
+    }
+}
+
+impl Serialize for ReductionOrder {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ReductionOrder {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
 }
 
 /// Configuration for the generators
@@ -79,6 +509,9 @@ pub enum Generator {
 
     /// Use Fontana's generator
     Fontana(FontanaGen),
+
+    /// Use the SKI combinator generator
+    SKI(SKIGen),
 }
 
 pub trait GenConfig {
@@ -96,15 +529,131 @@ pub struct BTreeGen {
     /// Number of nodes in the binary tree
     pub size: u32,
 
+    /// Distribution to draw the number of nodes in the binary tree from, for each generated
+    /// term. When set to `None`, every tree has `size` nodes. Default: `None`.
+    #[serde(default, deserialize_with = "deserialize_size_distribution")]
+    pub size_distribution: Option<SizeDistribution>,
+
     /// Probability that a leaf vertex is a free variable
     pub freevar_generation_probability: f64,
 
     /// Size of the free variable palette
     pub n_max_free_vars: u32,
 
+    /// Probability that an internal node of the tree is an abstraction rather than an
+    /// application. When set to `None`, the shape of the tree comes from inserting the nodes
+    /// into a binary search tree in random order, as it always has. Must lie in `[0, 1]`.
+    /// Default: `None`.
+    #[serde(default, deserialize_with = "deserialize_lambda_probability")]
+    pub lambda_probability: Option<f64>,
+
     /// Standardization scheme. Defaults to prefix standardization (this is different from the
     /// paper!)
     pub standardization: Standardization,
+
+    /// Throw away terms with free variables and generate new ones in their place, so that
+    /// every generated term is closed. Prefix standardization already closes every term, so
+    /// this only matters for the other schemes. Default: `false`.
+    #[serde(default)]
+    pub require_closed: bool,
+
+    /// Throw away terms that do not reach a normal form within `normal_form_step_budget`
+    /// steps of normal-order reduction, and emit the normal forms of the rest. Default: `false`.
+    #[serde(default)]
+    pub normal_form_only: bool,
+
+    /// Most reduction steps to spend on each term when `normal_form_only` is set. Default:
+    /// `1000`.
+    #[serde(default = "default_normal_form_step_budget")]
+    pub normal_form_step_budget: usize,
+
+    /// Largest number of nodes a term may grow to while it is reduced for `normal_form_only`.
+    /// Terms that outgrow it are thrown away like those that run out of steps. Default: `1000`.
+    #[serde(default = "default_normal_form_size_limit")]
+    pub normal_form_size_limit: usize,
+
+    /// Only generate simply typed terms, by choosing each variable among those whose type fits
+    /// where it is used. Simply typed terms are strongly normalizing, so they never run into
+    /// `reduction_cutoff`. Default: `false`.
+    #[serde(default)]
+    pub typed: bool,
+}
+
+fn default_normal_form_step_budget() -> usize {
+    1000
+}
+
+fn default_normal_form_size_limit() -> usize {
+    1000
+}
+
+/// Configuration for the SKI combinator generator. A config that `validate` rejects is
+/// rejected when it is parsed.
+#[warn(missing_docs)]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(try_from = "UncheckedSKIGen")]
+pub struct SKIGen {
+    /// The seed for the lambda expression generator. If set to `None`, then a seed is chosen
+    /// randomly. Default: `None`
+    pub seed: ConfigSeed,
+
+    /// Largest number of combinators in a generated term. The number of combinators in each
+    /// term is drawn uniformly from `1..=max_size`. Default: `8`.
+    pub max_size: u32,
+
+    /// Relative weight of the `S` combinator. Default: `1.0`.
+    pub s_weight: f64,
+
+    /// Relative weight of the `K` combinator. Default: `1.0`.
+    pub k_weight: f64,
+
+    /// Relative weight of the `I` combinator. Default: `1.0`.
+    pub i_weight: f64,
+}
+
+impl SKIGen {
+    /// Check that the generator can draw a size and a combinator, describing the problem if it
+    /// can't.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let weights = [self.s_weight, self.k_weight, self.i_weight];
+        if self.max_size == 0 {
+            Err(String::from("ski max size must be at least 1"))
+        } else if weights.iter().any(|w| !w.is_finite() || *w < 0.0) {
+            Err(String::from(
+                "combinator weights must be finite and not negative",
+            ))
+        } else if weights.iter().all(|w| *w == 0.0) {
+            Err(String::from("combinator weights must not all be zero"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The fields of an `SKIGen`, as parsed, before they are validated.
+#[derive(Deserialize)]
+struct UncheckedSKIGen {
+    seed: ConfigSeed,
+    max_size: u32,
+    s_weight: f64,
+    k_weight: f64,
+    i_weight: f64,
+}
+
+impl TryFrom<UncheckedSKIGen> for SKIGen {
+    type Error = String;
+
+    fn try_from(unchecked: UncheckedSKIGen) -> Result<Self, Self::Error> {
+        let cfg = SKIGen {
+            seed: unchecked.seed,
+            max_size: unchecked.max_size,
+            s_weight: unchecked.s_weight,
+            k_weight: unchecked.k_weight,
+            i_weight: unchecked.i_weight,
+        };
+        cfg.validate()?;
+        Ok(cfg)
+    }
 }
 
 /// Configuration for Fontana's generator
@@ -134,16 +683,33 @@ impl Reactor {
     /// Produce a new `ReactorConfig` struct with default values.
     pub fn new() -> Self {
         Reactor {
-            rules: vec![String::from("\\x.\\y.x y")],
+            rules: vec![ReactionRule::new("\\x.\\y.x y")],
+            rule_application: RuleApplication::All,
+            rule_weights: None,
 
             discard_copy_actions: true,
             discard_identity: true,
             discard_free_variable_expressions: true,
             maintain_constant_population_size: true,
+            protect_new_products: false,
+            require_distinct_reactants: false,
+            selection_strategy: SelectionStrategy::Random,
+            eviction_policy: EvictionPolicy::Random,
+            decay_probability: 0.0,
+            max_population: None,
             discard_parents: false,
             reduction_cutoff: 500,
             size_cutoff: 500,
+            reduction_order: ReductionOrder::default(),
+            collision_timeout_ms: None,
+            size_check_interval: None,
+            collision_cache_size: None,
             seed: ConfigSeed(None),
+            record_tape: false,
+            record_lineage: false,
+            lineage_depth: None,
+            record_genealogy: false,
+            removed_age_buffer: default_removed_age_buffer(),
         }
     }
 }
@@ -156,15 +722,189 @@ impl Default for Reactor {
     }
 }
 
+impl Reactor {
+    /// Read a reactor config from the TOML file at `path`. The file holds the fields of
+    /// `Reactor` at the top level. Every reaction rule must parse as a lambda expression in
+    /// `Classic` notation.
+    pub fn from_toml_path(path: &Path) -> Result<Reactor, ConfigError> {
+        let reactor: Reactor = toml::from_str(&read_to_string(path)?)?;
+        reactor.parse_rules()?;
+        Ok(reactor)
+    }
+
+    /// Parse every reaction rule in `Classic` notation. If any fail to parse, the error lists
+    /// all of them.
+    pub fn parse_rules(&self) -> Result<Vec<Term>, ConfigError> {
+        let mut terms = Vec::with_capacity(self.rules.len());
+        let mut invalid = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            match lambda_calculus::parse(&rule.rule, lambda_calculus::Classic) {
+                Ok(term) => terms.push(term),
+                Err(error) => invalid.push(InvalidRule {
+                    index,
+                    rule: rule.rule.clone(),
+                    error,
+                }),
+            }
+        }
+        if invalid.is_empty() {
+            Ok(terms)
+        } else {
+            Err(ConfigError::InvalidRules(invalid))
+        }
+    }
+}
+
+/// Serde adapter that writes a `Reactor` as the text of a config file, for use with
+/// `#[serde(with = "crate::config::reactor_as_toml")]`. Reaction rules may be written in a
+/// shorthand that only self-describing formats can tell apart, so a `Reactor` saved in a
+/// binary format goes through TOML.
+pub(crate) mod reactor_as_toml {
+    use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serializer};
+
+    use super::Reactor;
+
+    pub fn serialize<S: Serializer>(reactor: &Reactor, serializer: S) -> Result<S::Ok, S::Error> {
+        let text = toml::to_string(reactor).map_err(S::Error::custom)?;
+        serializer.serialize_str(&text)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Reactor, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        toml::from_str(&text).map_err(D::Error::custom)
+    }
+}
+
+impl BTreeGen {
+    /// Read a BTree generator config from the TOML file at `path`. The file holds the fields of
+    /// `BTreeGen` at the top level.
+    pub fn from_toml_path(path: &Path) -> Result<BTreeGen, ConfigError> {
+        Ok(toml::from_str(&read_to_string(path)?)?)
+    }
+}
+
+/// Why a config file could not be read.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read
+    Io(io::Error),
+
+    /// The file is not valid TOML, or does not describe a config. This includes malformed seeds.
+    Toml(toml::de::Error),
+
+    /// Some reaction rules are not valid lambda expressions
+    InvalidRules(Vec<InvalidRule>),
+
+    /// The reaction rule weights don't fit the rules, or are given without
+    /// `RuleApplication::Random`
+    InvalidRuleWeights(String),
+
+    /// The decay probability is not between `0.0` and `1.0`
+    InvalidDecayProbability(f64),
+
+    /// The population cap is below two
+    InvalidMaxPopulation(usize),
+}
+
+/// A reaction rule that failed to parse, and its index among the rules.
+#[derive(Debug)]
+pub struct InvalidRule {
+    pub index: usize,
+    pub rule: String,
+    pub error: ParseError,
+}
+
+impl fmt::Display for InvalidRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rule {} `{}`: {}", self.index, self.rule, self.error)
+    }
+}
+
+impl std::error::Error for InvalidRule {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "cannot read config: {e}"),
+            ConfigError::Toml(e) => write!(f, "invalid config: {e}"),
+            ConfigError::InvalidRules(rules) => {
+                write!(f, "invalid reaction rules:")?;
+                for rule in rules {
+                    write!(f, " {rule};")?;
+                }
+                Ok(())
+            }
+            ConfigError::InvalidRuleWeights(reason) => {
+                write!(f, "invalid reaction rule weights: {reason}")
+            }
+            ConfigError::InvalidDecayProbability(p) => {
+                write!(f, "decay probability {p} must lie between 0 and 1")
+            }
+            ConfigError::InvalidMaxPopulation(cap) => {
+                write!(f, "max population {cap} must be at least 2")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(e) => Some(e),
+            ConfigError::Toml(e) => Some(e),
+            ConfigError::InvalidRules(rules) => rules.first().map(|r| &r.error as _),
+            ConfigError::InvalidRuleWeights(_)
+            | ConfigError::InvalidDecayProbability(_)
+            | ConfigError::InvalidMaxPopulation(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(e: toml::de::Error) -> Self {
+        ConfigError::Toml(e)
+    }
+}
+
+impl GenConfig for SKIGen {
+    /// Produce a new `SKIGen` config with default values.
+    fn new() -> Self {
+        SKIGen {
+            seed: ConfigSeed(None),
+            max_size: 8,
+            s_weight: 1.0,
+            k_weight: 1.0,
+            i_weight: 1.0,
+        }
+    }
+}
+
 impl GenConfig for BTreeGen {
     /// Produce a new `BTreeGenConfig` struct with default values.
     fn new() -> Self {
         BTreeGen {
             size: 20,
+            size_distribution: None,
             freevar_generation_probability: 0.2,
             standardization: Standardization::Prefix,
             n_max_free_vars: 6,
+            lambda_probability: None,
             seed: ConfigSeed(None),
+            require_closed: false,
+            normal_form_only: false,
+            normal_form_step_budget: default_normal_form_step_budget(),
+            normal_form_size_limit: default_normal_form_size_limit(),
+            typed: false,
         }
     }
 }
@@ -201,9 +941,28 @@ impl Config {
             sample_size: 1000,
             polling_interval: None,
             verbose_logging: false,
+            experiment: None,
         }
     }
 
+    /// Create a config object from a TOML string
+    pub fn from_toml_str(s: &str) -> Result<Config, toml::de::Error> {
+        toml::from_str(s)
+    }
+
+    /// Convert the config object to a TOML string
+    pub fn to_toml_str(&self) -> String {
+        toml::to_string(self).unwrap()
+    }
+
+    /// Read a config object from the TOML file at `path`. Every reaction rule must parse as a
+    /// lambda expression in `Classic` notation, as for `Reactor::from_toml_path`.
+    pub fn from_toml_file(path: &Path) -> Result<Config, ConfigError> {
+        let config: Config = toml::from_str(&read_to_string(path)?)?;
+        config.reactor_config.parse_rules()?;
+        Ok(config)
+    }
+
     pub fn set_reduction_cutoff(&mut self, cutoff: usize) {
         self.reactor_config.reduction_cutoff = cutoff;
     }
@@ -244,8 +1003,60 @@ impl ConfigSeed {
     pub fn blank() -> Self {
         ConfigSeed(None)
     }
+
+    /// Read a seed written as 64 hex digits, as in a config file.
+    pub fn from_hex(s: &str) -> Result<Self, SeedError> {
+        if let Some(c) = s.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(SeedError::NotHex(c));
+        }
+        if s.len() != 64 {
+            return Err(SeedError::Length(s.len()));
+        }
+        let bytes = decode_hex(s).expect("64 hex digits decode to 32 bytes");
+        Ok(ConfigSeed::new(bytes.try_into().unwrap()))
+    }
+
+    /// A fixed seed drawn from the OS RNG. Unlike a blank seed, it can be printed and reused
+    /// to reproduce the run.
+    pub fn random() -> Self {
+        ConfigSeed::new(OsRng.gen())
+    }
+}
+
+/// Prints the seed as hex digits, or `random` if it is blank.
+impl fmt::Display for ConfigSeed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(seed) => f.write_str(&encode_hex(&seed)),
+            None => f.write_str("random"),
+        }
+    }
 }
 
+/// Why a string is not a valid seed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SeedError {
+    /// The string contains a character that is not a hex digit
+    NotHex(char),
+
+    /// The string has this many hex digits instead of 64
+    Length(usize),
+}
+
+impl fmt::Display for SeedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SeedError::NotHex(c) => write!(f, "seed contains {c:?}, which is not a hex digit"),
+            SeedError::Length(n) => write!(
+                f,
+                "seed must be 32 bytes long, written as 64 hex digits, not {n} digits"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SeedError {}
+
 /// Manually serialize [u8; 32] to a hex string
 impl Serialize for ConfigSeed {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -260,20 +1071,248 @@ impl Serialize for ConfigSeed {
     }
 }
 
-/// Manually deserialize a hex string to [u8; 32]
-///
-/// SAFETY: `panic!`s when hex string is malformed or of odd length
+/// Manually deserialize a hex string to [u8; 32]. The string `"random"` deserializes to a blank
+/// seed, since TOML has no way to write `None`. Anything else must be 64 hex digits.
 impl<'de> Deserialize<'de> for ConfigSeed {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        let seed_string: Option<&str> = serde::de::Deserialize::deserialize(deserializer)?;
-        Ok(if let Some(s) = seed_string {
-            let hexvec = decode_hex(s).unwrap();
-            ConfigSeed::new(hexvec.try_into().unwrap())
-        } else {
-            ConfigSeed::blank()
+        let seed_string: Option<String> = serde::de::Deserialize::deserialize(deserializer)?;
+        Ok(match seed_string.as_deref() {
+            Some("random") | None => ConfigSeed::blank(),
+            Some(s) => ConfigSeed::from_hex(s).map_err(serde::de::Error::custom)?,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        BTreeGen, Config, ConfigError, ConfigSeed, GenConfig, Generator, ReactionRule, Reactor,
+        ReductionOrder, SKIGen, SeedError,
+    };
+
+    #[test]
+    fn zero_rule_weight_is_rejected() {
+        let mut cfg = serde_json::to_value(Reactor::new()).unwrap();
+        cfg["rules"] = serde_json::json!(["\\x.\\y.x y", "\\x.\\y.y x"]);
+        cfg["rule_weights"] = serde_json::json!([1.0, 0.0]);
+        let err = serde_json::from_value::<Reactor>(cfg).unwrap_err();
+        assert!(err.to_string().contains("must be positive"));
+    }
+
+    #[test]
+    fn lambda_probability_must_be_a_probability() {
+        let mut cfg = serde_json::to_value(BTreeGen::new()).unwrap();
+        cfg["lambda_probability"] = serde_json::json!(0.5);
+        let gen = serde_json::from_value::<BTreeGen>(cfg.clone()).unwrap();
+        assert_eq!(gen.lambda_probability, Some(0.5));
+
+        cfg["lambda_probability"] = serde_json::json!(1.5);
+        let err = serde_json::from_value::<BTreeGen>(cfg).unwrap_err();
+        assert!(err.to_string().contains("must lie between 0 and 1"));
+    }
+
+    #[test]
+    fn decay_probability_must_be_a_probability() {
+        let mut cfg = serde_json::to_value(Reactor::new()).unwrap();
+        cfg["decay_probability"] = serde_json::json!(0.5);
+        let reactor = serde_json::from_value::<Reactor>(cfg.clone()).unwrap();
+        assert_eq!(reactor.decay_probability, 0.5);
+
+        for p in [1.5, -0.1] {
+            cfg["decay_probability"] = serde_json::json!(p);
+            let err = serde_json::from_value::<Reactor>(cfg.clone()).unwrap_err();
+            assert!(err.to_string().contains("must lie between 0 and 1"));
+        }
+    }
+
+    #[test]
+    fn max_population_leaves_room_for_a_reaction() {
+        let mut cfg = serde_json::to_value(Reactor::new()).unwrap();
+        cfg["max_population"] = serde_json::json!(2);
+        let reactor = serde_json::from_value::<Reactor>(cfg.clone()).unwrap();
+        assert_eq!(reactor.max_population, Some(2));
+
+        for cap in [0, 1] {
+            cfg["max_population"] = serde_json::json!(cap);
+            let err = serde_json::from_value::<Reactor>(cfg.clone()).unwrap_err();
+            assert!(err.to_string().contains("must be at least 2"));
+        }
+    }
+
+    #[test]
+    fn size_distributions_must_draw_positive_sizes() {
+        let mut cfg = serde_json::to_value(BTreeGen::new()).unwrap();
+        cfg["size_distribution"] = serde_json::json!({ "Uniform": { "min": 1, "max": 4 } });
+        assert!(serde_json::from_value::<BTreeGen>(cfg.clone()).is_ok());
+
+        for distribution in [
+            serde_json::json!({ "Fixed": 0 }),
+            serde_json::json!({ "Fixed": u64::from(u32::MAX) + 1 }),
+            serde_json::json!({ "Uniform": { "min": 0, "max": 4 } }),
+            serde_json::json!({ "Uniform": { "min": 5, "max": 4 } }),
+            serde_json::json!({ "Geometric": { "mean": 0.5 } }),
+            serde_json::json!({ "Custom": [] }),
+            serde_json::json!({ "Custom": [0.0, 0.0] }),
+            serde_json::json!({ "Custom": [1.0, -1.0] }),
+        ] {
+            cfg["size_distribution"] = distribution;
+            assert!(serde_json::from_value::<BTreeGen>(cfg.clone()).is_err());
+        }
+    }
+
+    #[test]
+    fn ski_configs_must_draw_a_combinator() {
+        let cfg = serde_json::to_value(SKIGen::new()).unwrap();
+        assert!(serde_json::from_value::<SKIGen>(cfg.clone()).is_ok());
+
+        for (field, value) in [
+            ("max_size", serde_json::json!(0)),
+            ("s_weight", serde_json::json!(-1.0)),
+            ("i_weight", serde_json::json!(-0.5)),
+        ] {
+            let mut bad = cfg.clone();
+            bad[field] = value;
+            assert!(serde_json::from_value::<SKIGen>(bad).is_err(), "{field}");
+        }
+
+        let mut zero = cfg;
+        for field in ["s_weight", "k_weight", "i_weight"] {
+            zero[field] = serde_json::json!(0.0);
+        }
+        let err = serde_json::from_value::<SKIGen>(zero).unwrap_err();
+        assert!(err.to_string().contains("must not all be zero"));
+    }
+
+    #[test]
+    fn rules_may_be_bare_or_have_a_probability() {
+        let mut cfg = serde_json::to_value(Reactor::new()).unwrap();
+        cfg["rules"] = serde_json::json!([
+            "\\x.\\y.x y",
+            { "rule": "\\x.\\y.y x", "probability": 0.25 },
+        ]);
+        let reactor = serde_json::from_value::<Reactor>(cfg.clone()).unwrap();
+        assert_eq!(reactor.rules[0], ReactionRule::new("\\x.\\y.x y"));
+        assert_eq!(reactor.rules[1].probability, 0.25);
+
+        assert_eq!(reactor.rules[1].reduction_order, None);
+
+        cfg["rules"][1]["reduction_order"] = serde_json::json!("CBN");
+        let reactor = serde_json::from_value::<Reactor>(cfg.clone()).unwrap();
+        assert_eq!(reactor.rules[1].reduction_order.unwrap().to_string(), "CBN");
+
+        cfg["rules"][1]["probability"] = serde_json::json!(1.5);
+        let err = serde_json::from_value::<Reactor>(cfg).unwrap_err();
+        assert!(err.to_string().contains("must lie between 0 and 1"));
+    }
+
+    #[test]
+    fn reduction_order_parses_names() {
+        let order = "nor".parse::<ReductionOrder>().unwrap();
+        assert_eq!(order.to_string(), "NOR");
+        assert_eq!(ReductionOrder::default().to_string(), "HAP");
+
+        let mut cfg = serde_json::to_value(Reactor::new()).unwrap();
+        assert_eq!(cfg["reduction_order"], "HAP");
+        cfg["reduction_order"] = serde_json::json!("XYZ");
+        let err = serde_json::from_value::<Reactor>(cfg).unwrap_err();
+        assert!(err.to_string().contains("expected one of NOR, CBN"));
+    }
+
+    #[test]
+    fn toml_config_round_trips() {
+        let cfg = Config::from_toml_str(&Config::new().to_toml_str()).unwrap();
+        assert_eq!(cfg.run_limit, Config::new().run_limit);
+        assert!(cfg.experiment.is_none());
+        assert!(matches!(cfg.generator_config, Generator::BTree(_)));
+    }
+
+    #[test]
+    fn toml_config_reads_random_seed_and_experiment() {
+        let mut table = toml::Table::try_from(Config::new()).unwrap();
+        let reactor = table["reactor_config"].as_table_mut().unwrap();
+        reactor.insert("seed".into(), "random".into());
+        table.insert(
+            "experiment".into(),
+            toml::toml! { name = "entropy-time-series" }.into(),
+        );
+        let cfg = Config::from_toml_str(&table.to_string()).unwrap();
+        assert!(cfg.reactor_config.seed.seed().is_none());
+        assert_eq!(cfg.experiment.unwrap().name, "entropy-time-series");
+    }
+
+    #[test]
+    fn toml_files_report_bad_rules_and_seeds() {
+        let path = std::env::temp_dir().join(format!("alchemy-config-{}.toml", std::process::id()));
+        let mut table = toml::Table::try_from(Reactor::new()).unwrap();
+        table.insert("seed".into(), "00ff".repeat(16).into());
+        table.insert("rules".into(), vec!["\\x.\\y.y x"].into());
+        std::fs::write(&path, table.to_string()).unwrap();
+        let reactor = Reactor::from_toml_path(&path).unwrap();
+        assert_eq!(reactor.rules, [ReactionRule::new("\\x.\\y.y x")]);
+        assert_eq!(reactor.seed.seed().unwrap()[..2], [0x00, 0xff]);
+
+        table.insert("rules".into(), vec!["\\x.(x"].into());
+        std::fs::write(&path, table.to_string()).unwrap();
+        let err = Reactor::from_toml_path(&path).unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidRules(ref r) if r[0].rule == "\\x.(x"));
+
+        let mut table = toml::Table::try_from(BTreeGen::new()).unwrap();
+        table.insert("seed".into(), "00ff".into());
+        std::fs::write(&path, table.to_string()).unwrap();
+        let err = BTreeGen::from_toml_path(&path).unwrap_err();
+        assert!(err.to_string().contains("seed must be 32 bytes long"));
+
+        std::fs::remove_file(&path).unwrap();
+        let err = BTreeGen::from_toml_path(&path).unwrap_err();
+        assert!(matches!(err, ConfigError::Io(_)));
+    }
+
+    #[test]
+    fn toml_config_files_report_bad_rules() {
+        let path = std::env::temp_dir().join(format!("alchemy-full-{}.toml", std::process::id()));
+        let mut table = toml::Table::try_from(Config::new()).unwrap();
+        std::fs::write(&path, table.to_string()).unwrap();
+        let cfg = Config::from_toml_file(&path).unwrap();
+        assert_eq!(cfg.run_limit, Config::new().run_limit);
+
+        let reactor = table["reactor_config"].as_table_mut().unwrap();
+        reactor.insert("rules".into(), vec!["\\x.(x"].into());
+        std::fs::write(&path, table.to_string()).unwrap();
+        let err = Config::from_toml_file(&path).unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidRules(ref r) if r[0].rule == "\\x.(x"));
+
+        std::fs::remove_file(&path).unwrap();
+        let err = Config::from_toml_file(&path).unwrap_err();
+        assert!(matches!(err, ConfigError::Io(_)));
+    }
+
+    #[test]
+    fn seeds_parse_from_hex() {
+        let hex = "0123456789abcdef".repeat(4);
+        let seed = ConfigSeed::from_hex(&hex).unwrap();
+        assert_eq!(seed.seed().unwrap()[..2], [0x01, 0x23]);
+        assert_eq!(seed.to_string(), hex);
+        assert_eq!(ConfigSeed::blank().to_string(), "random");
+
+        let random = ConfigSeed::random();
+        let reread = ConfigSeed::from_hex(&random.to_string()).unwrap();
+        assert_eq!(reread.seed(), random.seed());
+
+        assert_eq!(
+            ConfigSeed::from_hex("00ff").unwrap_err(),
+            SeedError::Length(4)
+        );
+        let bad = hex.replace('a', "g");
+        assert_eq!(
+            ConfigSeed::from_hex(&bad).unwrap_err(),
+            SeedError::NotHex('g')
+        );
+        assert_eq!(
+            ConfigSeed::from_hex("é").unwrap_err(),
+            SeedError::NotHex('é')
+        );
+    }
+}
@@ -2,12 +2,12 @@ use async_std::task::{block_on, spawn};
 use futures::stream::{FuturesUnordered, StreamExt};
 use lambda_calculus::{
     data::num::church::{add, succ},
-    Term,
+    Term, HAP,
 };
 use rand::random;
 
 use crate::{
-    config::{self, ConfigSeed},
+    config::{self, ConfigSeed, GenConfig},
     generators::BTreeGen,
     lambda::recursive::reduce_with_limit,
     utils::dump_series_to_file,
@@ -22,11 +22,8 @@ use super::{
 
 fn experiment_gen(seed: ConfigSeed) -> BTreeGen {
     BTreeGen::from_config(&config::BTreeGen {
-        size: 20,
-        freevar_generation_probability: 0.2,
-        standardization: crate::generators::Standardization::Prefix,
-        n_max_free_vars: 6,
         seed,
+        ..config::BTreeGen::new()
     })
 }
 
@@ -39,10 +36,10 @@ pub fn measure_initial_population() {
                 gen.generate_n(10000)
                     .iter_mut()
                     .map(|mut t| {
-                        let r = reduce_with_limit(&mut t, 1000, 8000);
+                        let r = reduce_with_limit(&mut t, HAP, 1000, 8000);
                         (r, t)
                     })
-                    .filter(|(r, t)| r.is_ok() && t.is_isomorphic_to(&term))
+                    .filter(|(r, t)| r.is_normalized() && t.is_isomorphic_to(&term))
                     .count()
             })
             .collect::<Vec<_>>();
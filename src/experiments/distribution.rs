@@ -1,16 +1,17 @@
-use std::collections::HashMap;
+use std::io;
 
 use lambda_calculus::Term;
 
 use crate::{
     config::{self, ConfigSeed},
+    export::{write_counts_csv, write_histograms_csv},
     lambda::recursive::LambdaSoup,
     utils::read_inputs,
 };
 
 fn experiment_soup(seed: ConfigSeed) -> LambdaSoup {
     LambdaSoup::from_config(&config::Reactor {
-        rules: vec![String::from("\\x.\\y.x y")],
+        rules: vec![config::ReactionRule::new("\\x.\\y.x y")],
         discard_copy_actions: false,
         discard_identity: false,
         discard_free_variable_expressions: true,
@@ -19,13 +20,13 @@ fn experiment_soup(seed: ConfigSeed) -> LambdaSoup {
         reduction_cutoff: 8000,
         size_cutoff: 1000,
         seed,
+        ..config::Reactor::new()
     })
 }
 
 pub fn one_sample_with_dist() {
     let run_length = 1000000;
     let polling_interval = 1000;
-    let polls = run_length / polling_interval;
     let sample = read_inputs().collect::<Vec<Term>>();
     let mut soup = experiment_soup(ConfigSeed::new([0; 32]));
 
@@ -34,30 +35,23 @@ pub fn one_sample_with_dist() {
         s.expression_counts()
     });
 
-    let mut map = HashMap::<Term, Vec<u32>>::new();
-    for (i, count) in counts.iter().enumerate() {
-        for (term, val) in count.iter() {
-            map.entry(term.clone())
-                .or_insert(vec![0; i.try_into().unwrap()])
-                .push(*val);
-        }
-        for (term, vals) in map.iter_mut() {
-            if !count.contains_key(term) {
-                vals.push(0);
-            }
-        }
-    }
-
-    print!("Term, ");
-    for i in 0..polls {
-        print!("{}, ", i)
-    }
-    println!();
-    for (term, vec) in map.iter() {
-        print!("{}, ", term);
-        for c in vec {
-            print!("{}, ", c);
-        }
-        println!();
-    }
+    write_counts_csv(&counts, &mut io::stdout().lock()).expect("Cannot write to stdout");
+}
+
+/// Like `one_sample_with_dist`, but track the sizes of the expressions instead of their
+/// counts, in buckets that double in width, so that growing expressions show up long before
+/// they exhaust memory.
+pub fn one_sample_with_size_dist() {
+    let run_length = 1000000;
+    let polling_interval = 1000;
+    let sample = read_inputs().collect::<Vec<Term>>();
+    let mut soup = experiment_soup(ConfigSeed::new([0; 32]));
+    let edges = (0..11).map(|i| 1 << i).collect::<Vec<usize>>();
+
+    soup.add_lambda_expressions(sample.into_iter().cycle().take(10000));
+    let sizes = soup.simulate_and_poll(run_length, polling_interval, false, |s| {
+        s.size_distribution(&edges)
+    });
+
+    write_histograms_csv(&sizes, &mut io::stdout().lock()).expect("Cannot write to stdout");
 }
@@ -3,14 +3,14 @@ use futures::{stream::FuturesUnordered, StreamExt};
 use lambda_calculus::Term;
 
 use crate::{
-    config::{self, ConfigSeed},
+    config::{self, ConfigSeed, GenConfig},
     generators::BTreeGen,
     lambda::recursive::LambdaSoup,
 };
 
 fn experiment_soup(seed: ConfigSeed) -> LambdaSoup {
     LambdaSoup::from_config(&config::Reactor {
-        rules: vec![String::from("\\x.\\y.x y")],
+        rules: vec![config::ReactionRule::new("\\x.\\y.x y")],
         discard_copy_actions: false,
         discard_identity: false,
         discard_free_variable_expressions: true,
@@ -19,16 +19,14 @@ fn experiment_soup(seed: ConfigSeed) -> LambdaSoup {
         reduction_cutoff: 8000,
         size_cutoff: 1000,
         seed,
+        ..config::Reactor::new()
     })
 }
 
 fn experiment_gen(seed: ConfigSeed) -> BTreeGen {
     BTreeGen::from_config(&config::BTreeGen {
-        size: 20,
-        freevar_generation_probability: 0.2,
-        standardization: crate::generators::Standardization::Prefix,
-        n_max_free_vars: 6,
         seed,
+        ..config::BTreeGen::new()
     })
 }
 
@@ -49,12 +47,19 @@ async fn simulate_soup_and_produce_entropies(
     id: usize,
     run_length: usize,
     polling_interval: usize,
+    inflow_rate: f64,
 ) -> (usize, Vec<f32>) {
     let mut seed: [u8; 32] = [0; 32];
     let bytes = id.to_le_bytes();
     seed[..bytes.len()].copy_from_slice(&bytes);
     let mut soup = experiment_soup(ConfigSeed::new([0; 32]));
     soup.add_lambda_expressions(sample);
+    if inflow_rate > 0.0 {
+        soup.set_generated_inflow(inflow_rate, move || {
+            let mut gen = experiment_gen(ConfigSeed::new(seed));
+            move || gen.generate()
+        });
+    }
     let data = soup.simulate_and_poll(run_length, polling_interval, false, |s: &LambdaSoup| {
         s.population_entropy()
     });
@@ -62,6 +67,16 @@ async fn simulate_soup_and_produce_entropies(
 }
 
 pub fn entropy_time_series() {
+    entropy_series(0.0)
+}
+
+/// Same as `entropy_time_series`, but with a steady inflow of freshly generated expressions, one
+/// every hundred collisions.
+pub fn entropy_time_series_with_inflow() {
+    entropy_series(0.01)
+}
+
+fn entropy_series(inflow_rate: f64) {
     let mut gen = experiment_gen(ConfigSeed::new([0; 32]));
     let mut futures = FuturesUnordered::new();
     let run_length = 10000000;
@@ -74,6 +89,7 @@ pub fn entropy_time_series() {
             i,
             run_length,
             polling_interval,
+            inflow_rate,
         )));
     }
 
@@ -100,10 +116,10 @@ pub fn entropy_and_failures() {
     }
 
     let mut data = Vec::new();
-    println!("Soup, Entropy, Failure rate");
+    println!("Soup, Entropy, Failure rate, Decayed");
     while let Some((soup, id, failure_rate)) = block_on(futures.next()) {
         let entropy = soup.population_entropy();
-        println!("{}, {}, {}", id, entropy, failure_rate);
+        println!("{}, {}, {}, {}", id, entropy, failure_rate, soup.decayed());
         data.push(entropy);
     }
 }
@@ -117,6 +133,6 @@ pub fn sync_entropy_and_failures() {
         soup.add_lambda_expressions(sample);
         soup.simulate_for(100000, false);
         let entropy = soup.population_entropy();
-        println!("{}: {}", i, entropy);
+        println!("{}: {}, {} decayed", i, entropy, soup.decayed());
     }
 }
@@ -0,0 +1,133 @@
+//! Island-model simulations: several soups evolve in parallel, and every so often a few
+//! expressions migrate from each soup to another.
+
+use std::sync::Arc;
+
+use async_std::task::{block_on, spawn};
+use futures::future::join_all;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{config::ConfigSeed, lambda::recursive::LambdaSoup};
+
+/// Parameters for `run_islands`.
+pub struct IslandParams {
+    /// Number of collisions each island goes through.
+    pub run_length: usize,
+
+    /// Poll each island every `polling_interval` collisions. Polling restarts after each
+    /// migration, so this should divide `migration_interval`.
+    pub polling_interval: usize,
+
+    /// Number of collisions between migrations.
+    pub migration_interval: usize,
+
+    /// Number of expressions each island sends away at every migration.
+    pub migration_count: usize,
+
+    /// Seed for choosing the migrants and their destinations.
+    pub migration_seed: ConfigSeed,
+}
+
+/// Run `islands` concurrently for `params.run_length` collisions each. Every
+/// `params.migration_interval` collisions, each island in turn sends `params.migration_count`
+/// random expressions to another island chosen at random. An island never sends away so many
+/// expressions that it is left with fewer than two. Returns the islands and the results of
+/// calling `poller` on each of them, in the order the islands were given. With fixed seeds for
+/// the islands and for migration, two runs give the same results.
+pub fn run_islands<F, R>(
+    mut islands: Vec<LambdaSoup>,
+    params: &IslandParams,
+    poller: F,
+) -> (Vec<LambdaSoup>, Vec<Vec<R>>)
+where
+    F: Fn(&LambdaSoup) -> R + Send + Sync + 'static,
+    R: Send + 'static,
+{
+    assert!(
+        params.migration_interval > 0,
+        "migration interval must be positive"
+    );
+    let poller = Arc::new(poller);
+    let mut rng = ChaCha8Rng::from_seed(params.migration_seed.get());
+    let mut series = islands.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+
+    let mut remaining = params.run_length;
+    while remaining > 0 {
+        let epoch = remaining.min(params.migration_interval);
+        remaining -= epoch;
+
+        let tasks = islands.into_iter().map(|mut soup| {
+            let poller = poller.clone();
+            let polling_interval = params.polling_interval;
+            spawn(async move {
+                let data = soup.simulate_and_poll(epoch, polling_interval, false, |s| poller(s));
+                (soup, data)
+            })
+        });
+        islands = Vec::new();
+        for ((soup, data), s) in block_on(join_all(tasks)).into_iter().zip(&mut series) {
+            islands.push(soup);
+            s.extend(data);
+        }
+
+        if remaining > 0 && islands.len() > 1 {
+            migrate(&mut islands, params.migration_count, &mut rng);
+        }
+    }
+
+    (islands, series)
+}
+
+fn migrate(islands: &mut [LambdaSoup], count: usize, rng: &mut ChaCha8Rng) {
+    for source in 0..islands.len() {
+        // Pick any island but the source
+        let mut destination = rng.gen_range(0..islands.len() - 1);
+        if destination >= source {
+            destination += 1;
+        }
+        let n = count.min(islands[source].len().saturating_sub(2));
+        let migrants = islands[source].take_random(n, rng);
+        islands[destination].perturb(migrants);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_islands, IslandParams};
+    use crate::config::{self, ConfigSeed};
+    use crate::lambda::recursive::{ski_cycle, LambdaSoup};
+
+    fn islands() -> Vec<LambdaSoup> {
+        (0..3u8)
+            .map(|i| {
+                let mut soup = LambdaSoup::from_config(&config::Reactor {
+                    seed: ConfigSeed::new([i; 32]),
+                    ..config::Reactor::new()
+                });
+                soup.add_lambda_expressions(ski_cycle().skip(i as usize).take(20));
+                soup
+            })
+            .collect()
+    }
+
+    #[test]
+    fn island_runs_are_reproducible() {
+        let params = IslandParams {
+            run_length: 100,
+            polling_interval: 10,
+            migration_interval: 25,
+            migration_count: 3,
+            migration_seed: ConfigSeed::new([7; 32]),
+        };
+        let run = || run_islands(islands(), &params, |s| s.unique_expressions().len());
+        let ((first, first_series), (second, second_series)) = (run(), run());
+
+        assert_eq!(first_series, second_series);
+        assert!(first_series.iter().all(|s| s.len() == 12));
+        assert_eq!(first.iter().map(|s| s.len()).sum::<usize>(), 60);
+        for (a, b) in first.iter().zip(&second) {
+            assert!(a.expressions().eq(b.expressions()));
+        }
+    }
+}
@@ -13,7 +13,7 @@ use super::magic_test_function::{asymmetric_skip_sample, test_succ};
 
 fn experiment_soup(seed: ConfigSeed) -> LambdaSoup {
     LambdaSoup::from_config(&config::Reactor {
-        rules: vec![String::from("\\x.\\y.x y")],
+        rules: vec![config::ReactionRule::new("\\x.\\y.x y")],
         discard_copy_actions: false,
         discard_identity: false,
         discard_free_variable_expressions: true,
@@ -22,6 +22,7 @@ fn experiment_soup(seed: ConfigSeed) -> LambdaSoup {
         reduction_cutoff: 8000,
         size_cutoff: 1000,
         seed,
+        ..config::Reactor::new()
     })
 }
 
@@ -6,7 +6,7 @@ use lambda_calculus::{
     combinators::{I, K, S},
     data::{
         boolean::{self, and},
-        num::church::{add, eq, succ},
+        num::church::{add, eq, fac, mul, succ},
     },
     parse,
     term::Notation::Classic,
@@ -16,15 +16,17 @@ use lambda_calculus::{
 use rand::random;
 
 use crate::{
-    config::{self, ConfigSeed},
+    config::{self, ConfigSeed, GenConfig},
     generators::BTreeGen,
-    lambda::recursive::{has_two_args, is_truthy, uses_both_arguments, LambdaSoup},
+    lambda::recursive::{
+        has_two_args, is_truthy, reduce_within, uses_both_arguments, LambdaSoup, ReductionLimits,
+    },
     utils::{dump_series_to_file, read_inputs},
 };
 
 fn experiment_soup(seed: ConfigSeed) -> LambdaSoup {
     LambdaSoup::from_config(&config::Reactor {
-        rules: vec![String::from("\\x.\\y.x y")],
+        rules: vec![config::ReactionRule::new("\\x.\\y.x y")],
         discard_copy_actions: false,
         discard_identity: false,
         discard_free_variable_expressions: true,
@@ -33,6 +35,7 @@ fn experiment_soup(seed: ConfigSeed) -> LambdaSoup {
         reduction_cutoff: 8000,
         size_cutoff: 1000,
         seed,
+        ..config::Reactor::new()
     })
 }
 
@@ -102,6 +105,74 @@ fn test_add_seq(pairs: impl Iterator<Item = (usize, usize)>) -> Term {
     test
 }
 
+pub(super) fn test_mul(a: usize, b: usize) -> Term {
+    let mut test = parse(r"\eq. \a. \b. \ab. \f. (eq (f a b) ab)", Classic).unwrap();
+    test = app!(
+        test,
+        eq(),
+        a.into_church(),
+        b.into_church(),
+        (a * b).into_church()
+    );
+    // `test` has type (church -> church -> church) -> bool
+    test.reduce(lambda_calculus::HAP, 0);
+    test
+}
+
+fn test_mul_seq(pairs: impl Iterator<Item = (usize, usize)>) -> Term {
+    let mut test = parse(r"\f. \a. \b. a", Classic).unwrap();
+    for (u, v) in pairs {
+        let gut = parse(
+            r"\and. \test. \testmul. \f. and (test f) (testmul f)",
+            Classic,
+        )
+        .unwrap();
+        test = app!(gut, and(), test, test_mul(u, v));
+    }
+    test.reduce(lambda_calculus::HAP, 0);
+    let mut comp = app!(test.clone(), mul());
+    comp.reduce(lambda_calculus::HAP, 0);
+    assert!(comp.is_isomorphic_to(&boolean::tru()));
+    test
+}
+
+/// The B combinator, `\f. \g. \x. f (g x)`, which composes two functions.
+pub fn compose() -> Term {
+    abs!(3, app!(Var(3), app!(Var(2), Var(1))))
+}
+
+/// A term that, applied to a candidate composition operator `c`, reduces to `true` if
+/// `c f g input` is the same Church numeral as `f (g input)`.
+pub fn test_compose(f_term: Term, g_term: Term, input: usize) -> Term {
+    let mut fgx = app!(f_term.clone(), app!(g_term.clone(), input.into_church()));
+    fgx.reduce(lambda_calculus::HAP, 0);
+
+    let mut test = parse(r"\eq. \f. \g. \x. \fgx. \c. (eq (c f g x) fgx)", Classic).unwrap();
+    test = app!(test, eq(), f_term, g_term, input.into_church(), fgx);
+    // `test` has type ((church -> church) -> (church -> church) -> church -> church) -> bool
+    test.reduce(lambda_calculus::HAP, 0);
+    test
+}
+
+/// Chain `test_compose` over several `(f, g, input)` triples, so the candidate must pass all of
+/// them.
+pub fn test_compose_seq(triples: impl Iterator<Item = (Term, Term, usize)>) -> Term {
+    let mut test = parse(r"\f. \a. \b. a", Classic).unwrap();
+    for (f, g, x) in triples {
+        let gut = parse(
+            r"\and. \test. \testcmp. \f. and (test f) (testcmp f)",
+            Classic,
+        )
+        .unwrap();
+        test = app!(gut, and(), test, test_compose(f, g, x));
+    }
+    test.reduce(lambda_calculus::HAP, 0);
+    let mut comp = app!(test.clone(), compose());
+    comp.reduce(lambda_calculus::HAP, 0);
+    assert!(comp.is_isomorphic_to(&boolean::tru()));
+    test
+}
+
 pub(super) fn test_succ(a: usize) -> Term {
     let mut test = parse(r"\eq. \a. \asucc. \f. (eq (f a) asucc)", Classic).unwrap();
     test = app!(test, eq(), a.into_church(), (a + 1).into_church());
@@ -135,6 +206,35 @@ pub fn test_addtwo(a: usize) -> Term {
     test
 }
 
+/// A test that a function maps the numeral `n` to the numeral `n!`.
+pub(super) fn test_factorial(n: usize) -> Term {
+    let mut test = parse(r"\eq. \a. \afact. \f. (eq (f a) afact)", Classic).unwrap();
+    let factorial = (1..=n).product::<usize>();
+    test = app!(test, eq(), n.into_church(), factorial.into_church());
+    // `test` has type (church -> church) -> bool
+    test.reduce(lambda_calculus::HAP, 0);
+    test
+}
+
+/// The conjunction of `test_factorial` for each of `nums`. Factorials grow quickly, so keep the
+/// numbers below 6 or so.
+pub(super) fn test_factorial_seq(nums: impl Iterator<Item = usize>) -> Term {
+    let mut test = parse(r"\f. \a. \b. a", Classic).unwrap();
+    for n in nums {
+        let gut = parse(
+            r"\and. \test. \testfact. \f. and (test f) (testfact f)",
+            Classic,
+        )
+        .unwrap();
+        test = app!(gut, and(), test, test_factorial(n));
+    }
+    test.reduce(lambda_calculus::HAP, 0);
+    let mut comp = app!(test.clone(), fac());
+    comp.reduce(lambda_calculus::HAP, 0);
+    assert!(comp.is_isomorphic_to(&boolean::tru()));
+    test
+}
+
 fn generate_sample_for_addsearch(seed: ConfigSeed) -> Vec<Term> {
     let mut sample = vec![S(); 200];
     sample.append(&mut vec![K(); 100]);
@@ -142,10 +242,8 @@ fn generate_sample_for_addsearch(seed: ConfigSeed) -> Vec<Term> {
     for size in 5..12 {
         let mut gen = BTreeGen::from_config(&config::BTreeGen {
             size,
-            freevar_generation_probability: 0.2,
-            standardization: crate::generators::Standardization::Prefix,
-            n_max_free_vars: 6,
             seed,
+            ..config::BTreeGen::new()
         });
         let n_samples = match size {
             5..=7 => 800,
@@ -262,6 +360,72 @@ async fn succ_magic_tests(
     (id, populations)
 }
 
+/// Run a soup with factorial tests until some expression in it passes `check`, and return
+/// the number of collisions that took, or `None` if none did within `run_length` collisions.
+/// The soup is checked every `polling_interval` collisions.
+async fn fact_magic_tests(
+    sample: impl Iterator<Item = Term>,
+    tests: impl Iterator<Item = Term>,
+    check: Term,
+    id: usize,
+    run_length: usize,
+    polling_interval: usize,
+) -> (usize, Option<usize>) {
+    let mut soup = experiment_soup(ConfigSeed::new([id as u8; 32]));
+    soup.add_lambda_expressions(sample);
+    soup.add_test_expressions(tests);
+    let limits = ReductionLimits::new(HAP, 32000, 16000);
+    let passes = |candidate: &Term| {
+        let mut comp = app!(check.clone(), candidate.clone());
+        reduce_within(&mut comp, &limits).is_normalized() && comp.is_isomorphic_to(&boolean::tru())
+    };
+    while soup.collisions() < run_length {
+        soup.simulate_for(polling_interval, false);
+        if soup.unique_expressions().iter().any(passes) {
+            return (id, Some(soup.collisions()));
+        }
+    }
+    (id, None)
+}
+
+async fn mul_magic_tests(
+    sample: impl Iterator<Item = Term>,
+    tests: impl Iterator<Item = Term>,
+    id: usize,
+    run_length: usize,
+    polling_interval: usize,
+) -> (usize, Vec<(usize, usize, usize)>) {
+    let mut soup = experiment_soup(ConfigSeed::new([id as u8; 32]));
+    soup.add_lambda_expressions(sample);
+    soup.add_test_expressions(tests);
+    let mut populations = Vec::new();
+    for i in 0..10 {
+        let pops = soup.simulate_and_poll(run_length / 10, polling_interval, false, |s| {
+            (
+                s.expressions().filter(|e| e.is_recursive()).count(),
+                s.population_of(&add()),
+                s.population_of(&mul()),
+            )
+        });
+        populations.extend(pops);
+        let n_remaining = 1000 - soup.expressions().filter(|e| e.is_recursive()).count();
+        let tests = [
+            || test_add(random::<usize>() % 20, random::<usize>() % 20),
+            || test_mul_seq((0..2).map(|_| (random::<usize>() % 6, random::<usize>() % 6))),
+        ]
+        .into_iter()
+        .map(|f| f())
+        .cycle()
+        .take(n_remaining);
+        soup.perturb_test_expressions(n_remaining, tests);
+        let skips = asymmetric_skip_sample();
+        soup.perturb_lambda_expressions(200, skips);
+
+        println!("Soup {id} {}0% done", i + 1);
+    }
+    (id, populations)
+}
+
 async fn simulate_additive_murder(
     sample: impl Iterator<Item = Term>,
     id: usize,
@@ -338,6 +502,38 @@ pub fn add_search_with_test() {
     }
 }
 
+pub fn mul_search_with_test() {
+    let mut futures = FuturesUnordered::new();
+    let run_length = 100000;
+    let polling_interval = 1000;
+    for i in 0..16 {
+        let sample = asymmetric_skip_sample();
+        dump_sample(&sample);
+
+        let distribution = sample.clone().into_iter().cycle().take(5000);
+        let tests = [
+            || test_add(random::<usize>() % 20, random::<usize>() % 20),
+            || test_mul(random::<usize>() % 6, random::<usize>() % 6),
+        ]
+        .into_iter()
+        .map(|f| f())
+        .cycle()
+        .take(1000);
+        futures.push(spawn(mul_magic_tests(
+            distribution,
+            tests,
+            i,
+            run_length,
+            polling_interval,
+        )));
+    }
+
+    let fname = "mul-search-output";
+    while let Some((id, series)) = block_on(futures.next()) {
+        dump_series_to_file(fname, &series, &[id]).expect("Cannot write to file");
+    }
+}
+
 pub fn succ_search_with_test() {
     let mut futures = FuturesUnordered::new();
     let run_length = 100000;
@@ -367,14 +563,57 @@ pub fn succ_search_with_test() {
     }
 }
 
+/// Search for factorial in 100 soups seeded with SKIP combinators and factorial tests, and
+/// print how many collisions it took each soup to hold an expression that passes a factorial
+/// test on `0` to `4`.
+pub fn fact_search_with_test() {
+    let mut futures = FuturesUnordered::new();
+    let run_length = 1000000;
+    let polling_interval = 1000;
+    for i in 0..100 {
+        let sample = asymmetric_skip_sample();
+        let distribution = sample.into_iter().cycle().take(5000);
+        let tests = [|| test_factorial(random::<usize>() % 5)]
+            .into_iter()
+            .map(|f| f())
+            .cycle()
+            .take(1000);
+        futures.push(spawn(fact_magic_tests(
+            distribution,
+            tests,
+            test_factorial_seq(0..5),
+            i,
+            run_length,
+            polling_interval,
+        )));
+    }
+
+    println!("Soup, Collisions to pass");
+    while let Some((id, collisions)) = block_on(futures.next()) {
+        match collisions {
+            Some(n) => println!("{}, {}", id, n),
+            None => println!("{}, never", id),
+        }
+    }
+}
+
 mod tests {
     use lambda_calculus::{
-        app, data::boolean::tru, data::num::church::add, reduction::Order::HNO, IntoChurchNum,
+        app,
+        data::boolean::tru,
+        data::num::church::{add, fac, mul, succ},
+        parse,
+        reduction::Order::HNO,
+        term::Notation::Classic,
+        IntoChurchNum,
     };
 
     use crate::experiments::magic_test_function::{addtwo, test_addtwo, test_succ};
 
-    use super::test_add;
+    use super::{
+        compose, test_add, test_compose, test_compose_seq, test_factorial, test_factorial_seq,
+        test_mul, test_mul_seq,
+    };
 
     #[test]
     fn add_test_reduces() {
@@ -384,6 +623,37 @@ mod tests {
         assert!(comp.is_isomorphic_to(&tru()))
     }
 
+    #[test]
+    fn mul_test_reduces() {
+        let mul_test = test_mul(3, 4);
+        let mut comp = app!(mul_test, mul());
+        comp.reduce(HNO, 0);
+        assert!(comp.is_isomorphic_to(&tru()));
+
+        // `test_mul_seq` checks itself against `mul`
+        test_mul_seq([(2, 3), (0, 5)].into_iter());
+    }
+
+    #[test]
+    fn compose_test_reduces() {
+        let double = app!(mul(), 2.into_church());
+        let compose_test = test_compose(succ(), double.clone(), 3);
+        let mut comp = app!(compose_test.clone(), compose());
+        comp.reduce(HNO, 0);
+        assert!(comp.is_isomorphic_to(&tru()));
+
+        // Composition in the wrong order is not composition
+        let mut comp = app!(
+            compose_test,
+            parse(r"\f. \g. \x. g (f x)", Classic).unwrap()
+        );
+        comp.reduce(HNO, 0);
+        assert!(!comp.is_isomorphic_to(&tru()));
+
+        // `test_compose_seq` checks itself against `compose`
+        test_compose_seq([(succ(), double.clone(), 1), (double, addtwo(), 0)].into_iter());
+    }
+
     #[test]
     fn addtwo_test_reduces() {
         let add_test = test_addtwo(3);
@@ -391,4 +661,19 @@ mod tests {
         comp.reduce(HNO, 0);
         assert!(comp.is_isomorphic_to(&tru()))
     }
+
+    #[test]
+    fn factorial_test_reduces() {
+        let fact_test = test_factorial(3);
+        let mut comp = app!(fact_test.clone(), fac());
+        comp.reduce(HNO, 0);
+        assert!(comp.is_isomorphic_to(&tru()));
+
+        let mut comp = app!(fact_test, succ());
+        comp.reduce(HNO, 0);
+        assert!(!comp.is_isomorphic_to(&tru()));
+
+        // `test_factorial_seq` checks itself against `fac`
+        test_factorial_seq([0, 1, 4].into_iter());
+    }
 }
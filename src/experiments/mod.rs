@@ -9,3 +9,5 @@ pub mod distribution;
 pub mod kinetics;
 
 pub mod discovery;
+
+pub mod islands;
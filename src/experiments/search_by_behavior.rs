@@ -1,16 +1,16 @@
 use async_std::task::{block_on, spawn};
 use futures::{stream::FuturesUnordered, StreamExt};
-use lambda_calculus::{app, Term};
+use lambda_calculus::{app, Term, HAP};
 
 use crate::{
-    config::{self, ConfigSeed},
+    config::{self, ConfigSeed, GenConfig},
     generators::BTreeGen,
     lambda::recursive::{reduce_with_limit, LambdaSoup},
 };
 
 fn experiment_soup(seed: ConfigSeed) -> LambdaSoup {
     LambdaSoup::from_config(&config::Reactor {
-        rules: vec![String::from("\\x.\\y.x y")],
+        rules: vec![config::ReactionRule::new("\\x.\\y.x y")],
         discard_copy_actions: false,
         discard_identity: false,
         discard_free_variable_expressions: true,
@@ -19,19 +19,23 @@ fn experiment_soup(seed: ConfigSeed) -> LambdaSoup {
         reduction_cutoff: 8000,
         size_cutoff: 1000,
         seed,
+        ..config::Reactor::new()
     })
 }
 
 fn experiment_gen(seed: ConfigSeed) -> BTreeGen {
     BTreeGen::from_config(&config::BTreeGen {
-        size: 20,
-        freevar_generation_probability: 0.2,
-        standardization: crate::generators::Standardization::Prefix,
-        n_max_free_vars: 6,
         seed,
+        ..config::BTreeGen::new()
     })
 }
 
+fn all_normalize(terms: [&mut Term; 4]) -> bool {
+    terms
+        .into_iter()
+        .all(|t| reduce_with_limit(t, HAP, 512, 1024).is_normalized())
+}
+
 fn xorset_test(a: &Term, b: &Term) -> bool {
     if a.is_isomorphic_to(b) {
         return false;
@@ -42,10 +46,10 @@ fn xorset_test(a: &Term, b: &Term) -> bool {
     let mut ba = app(b.clone(), a.clone());
     let mut bb = app(b.clone(), b.clone());
 
-    let _ = reduce_with_limit(&mut aa, 512, 1024);
-    let _ = reduce_with_limit(&mut ba, 512, 1024);
-    let _ = reduce_with_limit(&mut ab, 512, 1024);
-    let _ = reduce_with_limit(&mut bb, 512, 1024);
+    // Partially reduced terms can match by accident, so only compare normal forms
+    if !all_normalize([&mut aa, &mut ab, &mut ba, &mut bb]) {
+        return false;
+    }
 
     aa.is_isomorphic_to(a)
         && ab.is_isomorphic_to(b)
@@ -63,10 +67,10 @@ fn not_xorset_test(a: &Term, b: &Term) -> bool {
     let mut ba = app(b.clone(), a.clone());
     let mut bb = app(b.clone(), b.clone());
 
-    let _ = reduce_with_limit(&mut aa, 512, 1024);
-    let _ = reduce_with_limit(&mut ba, 512, 1024);
-    let _ = reduce_with_limit(&mut ab, 512, 1024);
-    let _ = reduce_with_limit(&mut bb, 512, 1024);
+    // Partially reduced terms can match by accident, so only compare normal forms
+    if !all_normalize([&mut aa, &mut ab, &mut ba, &mut bb]) {
+        return false;
+    }
 
     aa.is_isomorphic_to(b)
         && ab.is_isomorphic_to(b)
@@ -74,23 +78,6 @@ fn not_xorset_test(a: &Term, b: &Term) -> bool {
         && bb.is_isomorphic_to(a)
 }
 
-fn pairwise_compare<F>(terms: &[Term], test: &F, symmetric: bool) -> Option<(Term, Term)>
-where
-    F: Fn(&Term, &Term) -> bool,
-{
-    for (i, t1) in terms.iter().enumerate() {
-        for (j, t2) in terms.iter().enumerate() {
-            if test(t1, t2) {
-                return Some((t1.clone(), t2.clone()));
-            }
-            if j >= i && symmetric {
-                break;
-            }
-        }
-    }
-    None
-}
-
 async fn test_and_search_for_function<F>(
     sample: impl Iterator<Item = Term>,
     id: usize,
@@ -105,8 +92,7 @@ where
     soup.add_lambda_expressions(sample);
     let check_series =
         soup.simulate_and_poll_with_killer(run_length, polling_interval, false, |s| {
-            let bests = s.k_most_frequent_exprs(10);
-            let pairs = pairwise_compare(&bests, &test, false);
+            let pairs = s.find_interacting_pair_in_top(10, &test, false);
             (pairs.clone(), pairs.is_some())
         });
     (id, check_series)
@@ -0,0 +1,176 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use lambda_calculus::{parse, Classic, Term};
+
+use crate::analysis::Histogram;
+use crate::lambda::recursive::LambdaSoup;
+
+/// Write a series of expression counts, such as the polls of `LambdaSoup::expression_counts`,
+/// as CSV. Each row is a term in `Classic` notation followed by its count at every poll, so
+/// columns are poll indices. Terms missing from a poll count as `0`. Rows are sorted by their
+/// notation, so the output does not depend on hash map iteration order.
+pub fn write_counts_csv<W: Write>(series: &[HashMap<Term, u32>], w: &mut W) -> io::Result<()> {
+    let mut rows = BTreeMap::<String, Vec<u32>>::new();
+    for (i, counts) in series.iter().enumerate() {
+        for (term, &count) in counts {
+            rows.entry(term.to_string())
+                .or_insert_with(|| vec![0; series.len()])[i] = count;
+        }
+    }
+
+    write!(w, "Term")?;
+    for i in 0..series.len() {
+        write!(w, ",{}", i)?;
+    }
+    writeln!(w)?;
+
+    for (term, counts) in rows {
+        write!(w, "{}", escape(&term))?;
+        for c in counts {
+            write!(w, ",{}", c)?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Write a series of histograms with the same edges, such as the polls of
+/// `LambdaSoup::size_distribution`, as CSV. Each row is a poll, and each column a bucket,
+/// headed by its label. Fails with `io::ErrorKind::InvalidInput`, before writing anything, if
+/// the histograms don't all have the same edges.
+pub fn write_histograms_csv<W: Write>(series: &[Histogram], w: &mut W) -> io::Result<()> {
+    let Some(first) = series.first() else {
+        return Ok(());
+    };
+    if series.iter().any(|h| h.edges() != first.edges()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "histograms must share their edges",
+        ));
+    }
+    write!(w, "Poll")?;
+    for label in first.labels() {
+        write!(w, ",{}", label)?;
+    }
+    writeln!(w)?;
+
+    for (i, histogram) in series.iter().enumerate() {
+        write!(w, "{}", i)?;
+        for c in histogram.counts() {
+            write!(w, ",{}", c)?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+impl LambdaSoup {
+    /// Write the expressions in the soup, one per line, in `Classic` notation.
+    pub fn expressions_to_csv(&self, writer: &mut dyn Write) -> io::Result<()> {
+        for expr in self.lambda_expressions() {
+            writeln!(writer, "{}", expr)?;
+        }
+        Ok(())
+    }
+
+    /// Add expressions read one per line, in `Classic` notation, as written by
+    /// `expressions_to_csv`. Blank lines are ignored, and lines that don't parse are skipped.
+    /// Returns the number of expressions added and the number of lines skipped, and fails only
+    /// if reading fails.
+    pub fn add_expressions_from_csv(
+        &mut self,
+        reader: &mut dyn Read,
+    ) -> io::Result<(usize, usize)> {
+        let mut terms = Vec::new();
+        let mut skipped = 0;
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            match parse(line, Classic) {
+                Ok(term) => terms.push(term),
+                Err(_) => skipped += 1,
+            }
+        }
+        let added = terms.len();
+        self.add_lambda_expressions(terms);
+        Ok((added, skipped))
+    }
+}
+
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use lambda_calculus::{parse, Classic, Term};
+
+    use super::{write_counts_csv, write_histograms_csv};
+    use crate::analysis::Histogram;
+    use crate::lambda::recursive::LambdaSoup;
+
+    fn term(s: &str) -> Term {
+        parse(s, Classic).unwrap()
+    }
+
+    #[test]
+    fn counts_are_pivoted_with_zeros() {
+        let (k, i) = (term(r"\x.\y.x"), term(r"\x.x"));
+        let series = [
+            HashMap::from([(k.clone(), 3)]),
+            HashMap::from([(k.clone(), 1), (i.clone(), 2)]),
+            HashMap::new(),
+        ];
+
+        let mut out = Vec::new();
+        write_counts_csv(&series, &mut out).unwrap();
+        let expected = format!("Term,0,1,2\n{},0,2,0\n{},3,1,0\n", i, k);
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn histograms_are_written_one_poll_per_row() {
+        let mut first = Histogram::new(vec![2, 4]);
+        first.add(3);
+        let mut second = first.clone();
+        second.add(10);
+
+        let mut out = Vec::new();
+        write_histograms_csv(&[first, second], &mut out).unwrap();
+        let expected = "Poll,..2,2..4,4..\n0,0,1,0\n1,0,1,1\n";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+        let mut out = Vec::new();
+        let mismatched = [Histogram::new(vec![2, 4]), Histogram::new(vec![2, 8])];
+        let error = write_histograms_csv(&mismatched, &mut out).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn expressions_round_trip_through_csv() {
+        let mut soup = LambdaSoup::new();
+        soup.add_lambda_expressions([r"\x.\y.x", r"\x.x x", r"\x.\y.\z.x z (y z)"].map(term));
+
+        let mut out = Vec::new();
+        soup.expressions_to_csv(&mut out).unwrap();
+        out.extend_from_slice(b"\n(\\x.\n");
+
+        let mut restored = LambdaSoup::new();
+        let counts = restored
+            .add_expressions_from_csv(&mut out.as_slice())
+            .unwrap();
+        assert_eq!(counts, (3, 1));
+        assert!(restored.expressions().eq(soup.expressions()));
+    }
+}
@@ -1,10 +1,19 @@
-use lambda_calculus::Term::{self, Abs};
-use rand::{seq::SliceRandom, Rng, SeedableRng};
+use std::collections::{BTreeSet, HashMap};
+
+use lambda_calculus::combinators::{I, K, S};
+use lambda_calculus::Term::{self, Abs, App};
+use lambda_calculus::NOR;
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    seq::SliceRandom,
+    Rng, SeedableRng,
+};
 use rand_chacha::ChaCha8Rng;
 use serde::{Deserialize, Serialize};
 
 use crate::config;
 use crate::config::GenConfig;
+use crate::lambda::recursive::{reduce_within, ReductionLimits};
 
 struct BTree {
     n: u32,
@@ -12,13 +21,122 @@ struct BTree {
     right: Option<Box<BTree>>,
 }
 
+/// How a generated term with free variables is closed.
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum Standardization {
+    /// Add abstractions at the root until the term is closed.
     Prefix,
+
+    /// Bind each free variable around the smallest subterm holding all of its occurrences.
     Postfix,
+
+    /// Leave the term as generated, free variables and all.
     None,
 }
 
+/// A distribution over the number of nodes in the binary trees of a `BTreeGen`. Every size it
+/// can draw must be at least one; configs that break this are rejected when they are parsed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SizeDistribution {
+    /// Always the given size.
+    Fixed(usize),
+
+    /// Uniform between `min` and `max`, inclusive.
+    Uniform { min: usize, max: usize },
+
+    /// Geometric over `1, 2, 3, ...`, with the given mean, which must be at least `1.0`.
+    Geometric { mean: f64 },
+
+    /// The given probability mass function over sizes `1..=N`. The weights don't need to sum to
+    /// one.
+    Custom(Vec<f64>),
+}
+
+impl SizeDistribution {
+    /// Check that the distribution can only draw sizes of at least one, and that its
+    /// parameters make sense, describing the problem if they don't.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        match self {
+            SizeDistribution::Fixed(0) => Err(String::from("fixed size must be at least 1")),
+            SizeDistribution::Uniform { min: 0, .. } => {
+                Err(String::from("uniform size minimum must be at least 1"))
+            }
+            SizeDistribution::Uniform { min, max } if min > max => Err(format!(
+                "uniform size minimum {min} is larger than its maximum {max}"
+            )),
+            SizeDistribution::Fixed(n) | SizeDistribution::Uniform { max: n, .. }
+                if u32::try_from(*n).is_err() =>
+            {
+                Err(format!(
+                    "size {n} is larger than the largest tree, {}",
+                    u32::MAX
+                ))
+            }
+            SizeDistribution::Geometric { mean } if mean.is_nan() || *mean < 1.0 => {
+                Err(format!("geometric size mean {mean} must be at least 1"))
+            }
+            SizeDistribution::Custom(pmf) if pmf.is_empty() => {
+                Err(String::from("custom size distribution has no weights"))
+            }
+            SizeDistribution::Custom(pmf) if pmf.iter().any(|w| !w.is_finite() || *w < 0.0) => Err(
+                String::from("custom size weights must be finite and not negative"),
+            ),
+            SizeDistribution::Custom(pmf) if pmf.iter().all(|w| *w == 0.0) => {
+                Err(String::from("custom size weights must not all be zero"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the distribution, and get ready to draw from it.
+    fn sampler(&self) -> Result<SizeSampler, String> {
+        self.validate()?;
+        let weights = match self {
+            SizeDistribution::Custom(pmf) => {
+                Some(WeightedIndex::new(pmf).map_err(|e| e.to_string())?)
+            }
+            _ => None,
+        };
+        Ok(SizeSampler {
+            distribution: self.clone(),
+            weights,
+        })
+    }
+}
+
+/// A checked `SizeDistribution`, with the weights of a `Custom` one indexed once rather than on
+/// every draw.
+struct SizeSampler {
+    distribution: SizeDistribution,
+    weights: Option<WeightedIndex<f64>>,
+}
+
+impl SizeSampler {
+    fn sample(&self, rng: &mut ChaCha8Rng) -> usize {
+        match &self.distribution {
+            SizeDistribution::Fixed(n) => *n,
+            SizeDistribution::Uniform { min, max } => rng.gen_range(*min..=*max),
+            SizeDistribution::Geometric { mean } => {
+                assert!(
+                    *mean >= 1.0,
+                    "mean of geometric distribution must be at least 1"
+                );
+                if *mean == 1.0 {
+                    return 1;
+                }
+                // Invert the CDF of the number of trials up to the first success
+                let p = 1.0 / mean;
+                let u: f64 = rng.gen();
+                1 + ((1.0 - u).ln() / (1.0 - p).ln()).floor() as usize
+            }
+            SizeDistribution::Custom(_) => {
+                let weights = self.weights.as_ref().expect("custom weights are indexed");
+                weights.sample(rng) + 1
+            }
+        }
+    }
+}
+
 impl BTree {
     fn new(n: u32) -> BTree {
         BTree {
@@ -28,6 +146,30 @@ impl BTree {
         }
     }
 
+    /// A tree of `n` nodes in which each internal node has one child, becoming an
+    /// abstraction, with probability `lambda_p`, and two children, becoming an application,
+    /// otherwise. A node with exactly one node below it is always an abstraction, and the
+    /// nodes below an application are split uniformly at random between its children.
+    fn with_lambda_probability(n: u32, lambda_p: f64, rng: &mut ChaCha8Rng) -> BTree {
+        let mut tree = BTree::new(n);
+        if n == 1 {
+            return tree;
+        }
+        let below = n - 1;
+        if below == 1 || rng.gen_bool(lambda_p) {
+            tree.left = Some(Box::new(BTree::with_lambda_probability(
+                below, lambda_p, rng,
+            )));
+        } else {
+            let left = rng.gen_range(1..below);
+            let lhs = BTree::with_lambda_probability(left, lambda_p, rng);
+            let rhs = BTree::with_lambda_probability(below - left, lambda_p, rng);
+            tree.left = Some(Box::new(lhs));
+            tree.right = Some(Box::new(rhs));
+        }
+        tree
+    }
+
     fn insert(&mut self, n: u32) {
         let child = BTree::new(n);
         match (&mut self.left, &mut self.right, n <= self.n) {
@@ -70,13 +212,162 @@ impl BTree {
     fn to_lambda(&self, rng: &mut ChaCha8Rng, freevar_p: f64, max_free_vars: u32) -> Term {
         self.to_lambda_h(rng, freevar_p, max_free_vars, 0)
     }
+
+    /// Like `to_lambda`, but only pick variables whose types are consistent with the rest of
+    /// the term, so that the term is simply typed. Returns `None` if some leaf has no such
+    /// variable to pick.
+    fn to_typed_lambda(
+        &self,
+        rng: &mut ChaCha8Rng,
+        freevar_p: f64,
+        max_free_vars: u32,
+    ) -> Option<Term> {
+        let mut typing = Typing::default();
+        let expected = typing.fresh();
+        self.to_typed_lambda_h(rng, freevar_p, max_free_vars, &mut typing, expected)
+    }
+
+    fn to_typed_lambda_h(
+        &self,
+        rng: &mut ChaCha8Rng,
+        freevar_p: f64,
+        max_free_vars: u32,
+        typing: &mut Typing,
+        expected: SimpleType,
+    ) -> Option<Term> {
+        let depth = typing.context.len();
+        match (&self.left, &self.right) {
+            (None, None) => {
+                if rng.gen_bool(freevar_p) || depth == 0 {
+                    let k = rng.gen_range(1..=max_free_vars) as usize;
+                    let ty = match typing.free.get(&k) {
+                        Some(ty) => ty.clone(),
+                        None => {
+                            let ty = typing.fresh();
+                            typing.free.insert(k, ty.clone());
+                            ty
+                        }
+                    };
+                    return typing.unify(&ty, &expected).then_some(Term::Var(depth + k));
+                }
+                let mut candidates = (1..=depth)
+                    .filter_map(|i| {
+                        let mut attempt = typing.clone();
+                        let ty = attempt.context[depth - i].clone();
+                        attempt.unify(&ty, &expected).then_some((i, attempt))
+                    })
+                    .collect::<Vec<_>>();
+                if candidates.is_empty() {
+                    return None;
+                }
+                let (i, attempt) = candidates.swap_remove(rng.gen_range(0..candidates.len()));
+                *typing = attempt;
+                Some(Term::Var(i))
+            }
+            (Some(t), None) | (None, Some(t)) => {
+                let (arg, ret) = (typing.fresh(), typing.fresh());
+                let arrow = SimpleType::Arrow(Box::new(arg.clone()), Box::new(ret.clone()));
+                if !typing.unify(&arrow, &expected) {
+                    return None;
+                }
+                typing.context.push(arg);
+                let body = t.to_typed_lambda_h(rng, freevar_p, max_free_vars, typing, ret);
+                typing.context.pop();
+                Some(Term::Abs(Box::new(body?)))
+            }
+            (Some(l), Some(r)) => {
+                let arg = typing.fresh();
+                let fun = SimpleType::Arrow(Box::new(arg.clone()), Box::new(expected));
+                let left = l.to_typed_lambda_h(rng, freevar_p, max_free_vars, typing, fun)?;
+                let right = r.to_typed_lambda_h(rng, freevar_p, max_free_vars, typing, arg)?;
+                Some(Term::App(Box::new((left, right))))
+            }
+        }
+    }
 }
 
+/// A simple type, built from type variables and arrows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SimpleType {
+    Var(usize),
+    Arrow(Box<SimpleType>, Box<SimpleType>),
+}
+
+/// The types assigned so far while generating a simply typed term: a substitution for type
+/// variables, solved by unification, and the types of the bound and free variables in scope.
+#[derive(Clone, Default)]
+struct Typing {
+    /// What each type variable stands for, if it has been solved
+    substitution: Vec<Option<SimpleType>>,
+
+    /// Types of the bound variables, innermost last
+    context: Vec<SimpleType>,
+
+    /// Types of the free variables, keyed by their index above the outermost binder
+    free: HashMap<usize, SimpleType>,
+}
+
+impl Typing {
+    fn fresh(&mut self) -> SimpleType {
+        self.substitution.push(None);
+        SimpleType::Var(self.substitution.len() - 1)
+    }
+
+    /// Follow solved type variables until reaching an arrow or an unsolved variable.
+    fn resolve(&self, ty: &SimpleType) -> SimpleType {
+        let mut ty = ty.clone();
+        while let SimpleType::Var(v) = ty {
+            match &self.substitution[v] {
+                Some(solved) => ty = solved.clone(),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    fn occurs(&self, v: usize, ty: &SimpleType) -> bool {
+        match self.resolve(ty) {
+            SimpleType::Var(w) => v == w,
+            SimpleType::Arrow(a, b) => self.occurs(v, &a) || self.occurs(v, &b),
+        }
+    }
+
+    /// Solve type variables so that `a` and `b` are the same type, if possible. On failure,
+    /// some variables may have been solved already, so callers that want to try something
+    /// else should unify a clone.
+    fn unify(&mut self, a: &SimpleType, b: &SimpleType) -> bool {
+        match (self.resolve(a), self.resolve(b)) {
+            (SimpleType::Var(v), SimpleType::Var(w)) if v == w => true,
+            (SimpleType::Var(v), ty) | (ty, SimpleType::Var(v)) => {
+                if self.occurs(v, &ty) {
+                    return false;
+                }
+                self.substitution[v] = Some(ty);
+                true
+            }
+            (SimpleType::Arrow(a1, b1), SimpleType::Arrow(a2, b2)) => {
+                self.unify(&a1, &a2) && self.unify(&b1, &b2)
+            }
+        }
+    }
+}
+
+/// How many candidate terms in a row a `BTreeGen` throws away, for being open or for not
+/// normalizing, before giving up. `generate_n(n)` thus makes at most this many times `n`
+/// candidates.
+const MAX_ATTEMPTS_PER_TERM: usize = 10_000;
+
 pub struct BTreeGen {
     n: u32,
+    sizes: Option<SizeSampler>,
     freevar_p: f64,
     max_free_vars: u32,
+    lambda_p: Option<f64>,
     std: Standardization,
+    require_closed: bool,
+    normal_form_limits: Option<ReductionLimits>,
+    typed: bool,
+    regenerations: usize,
 
     seed: [u8; 32],
     rng: ChaCha8Rng,
@@ -87,52 +378,139 @@ impl BTreeGen {
         BTreeGen::from_config(&config::BTreeGen::new())
     }
 
+    /// Build a generator from `cfg`. Panics if its size distribution doesn't pass
+    /// `SizeDistribution::validate`, which parsed configs always do.
     pub fn from_config(cfg: &config::BTreeGen) -> BTreeGen {
         let seed = cfg.seed.get();
         let rng = ChaCha8Rng::from_seed(seed);
+        let sizes = cfg.size_distribution.as_ref().map(|dist| {
+            dist.sampler()
+                .unwrap_or_else(|e| panic!("bad size distribution {dist:?}: {e}"))
+        });
         BTreeGen {
             n: cfg.size,
+            sizes,
             freevar_p: cfg.freevar_generation_probability,
             max_free_vars: cfg.n_max_free_vars,
+            lambda_p: cfg.lambda_probability,
             std: cfg.standardization,
+            require_closed: cfg.require_closed,
+            normal_form_limits: cfg.normal_form_only.then(|| {
+                ReductionLimits::new(NOR, cfg.normal_form_step_budget, cfg.normal_form_size_limit)
+            }),
+            typed: cfg.typed,
+            regenerations: 0,
 
             seed,
             rng,
         }
     }
 
+    /// Generate a term from a random binary tree. The number of nodes in the tree is drawn from
+    /// the size distribution, if there is one. If the generator requires closed terms, open
+    /// ones are thrown away until a closed one comes up; likewise for terms that don't
+    /// normalize, if it requires normal forms. A typed generator also starts over whenever it
+    /// cannot pick a variable of the right type. Returns `None` after `MAX_ATTEMPTS_PER_TERM`
+    /// rejections in a row, which means the requirements are too strict for the rest of the
+    /// config.
+    pub fn try_generate(&mut self) -> Option<Term> {
+        for _ in 0..MAX_ATTEMPTS_PER_TERM {
+            if let Some(lambda) = self.generate_any().and_then(|c| self.accept(c)) {
+                return Some(lambda);
+            }
+            self.regenerations += 1;
+        }
+        None
+    }
+
+    /// Like `try_generate`, but panics if the generator gives up.
     pub fn generate(&mut self) -> Term {
-        let n = self.n;
+        self.try_generate()
+            .expect("the generator config is too strict for any term to pass")
+    }
+
+    /// The term to emit for a `candidate`, or `None` if it should be thrown away.
+    fn accept(&self, mut candidate: Term) -> Option<Term> {
+        if self.require_closed && candidate.has_free_variables() {
+            return None;
+        }
+        if let Some(limits) = self.normal_form_limits {
+            if !reduce_within(&mut candidate, &limits).is_normalized() {
+                return None;
+            }
+        }
+        Some(candidate)
+    }
+
+    fn generate_any(&mut self) -> Option<Term> {
+        let n = match &self.sizes {
+            Some(sizes) => {
+                let size = sizes.sample(&mut self.rng);
+                u32::try_from(size)
+                    .unwrap_or_else(|_| panic!("drawn size {size} is larger than the largest tree"))
+            }
+            None => self.n,
+        };
         assert!(
             n > 0,
             "btree generator does not produce zero-sized expressions."
         );
-        let mut permutation = (0..n).collect::<Vec<u32>>();
-        permutation.shuffle(&mut self.rng);
-        let mut tree = BTree::new(permutation[0]);
-        permutation.iter().skip(1).for_each(|i| tree.insert(*i));
-        let lambda = tree.to_lambda(&mut self.rng, self.freevar_p, self.max_free_vars);
-        match self.std {
+        let tree = match self.lambda_p {
+            Some(p) => BTree::with_lambda_probability(n, p, &mut self.rng),
+            None => {
+                let mut permutation = (0..n).collect::<Vec<u32>>();
+                permutation.shuffle(&mut self.rng);
+                let mut tree = BTree::new(permutation[0]);
+                permutation.iter().skip(1).for_each(|i| tree.insert(*i));
+                tree
+            }
+        };
+        let lambda = if self.typed {
+            tree.to_typed_lambda(&mut self.rng, self.freevar_p, self.max_free_vars)?
+        } else {
+            tree.to_lambda(&mut self.rng, self.freevar_p, self.max_free_vars)
+        };
+        Some(match self.std {
             Standardization::Postfix => BTreeGen::postfix_standardize(lambda),
             Standardization::Prefix => BTreeGen::prefix_standardize(lambda),
             Standardization::None => lambda,
-        }
+        })
     }
 
+    /// Generate exactly `n` terms. Panics if the generator gives up; see `generate`.
     pub fn generate_n(&mut self, n: usize) -> Vec<Term> {
-        let mut v = Vec::with_capacity(n);
-        for _ in 0..n {
-            v.push(self.generate())
-        }
-        v
+        (0..n).map(|_| self.generate()).collect()
+    }
+
+    /// A stream of generated terms, for `take`, `take_while` and the like without collecting a
+    /// `Vec` first. Every term drawn from the stream advances the generator's RNG, so terms
+    /// drawn afterwards from the generator itself continue where the stream stopped. The stream
+    /// ends if the generator gives up; see `try_generate`. The stream borrows the generator;
+    /// `BTreeGen` is itself an iterator if it can be moved.
+    pub fn generate_stream(&mut self) -> impl Iterator<Item = Term> + '_ {
+        std::iter::from_fn(move || self.try_generate())
     }
 
     pub fn seed(&self) -> [u8; 32] {
         self.seed
     }
 
-    fn postfix_standardize(_t: Term) -> Term {
-        unimplemented!("Postfix standiardization is unimplimented!!!!");
+    /// The number of terms thrown away so far for being open, not normalizing or, for a typed
+    /// generator, running out of variables of the right type.
+    pub fn regenerations(&self) -> usize {
+        self.regenerations
+    }
+
+    /// Bind each free variable with an abstraction around the smallest subterm that holds
+    /// every occurrence of it. Where prefix standardization puts the new abstractions at the
+    /// root of the expression, this pushes them as far towards the leaves as they can go.
+    fn postfix_standardize(mut t: Term) -> Term {
+        let mut names = BTreeSet::new();
+        free_names(&t, 0, &mut names);
+        for name in names {
+            t = bind_innermost(t, name, 0);
+        }
+        t
     }
 
     /// Add abstractions until the expression has no free variables
@@ -146,6 +524,155 @@ impl BTreeGen {
     }
 }
 
+/// Collect the free variables of `t`, under `depth` abstractions, numbered from `1` outside
+/// the outermost abstraction.
+fn free_names(t: &Term, depth: usize, names: &mut BTreeSet<usize>) {
+    match t {
+        Term::Var(i) if *i > depth => {
+            names.insert(i - depth);
+        }
+        Term::Var(_) => {}
+        Abs(body) => free_names(body, depth + 1, names),
+        App(boxed) => {
+            free_names(&boxed.0, depth, names);
+            free_names(&boxed.1, depth, names);
+        }
+    }
+}
+
+fn occurs_free(t: &Term, name: usize, depth: usize) -> bool {
+    match t {
+        Term::Var(i) => *i == depth + name,
+        Abs(body) => occurs_free(body, name, depth + 1),
+        App(boxed) => occurs_free(&boxed.0, name, depth) || occurs_free(&boxed.1, name, depth),
+    }
+}
+
+/// Abstract the free variable `name` of `t`, which sits under `depth` abstractions, over the
+/// smallest subterm that holds every occurrence of it.
+fn bind_innermost(t: Term, name: usize, depth: usize) -> Term {
+    match t {
+        Abs(body) => Abs(Box::new(bind_innermost(*body, name, depth + 1))),
+        App(boxed) => {
+            let (lhs, rhs) = *boxed;
+            match (
+                occurs_free(&lhs, name, depth),
+                occurs_free(&rhs, name, depth),
+            ) {
+                (true, false) => App(Box::new((bind_innermost(lhs, name, depth), rhs))),
+                (false, true) => App(Box::new((lhs, bind_innermost(rhs, name, depth)))),
+                _ => Abs(Box::new(rebind(App(Box::new((lhs, rhs))), depth + name, 0))),
+            }
+        }
+        var => Abs(Box::new(rebind(var, depth + name, 0))),
+    }
+}
+
+/// Rewrite `t` to sit under one more abstraction, which binds the free variable with index
+/// `target` just outside `t`. `depth` counts the abstractions inside `t` passed so far.
+fn rebind(t: Term, target: usize, depth: usize) -> Term {
+    match t {
+        Term::Var(i) if i <= depth => Term::Var(i),
+        Term::Var(i) if i - depth == target => Term::Var(depth + 1),
+        Term::Var(i) => Term::Var(i + 1),
+        Abs(body) => Abs(Box::new(rebind(*body, target, depth + 1))),
+        App(boxed) => {
+            let (lhs, rhs) = *boxed;
+            App(Box::new((
+                rebind(lhs, target, depth),
+                rebind(rhs, target, depth),
+            )))
+        }
+    }
+}
+
+/// A stream of generated terms, so a `BTreeGen` can be used with iterator adapters, e.g.
+/// `gen.take(10000)` or `gen.filter(|t| !t.has_free_variables())`. The stream ends, returning
+/// `None`, if the generator gives up, which only happens when `try_generate` throws away
+/// `MAX_ATTEMPTS_PER_TERM` candidates in a row; don't rely on it being infinite.
+impl Iterator for BTreeGen {
+    type Item = Term;
+
+    fn next(&mut self) -> Option<Term> {
+        self.try_generate()
+    }
+}
+
+/// Generates random applicative combinations of the `S`, `K` and `I` combinators. Every
+/// generated term is closed.
+pub struct SKIGen {
+    max_size: u32,
+    combinators: WeightedIndex<f64>,
+
+    seed: [u8; 32],
+    rng: ChaCha8Rng,
+}
+
+impl SKIGen {
+    pub fn new() -> SKIGen {
+        SKIGen::from_config(&config::SKIGen::new())
+    }
+
+    /// Build a generator from `cfg`. Panics if `cfg` does not pass `config::SKIGen::validate`,
+    /// which parsed configs always do.
+    pub fn from_config(cfg: &config::SKIGen) -> SKIGen {
+        cfg.validate().unwrap_or_else(|e| panic!("{e}"));
+        let seed = cfg.seed.get();
+        let rng = ChaCha8Rng::from_seed(seed);
+        SKIGen {
+            max_size: cfg.max_size,
+            combinators: WeightedIndex::new([cfg.s_weight, cfg.k_weight, cfg.i_weight])
+                .expect("combinator weights are validated"),
+
+            seed,
+            rng,
+        }
+    }
+
+    /// Generate a term of between one and `max_size` combinators, applied to each other in a
+    /// random binary tree.
+    pub fn generate(&mut self) -> Term {
+        let n = self.rng.gen_range(1..=self.max_size);
+        self.generate_h(n)
+    }
+
+    fn generate_h(&mut self, n: u32) -> Term {
+        if n == 1 {
+            return match self.combinators.sample(&mut self.rng) {
+                0 => S(),
+                1 => K(),
+                _ => I(),
+            };
+        }
+        let left = self.rng.gen_range(1..n);
+        let lhs = self.generate_h(left);
+        let rhs = self.generate_h(n - left);
+        App(Box::new((lhs, rhs)))
+    }
+
+    pub fn generate_n(&mut self, n: usize) -> Vec<Term> {
+        self.take(n).collect()
+    }
+
+    pub fn seed(&self) -> [u8; 32] {
+        self.seed
+    }
+}
+
+impl Default for SKIGen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Iterator for SKIGen {
+    type Item = Term;
+
+    fn next(&mut self) -> Option<Term> {
+        Some(self.generate())
+    }
+}
+
 pub struct FontanaGen {
     abs_range: (f64, f64),
     app_range: (f64, f64),
@@ -175,3 +702,243 @@ impl FontanaGen {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{abs, app, Term, Var, NOR};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::{BTreeGen, SKIGen, SizeDistribution, Standardization};
+    use crate::config::{self, ConfigSeed, GenConfig};
+
+    #[test]
+    fn stream_continues_where_it_stopped() {
+        let mut gen = standardized_gen(Standardization::Prefix);
+        let mut streamed = gen.generate_stream().take(50).collect::<Vec<_>>();
+        streamed.extend(gen.generate_n(50));
+        assert_eq!(
+            streamed,
+            standardized_gen(Standardization::Prefix).generate_n(100)
+        );
+    }
+
+    #[test]
+    fn ski_terms_are_closed_and_normalize() {
+        let mut gen = SKIGen::from_config(&config::SKIGen {
+            seed: ConfigSeed::new([0; 32]),
+            max_size: 5,
+            ..config::SKIGen::new()
+        });
+        for mut term in gen.generate_n(1000) {
+            assert!(!term.has_free_variables());
+            assert!(term.reduce(NOR, 100) < 100, "{} does not normalize", term);
+        }
+    }
+
+    #[test]
+    fn closed_btree_terms_are_regenerated_not_bound() {
+        let mut gen = BTreeGen::from_config(&config::BTreeGen {
+            seed: ConfigSeed::new([0; 32]),
+            size: 6,
+            freevar_generation_probability: 0.3,
+            standardization: Standardization::None,
+            require_closed: true,
+            ..config::BTreeGen::new()
+        });
+        let terms = gen.generate_n(200);
+        assert!(terms.iter().all(|t| !t.has_free_variables()));
+        assert!(gen.regenerations() > 0);
+    }
+
+    #[test]
+    fn normal_form_only_emits_normal_forms() {
+        let mut gen = BTreeGen::from_config(&config::BTreeGen {
+            seed: ConfigSeed::new([0; 32]),
+            size: 12,
+            normal_form_only: true,
+            normal_form_step_budget: 50,
+            ..config::BTreeGen::new()
+        });
+        for mut term in gen.generate_n(200) {
+            assert_eq!(term.reduce(NOR, 0), 0, "{} is not in normal form", term);
+        }
+    }
+
+    #[test]
+    fn normal_form_only_throws_away_growing_terms() {
+        let gen = BTreeGen::from_config(&config::BTreeGen {
+            seed: ConfigSeed::new([0; 32]),
+            normal_form_only: true,
+            normal_form_step_budget: 100_000,
+            normal_form_size_limit: 100,
+            ..config::BTreeGen::new()
+        });
+        let triple = abs(app(app(Var(1), Var(1)), Var(1)));
+        assert_eq!(gen.accept(app(triple.clone(), triple)), None);
+    }
+
+    #[test]
+    fn typed_terms_are_strongly_normalizing() {
+        let mut gen = BTreeGen::from_config(&config::BTreeGen {
+            seed: ConfigSeed::new([0; 32]),
+            size: 15,
+            typed: true,
+            ..config::BTreeGen::new()
+        });
+        for mut term in gen.generate_n(500) {
+            assert!(!term.has_free_variables());
+            assert!(
+                term.reduce(NOR, 10000) < 10000,
+                "{} does not normalize",
+                term
+            );
+        }
+    }
+
+    fn standardized_gen(standardization: Standardization) -> BTreeGen {
+        BTreeGen::from_config(&config::BTreeGen {
+            seed: ConfigSeed::new([0; 32]),
+            size: 10,
+            freevar_generation_probability: 0.5,
+            standardization,
+            ..config::BTreeGen::new()
+        })
+    }
+
+    #[test]
+    fn prefix_standardization_binds_at_the_root() {
+        // (\x.x) y
+        let open = app(abs(Var(1)), Var(1));
+        let closed = BTreeGen::prefix_standardize(open.clone());
+        assert_eq!(closed, abs(open));
+        assert!(standardized_gen(Standardization::Prefix)
+            .generate_n(200)
+            .iter()
+            .all(|t| !t.has_free_variables()));
+    }
+
+    #[test]
+    fn postfix_standardization_binds_at_the_leaves() {
+        // (\x.x) y becomes (\x.x) (\y.y)
+        let open = app(abs(Var(1)), Var(1));
+        let closed = BTreeGen::postfix_standardize(open);
+        assert_eq!(closed, app(abs(Var(1)), abs(Var(1))));
+
+        // \z.y (z y) w becomes \z.(\y.y (z y)) (\w.w)
+        let open = abs(app(app(Var(2), app(Var(1), Var(2))), Var(3)));
+        let closed = BTreeGen::postfix_standardize(open);
+        let expected = abs(app(abs(app(Var(1), app(Var(2), Var(1)))), abs(Var(1))));
+        assert_eq!(closed, expected);
+
+        for t in standardized_gen(Standardization::Postfix).generate_n(200) {
+            assert!(!t.has_free_variables());
+        }
+    }
+
+    #[test]
+    fn impossible_requirements_end_generation() {
+        let mut gen = BTreeGen::from_config(&config::BTreeGen {
+            seed: ConfigSeed::new([0; 32]),
+            freevar_generation_probability: 1.0,
+            standardization: Standardization::None,
+            require_closed: true,
+            ..config::BTreeGen::new()
+        });
+        assert_eq!(gen.try_generate(), None);
+        assert_eq!(gen.next(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "too strict")]
+    fn generate_n_panics_rather_than_return_fewer_terms() {
+        BTreeGen::from_config(&config::BTreeGen {
+            seed: ConfigSeed::new([0; 32]),
+            freevar_generation_probability: 1.0,
+            standardization: Standardization::None,
+            require_closed: true,
+            ..config::BTreeGen::new()
+        })
+        .generate_n(3);
+    }
+
+    #[test]
+    fn no_standardization_keeps_free_variables() {
+        let mut gen = BTreeGen::from_config(&config::BTreeGen {
+            seed: ConfigSeed::new([0; 32]),
+            freevar_generation_probability: 1.0,
+            standardization: Standardization::None,
+            ..config::BTreeGen::new()
+        });
+        assert!(gen.generate_n(200).iter().all(|t| t.has_free_variables()));
+    }
+
+    /// The numbers of abstractions and applications in `t`.
+    fn internal_nodes(t: &Term) -> (usize, usize) {
+        match t {
+            Term::Var(_) => (0, 0),
+            Term::Abs(body) => {
+                let (abs, app) = internal_nodes(body);
+                (abs + 1, app)
+            }
+            Term::App(boxed) => {
+                let (l_abs, l_app) = internal_nodes(&boxed.0);
+                let (r_abs, r_app) = internal_nodes(&boxed.1);
+                (l_abs + r_abs, l_app + r_app + 1)
+            }
+        }
+    }
+
+    #[test]
+    fn lambda_probability_shifts_abstractions_and_applications() {
+        let abstraction_share = |p| {
+            let mut gen = BTreeGen::from_config(&config::BTreeGen {
+                seed: ConfigSeed::new([0; 32]),
+                lambda_probability: Some(p),
+                standardization: Standardization::None,
+                ..config::BTreeGen::new()
+            });
+            let (abs, app) = gen
+                .generate_n(200)
+                .iter()
+                .map(internal_nodes)
+                .fold((0, 0), |(a, b), (c, d)| (a + c, b + d));
+            abs as f64 / (abs + app) as f64
+        };
+        assert_eq!(abstraction_share(1.0), 1.0);
+        assert!(abstraction_share(0.8) > 0.7);
+        assert!(abstraction_share(0.2) < 0.4);
+    }
+
+    #[test]
+    fn size_distributions_sample_their_support() {
+        let mut rng = ChaCha8Rng::from_seed([0; 32]);
+        let mut samples = |dist: SizeDistribution| {
+            let sampler = dist.sampler().unwrap();
+            (0..10000)
+                .map(|_| sampler.sample(&mut rng))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(samples(SizeDistribution::Fixed(7)).iter().all(|n| *n == 7));
+        let uniform = samples(SizeDistribution::Uniform { min: 3, max: 6 });
+        assert!(uniform.iter().all(|n| (3..=6).contains(n)));
+        assert!(samples(SizeDistribution::Custom(vec![0.0, 2.0, 0.0]))
+            .iter()
+            .all(|n| *n == 2));
+
+        let geometric = samples(SizeDistribution::Geometric { mean: 4.0 });
+        assert!(geometric.iter().all(|n| *n >= 1));
+        let mean = geometric.iter().sum::<usize>() as f64 / geometric.len() as f64;
+        assert!((mean - 4.0).abs() < 0.2);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad size distribution Custom([0.0, 0.0])")]
+    fn unchecked_size_distributions_are_rejected_when_the_generator_is_built() {
+        BTreeGen::from_config(&config::BTreeGen {
+            size_distribution: Some(SizeDistribution::Custom(vec![0.0, 0.0])),
+            ..config::BTreeGen::new()
+        });
+    }
+}
@@ -0,0 +1,70 @@
+//! One representative per alpha-equivalence class of terms. `Term` stores variables as de Bruijn
+//! indices, so two terms that differ only in the names of their bound variables parse to the
+//! same `Term`, and `==` and `Hash` on terms already agree with `is_isomorphic_to`. The functions
+//! here make that explicit, so code that groups terms by class says so, and keeps working if
+//! terms ever carry names.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use lambda_calculus::Term;
+
+/// The representative of the alpha-equivalence class of `term`. Two terms are isomorphic if
+/// and only if their canonical forms are equal. Since terms already use de Bruijn indices,
+/// this is just a clone: compare terms with `==` rather than canonicalizing both sides.
+pub fn canonical(term: &Term) -> Term {
+    term.clone()
+}
+
+/// A hash of the alpha-equivalence class of `term`, without building its canonical form.
+/// Isomorphic terms have the same hash. Hashes are only stable within a single build.
+pub fn canonical_hash(term: &Term) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hash_h(term, &mut hasher);
+    hasher.finish()
+}
+
+fn hash_h(term: &Term, hasher: &mut DefaultHasher) {
+    match term {
+        Term::Var(i) => (0u8, i).hash(hasher),
+        Term::Abs(body) => {
+            1u8.hash(hasher);
+            hash_h(body, hasher);
+        }
+        Term::App(boxed) => {
+            2u8.hash(hasher);
+            hash_h(&boxed.0, hasher);
+            hash_h(&boxed.1, hasher);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{parse, Classic, Term};
+
+    use super::{canonical, canonical_hash};
+
+    fn term(s: &str) -> Term {
+        parse(s, Classic).unwrap()
+    }
+
+    #[test]
+    fn alpha_variants_share_a_canonical_form() {
+        let pairs = [
+            (r"\x.\y.x", r"\a.\b.a"),
+            (r"\f.\x.f (f x)", r"\g.\y.g (g y)"),
+            (r"\m.\n.\f.\x.m f (n f x)", r"\a.\b.\c.\d.a c (b c d)"),
+        ];
+        for (a, b) in pairs {
+            assert_eq!(canonical(&term(a)), canonical(&term(b)));
+            assert_eq!(canonical_hash(&term(a)), canonical_hash(&term(b)));
+            assert!(term(a).is_isomorphic_to(&canonical(&term(b))));
+        }
+        assert_ne!(canonical(&term(r"\x.\y.x")), canonical(&term(r"\x.\y.y")));
+        assert_ne!(
+            canonical_hash(&term(r"\x.\y.x")),
+            canonical_hash(&term(r"\x.\y.y"))
+        );
+    }
+}
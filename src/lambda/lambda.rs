@@ -3,17 +3,22 @@ use std::fmt::{Debug, Display};
 use std::marker::PhantomData;
 
 use crate::config;
-use crate::supercollider::{Collider, Particle, Residue, Soup};
+use crate::lambda::recursive::term_size;
+use crate::lineage::{GenealogyTree, Lineage};
+use crate::supercollider::{
+    AgeBuffer, Collider, Particle, Population, ReactionTape, ReductionWork, Residue, Soup,
+};
+use lambda_calculus::reduction::Order;
 use lambda_calculus::Term::Var;
 use lambda_calculus::{abs, app, Term};
 
-use rand::{Rng, SeedableRng};
+use rand::SeedableRng;
 use rand_chacha::ChaCha8Rng;
 
 pub type LambdaSoup =
     Soup<LambdaParticle, AlchemyCollider, LambdaCollisionOk, LambdaCollisionError>;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct LambdaParticle {
     pub expr: Term,
 }
@@ -22,6 +27,7 @@ pub struct LambdaParticle {
 pub struct AlchemyCollider {
     rlimit: usize,
     slimit: usize,
+    reduction_order: Order,
     disallow_recursive: bool,
     reaction_rules: Vec<Term>,
     discard_copy_actions: bool,
@@ -86,19 +92,19 @@ pub fn is_truthy(expr: &Term) -> bool {
 
 pub fn reduce_with_limit(
     expr: &mut Term,
+    order: Order,
     rlimit: usize,
     slimit: usize,
 ) -> Result<usize, LambdaCollisionError> {
     let mut n = 0;
     for _ in 0..rlimit {
-        if expr.reduce(lambda_calculus::HAP, 1) == 0 {
+        if expr.reduce(order, 1) == 0 {
             break;
         }
 
-        // WARNING: This is EXTREMELY expensive. Calling max_depth is log(depth), and is done
-        // per reduction step. Remove when possible.
-        let depth = expr.size();
-        if depth > slimit {
+        // WARNING: This is EXTREMELY expensive. Computing the size walks the whole term, and is
+        // done per reduction step. Remove when possible.
+        if term_size(expr) > slimit {
             return Err(LambdaCollisionError::ExceedsDepthLimit);
         }
         n += 1;
@@ -111,11 +117,12 @@ impl AlchemyCollider {
         Self {
             rlimit: cfg.reduction_cutoff,
             slimit: cfg.size_cutoff,
+            reduction_order: cfg.reduction_order.0,
             disallow_recursive: false,
             reaction_rules: cfg
                 .rules
                 .iter()
-                .map(|r| lambda_calculus::parse(r, lambda_calculus::Classic).unwrap())
+                .map(|r| lambda_calculus::parse(&r.rule, lambda_calculus::Classic).unwrap())
                 .collect(),
             discard_copy_actions: cfg.discard_copy_actions,
             discard_identity: cfg.discard_identity,
@@ -125,17 +132,17 @@ impl AlchemyCollider {
 
     fn collide(
         &self,
-        left: LambdaParticle,
-        right: LambdaParticle,
+        left: &LambdaParticle,
+        right: &LambdaParticle,
     ) -> Result<LambdaCollisionOk, LambdaCollisionError> {
-        let lt = left.expr;
-        let rt = right.expr;
+        let lt = &left.expr;
+        let rt = &right.expr;
         let mut collision_results = Vec::with_capacity(self.reaction_rules.len());
 
         for rule in &self.reaction_rules {
             let mut expr = app!(rule.clone(), lt.clone(), rt.clone());
-            let n = reduce_with_limit(&mut expr, self.rlimit, self.slimit)?;
-            let size = expr.size();
+            let n = reduce_with_limit(&mut expr, self.reduction_order, self.rlimit, self.slimit)?;
+            let size = term_size(&expr);
 
             if n == self.rlimit {
                 return Err(LambdaCollisionError::ExceedsReductionLimit);
@@ -146,7 +153,7 @@ impl AlchemyCollider {
                 return Err(LambdaCollisionError::IsIdentity);
             }
 
-            let is_copy_action = expr.is_isomorphic_to(&lt) || expr.is_isomorphic_to(&rt);
+            let is_copy_action = expr.is_isomorphic_to(lt) || expr.is_isomorphic_to(rt);
             if is_copy_action && self.discard_copy_actions {
                 return Err(LambdaCollisionError::IsParent);
             }
@@ -163,8 +170,8 @@ impl AlchemyCollider {
             results: collision_results.iter().map(|t| t.0.clone()).collect(),
             reductions: collision_results.iter().map(|t| t.1).collect(),
             sizes: collision_results.iter().map(|t| t.2).collect(),
-            left_size: lt.size(),
-            right_size: rt.size(),
+            left_size: term_size(lt),
+            right_size: term_size(rt),
         })
     }
 }
@@ -179,17 +186,26 @@ impl Particle for LambdaParticle {
     fn is_isomorphic_to(&self, other: &Self) -> bool {
         self.expr.is_isomorphic_to(&other.expr)
     }
+
+    fn size(&self) -> usize {
+        term_size(&self.expr)
+    }
+
+    fn depth(&self) -> u32 {
+        self.expr.max_depth()
+    }
 }
 
 impl Collider<LambdaParticle, LambdaCollisionOk, LambdaCollisionError> for AlchemyCollider {
     /// Return the result of ((`rule` `left`) `right`), up to a limit of
     /// `self.reduction_limit`.
     fn collide(
-        &self,
-        left: LambdaParticle,
-        right: LambdaParticle,
+        &mut self,
+        left: &LambdaParticle,
+        right: &LambdaParticle,
+        _rng: &mut ChaCha8Rng,
     ) -> Result<LambdaCollisionOk, LambdaCollisionError> {
-        self.collide(left, right)
+        AlchemyCollider::collide(self, left, right)
     }
 }
 
@@ -201,6 +217,10 @@ impl Residue<LambdaParticle> for LambdaCollisionOk {
     fn count(&self) -> usize {
         self.results.len()
     }
+
+    fn reductions(&self) -> usize {
+        self.reductions.iter().sum()
+    }
 }
 
 impl fmt::Display for LambdaCollisionOk {
@@ -258,20 +278,37 @@ impl LambdaSoup {
         let seed = cfg.seed.get();
         let rng = ChaCha8Rng::from_seed(seed);
         Self {
-            expressions: Vec::new(),
+            expressions: Population::new(),
             collider: AlchemyCollider::from_config(cfg),
             maintain_constant_population_size: cfg.maintain_constant_population_size,
             discard_parents: cfg.discard_parents,
+            protect_new_products: cfg.protect_new_products,
+            require_distinct_reactants: cfg.require_distinct_reactants,
+            selection_strategy: cfg.selection_strategy,
+            cursor: 0,
+            eviction_policy: cfg.eviction_policy,
+            decay_probability: cfg.decay_probability,
+            max_population: cfg.max_population,
+            decayed: 0,
             rng,
-            n_collisions: 0,
+            total_collisions: 0,
+            successful_collisions: 0,
+            tape: cfg.record_tape.then(ReactionTape::new),
+            inflow: None,
+            schedule: Vec::new(),
+            convergence: None,
+            lineage: cfg.record_lineage.then(|| Lineage::new(cfg.lineage_depth)),
+            genealogy: cfg.record_genealogy.then(GenealogyTree::new),
+            removed_ages: AgeBuffer::new(cfg.removed_age_buffer),
+            since_poll: ReductionWork::default(),
+            next_id: 0,
             t: PhantomData,
             e: PhantomData,
         }
     }
 
     pub fn add_lambda_expressions(&mut self, expressions: impl IntoIterator<Item = Term>) {
-        self.expressions
-            .extend(expressions.into_iter().map(|t| LambdaParticle { expr: t }))
+        self.perturb(expressions.into_iter().map(|t| LambdaParticle { expr: t }))
     }
 
     pub fn perturb_lambda_expressions<I>(&mut self, nterms: usize, expressions: I)
@@ -279,17 +316,12 @@ impl LambdaSoup {
         I: IntoIterator<Item = Term>,
         <I as IntoIterator>::IntoIter: Clone,
     {
-        if self.maintain_constant_population_size {
-            for _ in 0..nterms {
-                let k = self.rng.gen_range(0..self.expressions.len());
-                self.expressions.swap_remove(k);
-            }
-        }
+        self.evict(nterms);
         self.add_lambda_expressions(expressions.into_iter().cycle().take(nterms))
     }
 
     pub fn lambda_expressions(&self) -> impl Iterator<Item = &Term> {
-        self.expressions.iter().map(|e| e.get_underlying_term())
+        self.expressions().map(|e| e.get_underlying_term())
     }
 
     pub fn population_of(&self, item: &Term) -> usize {
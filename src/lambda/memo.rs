@@ -0,0 +1,173 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+
+use lambda_calculus::Term;
+use serde::{Deserialize, Serialize};
+
+use crate::lambda::recursive::LambdaCollisionError;
+
+/// How often a collision cache found the reduction it was asked for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+/// The outcome of reducing a rule applied to two reactants: the normal form and the number of
+/// steps it took, or the reason the reduction was abandoned.
+pub(crate) type Reduction = Result<(Term, usize), LambdaCollisionError>;
+
+struct MemoEntry {
+    rule: usize,
+    left: Term,
+    right: Term,
+    reduction: Reduction,
+    last_used: u64,
+}
+
+/// A bounded cache of reductions, keyed by the index of the rule and both reactants, that
+/// forgets the least recently used entry when it is full. Terms are stored in de Bruijn form,
+/// so alpha-equivalent reactants share an entry. Only the reduction is cached: filters are
+/// applied to the cached normal form as if it had just been computed.
+///
+/// The entries are not saved with the soup; a restored soup starts with an empty cache.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct CollisionMemo {
+    capacity: usize,
+
+    #[serde(skip)]
+    entries: HashMap<u64, MemoEntry>,
+
+    /// Keys of `entries`, by the tick at which they were last used
+    #[serde(skip)]
+    recency: BTreeMap<u64, u64>,
+
+    #[serde(skip)]
+    tick: u64,
+
+    #[serde(skip)]
+    pub(crate) stats: CacheStats,
+}
+
+impl CollisionMemo {
+    pub(crate) fn new(capacity: usize) -> Self {
+        CollisionMemo {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn key(rule: usize, left: &Term, right: &Term) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (rule, left, right).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Forget every entry, keeping the statistics.
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    /// The cached reduction of `rule` applied to `left` and `right`, if there is one.
+    pub(crate) fn get(&mut self, rule: usize, left: &Term, right: &Term) -> Option<Reduction> {
+        let key = Self::key(rule, left, right);
+        let tick = self.tick;
+        let found = self
+            .entries
+            .get_mut(&key)
+            .filter(|e| e.rule == rule && e.left == *left && e.right == *right);
+        let Some(entry) = found else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.stats.hits += 1;
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(tick, key);
+        entry.last_used = tick;
+        self.tick += 1;
+        Some(entry.reduction.clone())
+    }
+
+    /// Remember a reduction, forgetting the least recently used one if the cache is full. An
+    /// entry whose key collides with that of another replaces it.
+    pub(crate) fn insert(&mut self, rule: usize, left: &Term, right: &Term, reduction: Reduction) {
+        if self.capacity == 0 {
+            return;
+        }
+        let key = Self::key(rule, left, right);
+        if let Some(old) = self.entries.remove(&key) {
+            self.recency.remove(&old.last_used);
+        } else if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        let entry = MemoEntry {
+            rule,
+            left: left.clone(),
+            right: right.clone(),
+            reduction,
+            last_used: self.tick,
+        };
+        self.entries.insert(key, entry);
+        self.recency.insert(self.tick, key);
+        self.tick += 1;
+    }
+}
+
+/// Clones start out empty: the entries only speed up reductions, and copying them would double
+/// the memory a cloned soup uses.
+impl Clone for CollisionMemo {
+    fn clone(&self) -> Self {
+        CollisionMemo {
+            stats: self.stats,
+            ..CollisionMemo::new(self.capacity)
+        }
+    }
+}
+
+impl std::fmt::Debug for CollisionMemo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CollisionMemo")
+            .field("capacity", &self.capacity)
+            .field("len", &self.entries.len())
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{parse, Classic, Term};
+
+    use super::CollisionMemo;
+
+    fn term(s: &str) -> Term {
+        parse(s, Classic).unwrap()
+    }
+
+    #[test]
+    fn least_recently_used_entry_is_forgotten() {
+        let (k, i, u) = (term(r"\x.\y.x"), term(r"\x.x"), term(r"\x.x x"));
+        let mut memo = CollisionMemo::new(2);
+        memo.insert(0, &k, &i, Ok((i.clone(), 1)));
+        memo.insert(0, &i, &k, Ok((k.clone(), 1)));
+        assert!(memo.get(0, &k, &i).is_some());
+        memo.insert(0, &u, &u, Ok((u.clone(), 1)));
+
+        assert_eq!(memo.entries.len(), 2);
+        assert!(memo.get(0, &i, &k).is_none());
+        assert_eq!(memo.get(0, &k, &i), Some(Ok((i.clone(), 1))));
+        assert!(memo.get(1, &k, &i).is_none());
+        assert_eq!((memo.stats.hits, memo.stats.misses), (2, 2));
+    }
+}
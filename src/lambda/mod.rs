@@ -1,5 +1,13 @@
+pub mod canonical;
+
 pub mod lambda;
 
+pub mod memo;
+
 pub mod recursive;
 
+pub mod serialize;
+
+pub mod snapshot;
+
 // pub mod extrinsic;
@@ -1,47 +1,256 @@
 use core::fmt;
 use std::fmt::{Debug, Display};
 use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
-use crate::config;
-use crate::supercollider::{Collider, Particle, Residue, Soup};
+use crate::config::{self, ConfigError, ConfigSeed, InvalidRule, ReductionOrder, RuleApplication};
+use crate::lambda::memo::{CacheStats, CollisionMemo, Reduction};
+use crate::lineage::{GenealogyTree, Lineage, LineageTree};
+use crate::supercollider::{
+    AgeBuffer, Collider, Inflow, Particle, Population, ReactionTape, ReductionWork, Residue, Soup,
+};
+use lambda_calculus::reduction::Order;
 use lambda_calculus::{abs, app, Term, Var};
 
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::IteratorRandom;
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 
 pub type LambdaSoup =
     Soup<LambdaParticle, AlchemyCollider, LambdaCollisionOk, LambdaCollisionError>;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// An expression in a `LambdaSoup`. Most particles are ordinary molecules, but a particle can
+/// also be a *test expression*: an oracle that maps a candidate function to a Church boolean,
+/// such as the terms built by `test_add`. Test expressions are the particles whose `recursive`
+/// tag is set, which only `LambdaSoup::add_test_expressions` does. They react differently from
+/// molecules: a test applied to a particle that passes it produces copies of that particle, and
+/// a molecule applied to a test fails with `LambdaCollisionError::RecursiveArgument`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct LambdaParticle {
+    #[serde(with = "crate::lambda::serialize")]
     pub expr: Term,
+
+    /// Set for test expressions
     recursive: bool,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AlchemyCollider {
     rlimit: usize,
     slimit: usize,
+    reduction_order: ReductionOrder,
+    timeout: Option<Duration>,
+    size_check_interval: usize,
     disallow_recursive: bool,
+    #[serde(with = "crate::lambda::serialize::vec")]
     reaction_rules: Vec<Term>,
+    rule_application: RuleApplication,
+
+    /// Draws a reaction rule by `config.rule_weights`, if they are set. Built with the
+    /// collider, and again on first use after it is deserialized or its rules change.
+    #[serde(skip)]
+    rule_index: OnceLock<Option<WeightedIndex<f64>>>,
+
+    /// Probability that each reaction rule is applied, one per rule
+    rule_probabilities: Vec<f64>,
+
+    /// Reduction order of each reaction rule, if it differs from `reduction_order`
+    rule_orders: Vec<Option<ReductionOrder>>,
     discard_copy_actions: bool,
-    discard_identity: bool,
-    discard_free_variable_expressions: bool,
+    filters: Vec<Filter>,
+
+    /// One entry per reaction rule
+    statistics: Vec<ReactionStats>,
+
+    /// The config the collider was made from, with the reaction rules kept up to date
+    #[serde(with = "crate::config::reactor_as_toml")]
+    config: config::Reactor,
+
+    /// Cache of reductions, if `collision_cache_size` is set
+    memo: Option<CollisionMemo>,
+}
+
+/// Colliders are equal if they are configured the same way. Their statistics are ignored.
+impl PartialEq for AlchemyCollider {
+    fn eq(&self, other: &Self) -> bool {
+        self.rlimit == other.rlimit
+            && self.slimit == other.slimit
+            && self.reduction_order == other.reduction_order
+            && self.timeout == other.timeout
+            && self.size_check_interval == other.size_check_interval
+            && self.disallow_recursive == other.disallow_recursive
+            && self.reaction_rules == other.reaction_rules
+            && self.rule_application == other.rule_application
+            && self.config.rule_weights == other.config.rule_weights
+            && self.rule_probabilities == other.rule_probabilities
+            && self.rule_orders == other.rule_orders
+            && self.discard_copy_actions == other.discard_copy_actions
+            && self.filters == other.filters
+            && self.memo.as_ref().map(|m| m.capacity()) == other.memo.as_ref().map(|m| m.capacity())
+    }
+}
+
+/// How often a reaction rule has been applied, and how those applications turned out. Every
+/// attempt is either a success or exactly one kind of failure.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReactionStats {
+    pub attempts: u64,
+    pub successes: u64,
+    pub failed_reduction_limit: u64,
+    pub failed_size_limit: u64,
+    pub failed_timeout: u64,
+    pub failed_identity: u64,
+    pub failed_copy: u64,
+    pub failed_free_vars: u64,
+
+    /// Products rejected by a filter other than `IsIdentity` and `HasFreeVariables`
+    pub failed_other_filter: u64,
+}
+
+impl ReactionStats {
+    fn record<T>(&mut self, result: &Result<T, LambdaCollisionError>) {
+        self.attempts += 1;
+        let counter = match result {
+            Ok(_) => &mut self.successes,
+            Err(LambdaCollisionError::ExceedsReductionLimit) => &mut self.failed_reduction_limit,
+            Err(LambdaCollisionError::ExceedsDepthLimit) => &mut self.failed_size_limit,
+            Err(LambdaCollisionError::Timeout) => &mut self.failed_timeout,
+            Err(LambdaCollisionError::IsIdentity) => &mut self.failed_identity,
+            Err(LambdaCollisionError::IsParent) => &mut self.failed_copy,
+            Err(LambdaCollisionError::HasFreeVariables) => &mut self.failed_free_vars,
+            Err(_) => &mut self.failed_other_filter,
+        };
+        *counter += 1;
+    }
+}
+
+/// A predicate on the products of reactions. A product is discarded if any one of the soup's
+/// filters matches it, so a product must pass all of them to enter the soup.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Filter {
+    /// Matches products with free variables.
+    HasFreeVariables,
+
+    /// Matches products isomorphic to the identity function: `\x.x`.
+    IsIdentity,
+
+    /// Matches products isomorphic to the given term.
+    IsCopyOf(#[serde(with = "crate::lambda::serialize")] Term),
+
+    /// Matches products with more nodes than the given limit.
+    SizeLargerThan(usize),
+
+    /// Matches products for which the function returns `true`. A soup with a custom filter
+    /// can't be saved to disk; `Soup::save_to_path` rejects it.
+    #[serde(skip)]
+    Custom(Arc<dyn Fn(&Term) -> bool + Send + Sync>),
+}
+
+impl Filter {
+    /// Wrap `f` in a `Filter::Custom`.
+    pub fn custom(f: impl Fn(&Term) -> bool + Send + Sync + 'static) -> Self {
+        Filter::Custom(Arc::new(f))
+    }
+
+    fn matches(&self, expr: &Term) -> bool {
+        match self {
+            Filter::HasFreeVariables => expr.has_free_variables(),
+            Filter::IsIdentity => expr.is_isomorphic_to(&abs(Var(1))),
+            Filter::IsCopyOf(t) => expr.is_isomorphic_to(t),
+            Filter::SizeLargerThan(limit) => term_size(expr) > *limit,
+            Filter::Custom(f) => f(expr),
+        }
+    }
+
+    fn error(&self) -> LambdaCollisionError {
+        match self {
+            Filter::HasFreeVariables => LambdaCollisionError::HasFreeVariables,
+            Filter::IsIdentity => LambdaCollisionError::IsIdentity,
+            _ => LambdaCollisionError::Filtered,
+        }
+    }
+}
+
+impl Debug for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::HasFreeVariables => write!(f, "HasFreeVariables"),
+            Filter::IsIdentity => write!(f, "IsIdentity"),
+            Filter::IsCopyOf(t) => write!(f, "IsCopyOf({:?})", t),
+            Filter::SizeLargerThan(limit) => write!(f, "SizeLargerThan({})", limit),
+            Filter::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// Custom filters are only equal if they share the same function.
+impl PartialEq for Filter {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Filter::HasFreeVariables, Filter::HasFreeVariables) => true,
+            (Filter::IsIdentity, Filter::IsIdentity) => true,
+            (Filter::IsCopyOf(a), Filter::IsCopyOf(b)) => a == b,
+            (Filter::SizeLargerThan(a), Filter::SizeLargerThan(b)) => a == b,
+            (Filter::Custom(a), Filter::Custom(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
 }
 
 /// The result of composing a vector `v` of 2-ary lambda expressions with
 /// the expressions A and B.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LambdaCollisionOk {
     pub results: Vec<LambdaParticle>,
+
+    /// Index of the reaction rule that produced each of the `results`. Empty when the left
+    /// expression is a test expression.
+    pub rules: Vec<usize>,
+
     pub reductions: Vec<usize>,
+
+    /// Size of each of the `results`, in nodes, as given by `term_size`
     pub sizes: Vec<usize>,
 
-    /// Size of A
+    /// Depth of each of the `results`, as given by `Term::max_depth`
+    pub depths: Vec<u32>,
+
+    /// Size of A, in nodes
     pub left_size: usize,
 
-    /// Size of B
+    /// Size of B, in nodes
     pub right_size: usize,
+
+    /// Depth of A
+    pub left_depth: u32,
+
+    /// Depth of B
+    pub right_depth: u32,
+}
+
+impl LambdaCollisionOk {
+    /// Each product of the collision, with the index of the reaction rule that made it. The
+    /// rule is `None` for the product of a test expression, which is not made by any rule.
+    pub fn products(&self) -> impl Iterator<Item = (Option<usize>, &Term)> {
+        self.results
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (self.rules.get(i).copied(), p.get_underlying_term()))
+    }
+}
+
+/// A product made by a single reaction rule, with the measurements that go into
+/// `LambdaCollisionOk`.
+struct RuleProduct {
+    rule: usize,
+    particle: LambdaParticle,
+    reductions: usize,
+    size: usize,
+    depth: u32,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -51,9 +260,25 @@ pub enum LambdaCollisionError {
     IsIdentity,
     IsParent,
     HasFreeVariables,
+
+    /// The term grew past the size limit during reduction
     ExceedsDepthLimit,
+
+    /// The reduction ran for longer than the collision timeout
+    Timeout,
     RecursiveArgument,
     BadArgument,
+    Filtered,
+
+    /// No reaction rule took part in the collision
+    NoRuleApplied,
+}
+
+/// Why two soups could not be merged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeError {
+    MismatchedReactionRules,
+    MismatchedConfig,
 }
 
 impl LambdaParticle {
@@ -61,6 +286,7 @@ impl LambdaParticle {
         &self.expr
     }
 
+    /// Whether this particle is a test expression rather than an ordinary molecule.
     pub fn is_recursive(&self) -> bool {
         self.recursive
     }
@@ -107,135 +333,439 @@ pub fn uses_both_arguments(expr: &Term) -> bool {
     left && right
 }
 
+/// The number of `Var`, `Abs` and `App` nodes in `term`. This is the measure of size used for
+/// size limits, collision statistics and `Particle::size` throughout the crate, in place of
+/// `Term::size`. Unlike `Term::max_depth`, it grows with the width of a term as well as its
+/// depth.
+pub fn term_size(term: &Term) -> usize {
+    match term {
+        Term::Var(_) => 1,
+        Term::Abs(body) => 1 + term_size(body),
+        Term::App(boxed) => 1 + term_size(&boxed.0) + term_size(&boxed.1),
+    }
+}
+
+/// Bounds on the reduction of a single term. Between checks, a term is reduced
+/// `check_interval` steps at a time, so it may overshoot the size limit by that many steps
+/// before reduction is abandoned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReductionLimits {
+    pub order: Order,
+
+    /// Most reduction steps to take
+    pub steps: usize,
+
+    /// Largest number of nodes the term may grow to
+    pub size: usize,
+
+    /// Number of steps between checks of the size limit and the timeout
+    pub check_interval: usize,
+
+    pub timeout: Option<Duration>,
+}
+
+impl ReductionLimits {
+    /// Limits that are checked after every step, with no timeout.
+    pub fn new(order: Order, steps: usize, size: usize) -> Self {
+        ReductionLimits {
+            order,
+            steps,
+            size,
+            check_interval: 1,
+            timeout: None,
+        }
+    }
+}
+
+/// How the reduction of a term ended. Every variant carries the number of steps taken; the
+/// failures also carry the size of the term, in nodes, when reduction stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReductionOutcome {
+    /// The term reached a normal form
+    Normalized { steps: usize },
+
+    /// The term took every step it was allowed without reaching a normal form
+    StepLimit { steps: usize, size: usize },
+
+    /// The term grew past the size limit
+    SizeLimit { steps: usize, size: usize },
+
+    /// Reduction ran for longer than the timeout
+    Timeout { steps: usize, size: usize },
+}
+
+impl ReductionOutcome {
+    pub fn steps(&self) -> usize {
+        match *self {
+            ReductionOutcome::Normalized { steps }
+            | ReductionOutcome::StepLimit { steps, .. }
+            | ReductionOutcome::SizeLimit { steps, .. }
+            | ReductionOutcome::Timeout { steps, .. } => steps,
+        }
+    }
+
+    pub fn is_normalized(&self) -> bool {
+        matches!(self, ReductionOutcome::Normalized { .. })
+    }
+
+    /// The number of steps taken, or the collision error for a term that grew too large or
+    /// took too long. Running out of steps is not an error here.
+    fn steps_or_error(self) -> Result<usize, LambdaCollisionError> {
+        match self {
+            ReductionOutcome::Normalized { steps } | ReductionOutcome::StepLimit { steps, .. } => {
+                Ok(steps)
+            }
+            ReductionOutcome::SizeLimit { .. } => Err(LambdaCollisionError::ExceedsDepthLimit),
+            ReductionOutcome::Timeout { .. } => Err(LambdaCollisionError::Timeout),
+        }
+    }
+}
+
+/// Reduce `expr` for at most `rlimit` steps, giving up once it grows past `slimit` nodes.
 pub fn reduce_with_limit(
     expr: &mut Term,
+    order: Order,
     rlimit: usize,
     slimit: usize,
-) -> Result<usize, LambdaCollisionError> {
+) -> ReductionOutcome {
+    reduce_within(expr, &ReductionLimits::new(order, rlimit, slimit))
+}
+
+/// Reduce `expr` in chunks of `limits.check_interval` steps, stopping as soon as the term is
+/// seen to be larger than `limits.size`, or once `limits.timeout` has passed.
+pub fn reduce_within(expr: &mut Term, limits: &ReductionLimits) -> ReductionOutcome {
+    let deadline = limits.timeout.map(|t| Instant::now() + t);
+    let chunk = limits.check_interval.max(1);
     let mut n = 0;
-    for _ in 0..rlimit {
-        if expr.reduce(lambda_calculus::HAP, 1) == 0 {
+    while n < limits.steps {
+        // `reduce` treats a limit of zero as no limit, but `wanted` is never zero here
+        let wanted = chunk.min(limits.steps - n);
+        let steps = expr.reduce(limits.order, wanted);
+        if steps == 0 {
             break;
         }
+        n += steps;
 
-        // WARNING: This is EXTREMELY expensive. Calling max_depth is log(depth), and is done
-        // per reduction step. Remove when possible.
-        let depth = expr.size();
-        if depth > slimit {
-            return Err(LambdaCollisionError::ExceedsDepthLimit);
+        // WARNING: Computing the size walks the whole term, so checking it after every step
+        // is expensive. Raise `check_interval` to check less often.
+        let size = term_size(expr);
+        if size > limits.size {
+            return ReductionOutcome::SizeLimit { steps: n, size };
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return ReductionOutcome::Timeout { steps: n, size };
+        }
+        if steps < wanted {
+            break;
         }
-        n += 1;
     }
-    Ok(n)
+    // A term that used up every step may have reached its normal form on the last one
+    if n == limits.steps && expr.clone().reduce(limits.order, 1) > 0 {
+        let size = term_size(expr);
+        return ReductionOutcome::StepLimit { steps: n, size };
+    }
+    ReductionOutcome::Normalized { steps: n }
 }
 
 impl AlchemyCollider {
+    /// Build a collider from `cfg`. Panics if a reaction rule does not parse; see
+    /// `try_from_config`.
     pub fn from_config(cfg: &config::Reactor) -> Self {
-        Self {
+        Self::try_from_config(cfg).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Build a collider from `cfg`, or fail with every reaction rule that does not parse, or
+    /// with rule weights that don't fit the rules.
+    pub fn try_from_config(cfg: &config::Reactor) -> Result<Self, ConfigError> {
+        let reaction_rules = cfg.parse_rules()?;
+        if let Some(ref weights) = cfg.rule_weights {
+            let reason = if cfg.rule_application != RuleApplication::Random {
+                Some("weights are only used by `RuleApplication::Random`".to_string())
+            } else if weights.len() != cfg.rules.len() {
+                Some(format!(
+                    "{} weights for {} rules",
+                    weights.len(),
+                    cfg.rules.len()
+                ))
+            } else {
+                weights
+                    .iter()
+                    .position(|w| w.is_nan() || *w <= 0.0)
+                    .map(|i| format!("weight {} of rule {i} must be positive", weights[i]))
+            };
+            if let Some(reason) = reason {
+                return Err(ConfigError::InvalidRuleWeights(reason));
+            }
+        }
+        let mut filters = Vec::new();
+        if cfg.discard_identity {
+            filters.push(Filter::IsIdentity);
+        }
+        if cfg.discard_free_variable_expressions {
+            filters.push(Filter::HasFreeVariables);
+        }
+        Ok(Self {
             rlimit: cfg.reduction_cutoff,
             slimit: cfg.size_cutoff,
+            reduction_order: cfg.reduction_order,
+            timeout: cfg.collision_timeout_ms.map(Duration::from_millis),
+            size_check_interval: cfg.size_check_interval.unwrap_or(1),
             disallow_recursive: false,
-            reaction_rules: cfg
-                .rules
-                .iter()
-                .map(|r| lambda_calculus::parse(r, lambda_calculus::Classic).unwrap())
-                .collect(),
+            reaction_rules,
             discard_copy_actions: cfg.discard_copy_actions,
-            discard_identity: cfg.discard_identity,
-            discard_free_variable_expressions: cfg.discard_free_variable_expressions,
-        }
+            rule_application: cfg.rule_application,
+            rule_index: OnceLock::from(Self::weighted_index(&cfg.rule_weights)),
+            rule_probabilities: cfg.rules.iter().map(|r| r.probability).collect(),
+            rule_orders: cfg.rules.iter().map(|r| r.reduction_order).collect(),
+            filters,
+            statistics: vec![ReactionStats::default(); cfg.rules.len()],
+            memo: cfg.collision_cache_size.map(CollisionMemo::new),
+            config: cfg.clone(),
+        })
+    }
+
+    fn weighted_index(weights: &Option<Vec<f64>>) -> Option<WeightedIndex<f64>> {
+        weights.as_ref().map(|w| {
+            WeightedIndex::new(w).expect("rule weights are checked when the collider is built")
+        })
     }
 
     fn recursive_collide(
         &self,
-        left: LambdaParticle,
-        right: LambdaParticle,
+        left: &LambdaParticle,
+        right: &LambdaParticle,
     ) -> Result<LambdaCollisionOk, LambdaCollisionError> {
         assert!(left.recursive);
         let has_good_signature = uses_both_arguments(&right.expr) && has_two_args(&right.expr);
         if is_truthy(&right.expr) || !has_good_signature {
             return Err(LambdaCollisionError::BadArgument);
         }
-        let lt = left.expr.clone();
-        let left_size = lt.size();
-        let rt = right.expr.clone();
-        let right_size = rt.size();
-
-        let mut expr = app!(lt, rt.clone());
-        let n = reduce_with_limit(&mut expr, 32000, 16000)?;
-
-        if expr.is_isomorphic_to(&lambda_calculus::data::boolean::tru()) {
-            Ok(LambdaCollisionOk {
-                results: vec![right.clone(); 100],
-                reductions: vec![n],
-                sizes: vec![expr.size()],
-                left_size,
-                right_size,
-            })
+        let (lt, rt) = (&left.expr, &right.expr);
+        let (left_size, left_depth) = (term_size(lt), lt.max_depth());
+        let (right_size, right_depth) = (term_size(rt), rt.max_depth());
+
+        let mut expr = app!(lt.clone(), rt.clone());
+        let n = reduce_within(&mut expr, &self.limits(32000, 16000)).steps_or_error()?;
+
+        let results = if expr.is_isomorphic_to(&lambda_calculus::data::boolean::tru()) {
+            vec![right.clone(); 100]
         } else {
-            Ok(LambdaCollisionOk {
-                results: vec![left],
-                reductions: vec![n],
-                sizes: vec![expr.size()],
-                left_size,
-                right_size,
-            })
+            vec![left.clone()]
+        };
+        Ok(LambdaCollisionOk {
+            results,
+            rules: Vec::new(),
+            reductions: vec![n],
+            sizes: vec![term_size(&expr)],
+            depths: vec![expr.max_depth()],
+            left_size,
+            right_size,
+            left_depth,
+            right_depth,
+        })
+    }
+
+    /// Reduce ((`rule` `lt`) `rt`), where `rule` is the reaction rule at `index`, and record the
+    /// outcome in the statistics of the rule.
+    fn apply_rule(
+        &mut self,
+        index: usize,
+        lt: &Term,
+        rt: &Term,
+    ) -> Result<RuleProduct, LambdaCollisionError> {
+        let result = self.reduce_with_rule(index, lt, rt);
+        self.statistics[index].record(&result);
+        result
+    }
+
+    /// The limits each collision is reduced within.
+    pub(crate) fn reduction_limits(&self) -> ReductionLimits {
+        self.limits(self.rlimit, self.slimit)
+    }
+
+    /// The limits collisions under the rule at `index` are reduced within, in the rule's own
+    /// reduction order if it has one.
+    pub(crate) fn rule_limits(&self, index: usize) -> ReductionLimits {
+        let mut limits = self.reduction_limits();
+        if let Some(order) = self.rule_orders[index] {
+            limits.order = order.0;
+        }
+        limits
+    }
+
+    fn limits(&self, steps: usize, size: usize) -> ReductionLimits {
+        ReductionLimits {
+            order: self.reduction_order.0,
+            steps,
+            size,
+            check_interval: self.size_check_interval,
+            timeout: self.timeout,
         }
     }
 
+    /// Reduce ((`rule` `lt`) `rt`), or look up the outcome in the collision cache.
+    fn reduce_memoized(&mut self, index: usize, lt: &Term, rt: &Term) -> Reduction {
+        if let Some(reduction) = self.memo.as_mut().and_then(|m| m.get(index, lt, rt)) {
+            return reduction;
+        }
+        let rule = &self.reaction_rules[index];
+        let mut expr = app!(rule.clone(), lt.clone(), rt.clone());
+        let reduction = match reduce_within(&mut expr, &self.rule_limits(index)) {
+            ReductionOutcome::StepLimit { .. } => Err(LambdaCollisionError::ExceedsReductionLimit),
+            outcome => outcome.steps_or_error().map(|n| (expr, n)),
+        };
+        if let Some(memo) = &mut self.memo {
+            if reduction != Err(LambdaCollisionError::Timeout) {
+                memo.insert(index, lt, rt, reduction.clone());
+            }
+        }
+        reduction
+    }
+
+    fn reduce_with_rule(
+        &mut self,
+        index: usize,
+        lt: &Term,
+        rt: &Term,
+    ) -> Result<RuleProduct, LambdaCollisionError> {
+        let (expr, n) = self.reduce_memoized(index, lt, rt)?;
+        if let Some(filter) = self.filters.iter().find(|f| f.matches(&expr)) {
+            return Err(filter.error());
+        }
+
+        let is_copy_action = expr.is_isomorphic_to(lt) || expr.is_isomorphic_to(rt);
+        if is_copy_action && self.discard_copy_actions {
+            return Err(LambdaCollisionError::IsParent);
+        }
+
+        Ok(RuleProduct {
+            rule: index,
+            size: term_size(&expr),
+            depth: expr.max_depth(),
+            reductions: n,
+            particle: LambdaParticle {
+                expr,
+                recursive: false,
+            },
+        })
+    }
+
+    /// Whether the rule at `index` takes part in this collision. The RNG is only used for rules
+    /// that are not always applied.
+    fn rule_is_present(&self, index: usize, rng: &mut ChaCha8Rng) -> bool {
+        let p = self.rule_probabilities[index];
+        p >= 1.0 || rng.gen_bool(p)
+    }
+
+    /// Try each present rule in order, returning the product of the first one that succeeds.
+    /// If every rule fails, return the error of the last one, and if no rule is present, return
+    /// `NoRuleApplied`.
+    fn apply_first_successful_rule(
+        &mut self,
+        lt: &Term,
+        rt: &Term,
+        rng: &mut ChaCha8Rng,
+    ) -> Result<Vec<RuleProduct>, LambdaCollisionError> {
+        let mut error = None;
+        for index in 0..self.reaction_rules.len() {
+            if !self.rule_is_present(index, rng) {
+                continue;
+            }
+            match self.apply_rule(index, lt, rt) {
+                Ok(result) => return Ok(vec![result]),
+                Err(e) => error = Some(e),
+            }
+        }
+        Err(error.unwrap_or(LambdaCollisionError::NoRuleApplied))
+    }
+
     fn nonrecursive_collide(
-        &self,
-        left: LambdaParticle,
-        right: LambdaParticle,
+        &mut self,
+        left: &LambdaParticle,
+        right: &LambdaParticle,
+        rng: &mut ChaCha8Rng,
     ) -> Result<LambdaCollisionOk, LambdaCollisionError> {
         assert!(!left.recursive);
-        let lt = left.expr;
-        let rt = right.expr;
+        let (lt, rt) = (&left.expr, &right.expr);
         if right.recursive {
             return Err(LambdaCollisionError::RecursiveArgument);
         }
-        let mut collision_results = Vec::with_capacity(self.reaction_rules.len());
 
-        for rule in &self.reaction_rules {
-            let mut expr = app!(rule.clone(), lt.clone(), rt.clone());
-            let n = reduce_with_limit(&mut expr, self.rlimit, self.slimit)?;
-            let size = expr.size();
-
-            if n == self.rlimit {
-                return Err(LambdaCollisionError::ExceedsReductionLimit);
+        let collision_results = match self.rule_application {
+            RuleApplication::All => {
+                let present = (0..self.reaction_rules.len())
+                    .filter(|&index| self.rule_is_present(index, rng))
+                    .collect::<Vec<_>>();
+                if present.is_empty() {
+                    return Err(LambdaCollisionError::NoRuleApplied);
+                }
+                present
+                    .into_iter()
+                    .map(|index| self.apply_rule(index, lt, rt))
+                    .collect::<Result<Vec<_>, _>>()?
             }
-
-            let identity = abs(Var(1));
-            if expr.is_isomorphic_to(&identity) && self.discard_identity {
-                return Err(LambdaCollisionError::IsIdentity);
+            RuleApplication::Random if self.reaction_rules.is_empty() => {
+                return Err(LambdaCollisionError::NoRuleApplied)
             }
-
-            let is_copy_action = expr.is_isomorphic_to(&lt) || expr.is_isomorphic_to(&rt);
-            if is_copy_action && self.discard_copy_actions {
-                return Err(LambdaCollisionError::IsParent);
-            }
-
-            if expr.has_free_variables() && self.discard_free_variable_expressions {
-                return Err(LambdaCollisionError::HasFreeVariables);
+            RuleApplication::Random => {
+                let weighted = self
+                    .rule_index
+                    .get_or_init(|| Self::weighted_index(&self.config.rule_weights));
+                let index = match weighted {
+                    Some(weights) => weights.sample(rng),
+                    // Same draw as `reaction_rules.choose(rng)`, so unweighted
+                    // soups keep their seeded trajectories.
+                    None => (0..self.reaction_rules.len())
+                        .choose(rng)
+                        .expect("reaction rules are non-empty"),
+                };
+                if !self.rule_is_present(index, rng) {
+                    return Err(LambdaCollisionError::NoRuleApplied);
+                }
+                vec![self.apply_rule(index, lt, rt)?]
             }
+            RuleApplication::FirstSuccess => self.apply_first_successful_rule(lt, rt, rng)?,
+        };
 
-            let expr = LambdaParticle {
-                expr,
-                recursive: false,
-            };
-
-            collision_results.push((expr, size, n))
-        }
         Ok(LambdaCollisionOk {
-            results: collision_results.iter().map(|t| t.0.clone()).collect(),
-            reductions: collision_results.iter().map(|t| t.1).collect(),
-            sizes: collision_results.iter().map(|t| t.2).collect(),
-            left_size: lt.size(),
-            right_size: rt.size(),
+            rules: collision_results.iter().map(|p| p.rule).collect(),
+            reductions: collision_results.iter().map(|p| p.reductions).collect(),
+            sizes: collision_results.iter().map(|p| p.size).collect(),
+            depths: collision_results.iter().map(|p| p.depth).collect(),
+            results: collision_results.into_iter().map(|p| p.particle).collect(),
+            left_size: term_size(lt),
+            right_size: term_size(rt),
+            left_depth: lt.max_depth(),
+            right_depth: rt.max_depth(),
         })
     }
 }
 
+/// A term becomes an ordinary molecule, not a test expression.
+impl From<Term> for LambdaParticle {
+    fn from(expr: Term) -> Self {
+        LambdaParticle {
+            expr,
+            recursive: false,
+        }
+    }
+}
+
+impl GenealogyTree<LambdaParticle> {
+    /// The distinct ancestors of the molecule `term`, up to `depth` generations back, nearest
+    /// first. Empty if `term` was never made in the soup.
+    pub fn ancestors_of(&self, term: &Term, depth: usize) -> Vec<Term> {
+        let molecule = LambdaParticle {
+            expr: term.clone(),
+            recursive: false,
+        };
+        self.ancestors(&molecule, depth)
+            .into_iter()
+            .map(|p| p.expr)
+            .collect()
+    }
+}
+
 impl Particle for LambdaParticle {
     fn compose(&self, other: &Self) -> Self {
         LambdaParticle {
@@ -247,22 +777,36 @@ impl Particle for LambdaParticle {
     fn is_isomorphic_to(&self, other: &Self) -> bool {
         self.expr.is_isomorphic_to(&other.expr)
     }
+
+    fn size(&self) -> usize {
+        term_size(&self.expr)
+    }
+
+    fn depth(&self) -> u32 {
+        self.expr.max_depth()
+    }
 }
 
 impl Collider<LambdaParticle, LambdaCollisionOk, LambdaCollisionError> for AlchemyCollider {
     /// Return the result of ((`rule` `left`) `right`), up to a limit of
     /// `self.reduction_limit`.
     fn collide(
-        &self,
-        left: LambdaParticle,
-        right: LambdaParticle,
+        &mut self,
+        left: &LambdaParticle,
+        right: &LambdaParticle,
+        rng: &mut ChaCha8Rng,
     ) -> Result<LambdaCollisionOk, LambdaCollisionError> {
         return if left.recursive {
             self.recursive_collide(left, right)
         } else {
-            self.nonrecursive_collide(left, right)
+            self.nonrecursive_collide(left, right, rng)
         };
     }
+
+    /// Custom filters are closures, so a collider with one can't be saved.
+    fn is_saveable(&self) -> bool {
+        !self.filters.iter().any(|f| matches!(f, Filter::Custom(_)))
+    }
 }
 
 impl Residue<LambdaParticle> for LambdaCollisionOk {
@@ -273,11 +817,28 @@ impl Residue<LambdaParticle> for LambdaCollisionOk {
     fn count(&self) -> usize {
         self.results.len()
     }
+
+    fn rule_of(&self, i: usize) -> Option<usize> {
+        self.rules.get(i).copied()
+    }
+
+    fn reductions(&self) -> usize {
+        self.reductions.iter().sum()
+    }
 }
 
 impl fmt::Display for LambdaCollisionOk {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        Display::fmt("no message", f)
+        for (i, (rule, term)) in self.products().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match rule {
+                Some(rule) => write!(f, "rule {} made {}", rule, term)?,
+                None => write!(f, "test made {}", term)?,
+            }
+        }
+        Ok(())
     }
 }
 
@@ -303,17 +864,35 @@ impl fmt::Display for LambdaCollisionError {
             LambdaCollisionError::ExceedsDepthLimit => {
                 Display::fmt("expression exceeds depth limit during reduction", f)
             }
+            LambdaCollisionError::Timeout => Display::fmt("reduction timed out", f),
             LambdaCollisionError::RecursiveArgument => Display::fmt("argument is recursive", f),
             LambdaCollisionError::BadArgument => Display::fmt(
                 "argument is truth-like or doesn't use all of own arguments",
                 f,
             ),
+            LambdaCollisionError::Filtered => {
+                Display::fmt("collision result is rejected by a filter", f)
+            }
+            LambdaCollisionError::NoRuleApplied => Display::fmt("no reaction rule was applied", f),
         }
     }
 }
 
 impl std::error::Error for LambdaCollisionError {}
 
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MergeError::MismatchedReactionRules => {
+                Display::fmt("soups have different reaction rules", f)
+            }
+            MergeError::MismatchedConfig => Display::fmt("soups have different configurations", f),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
 impl fmt::Display for LambdaParticle {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         Display::fmt(&format!("{:?}", self.expr), f)
@@ -326,28 +905,62 @@ impl LambdaSoup {
         LambdaSoup::from_config(&config::Reactor::new())
     }
 
-    /// Generate an empty soup from a given `config` object.
+    /// Generate an empty soup from a given `config` object. Panics if a reaction rule does not
+    /// parse; use `try_from_config` for configs supplied by users.
     pub fn from_config(cfg: &config::Reactor) -> Self {
+        Self::try_from_config(cfg).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Generate an empty soup from a given `config` object, or fail with every reaction rule
+    /// that does not parse, with a decay probability that is not a probability, or with a
+    /// population cap too small to react.
+    pub fn try_from_config(cfg: &config::Reactor) -> Result<Self, ConfigError> {
+        let collider = AlchemyCollider::try_from_config(cfg)?;
+        if !(0.0..=1.0).contains(&cfg.decay_probability) {
+            return Err(ConfigError::InvalidDecayProbability(cfg.decay_probability));
+        }
+        if let Some(cap) = cfg.max_population.filter(|&cap| cap < 2) {
+            return Err(ConfigError::InvalidMaxPopulation(cap));
+        }
         let seed = cfg.seed.get();
         let rng = ChaCha8Rng::from_seed(seed);
-        Self {
-            expressions: Vec::new(),
-            collider: AlchemyCollider::from_config(cfg),
+        Ok(Self {
+            expressions: Population::new(),
+            collider,
             maintain_constant_population_size: cfg.maintain_constant_population_size,
             discard_parents: cfg.discard_parents,
+            protect_new_products: cfg.protect_new_products,
+            require_distinct_reactants: cfg.require_distinct_reactants,
+            selection_strategy: cfg.selection_strategy,
+            cursor: 0,
+            eviction_policy: cfg.eviction_policy,
+            decay_probability: cfg.decay_probability,
+            max_population: cfg.max_population,
+            decayed: 0,
             rng,
-            n_collisions: 0,
+            total_collisions: 0,
+            successful_collisions: 0,
+            tape: cfg.record_tape.then(ReactionTape::new),
+            inflow: None,
+            schedule: Vec::new(),
+            convergence: None,
+            lineage: cfg.record_lineage.then(|| Lineage::new(cfg.lineage_depth)),
+            genealogy: cfg.record_genealogy.then(GenealogyTree::new),
+            removed_ages: AgeBuffer::new(cfg.removed_age_buffer),
+            since_poll: ReductionWork::default(),
+            next_id: 0,
             t: PhantomData,
             e: PhantomData,
-        }
+        })
     }
 
+    /// Add `expressions` to the soup as ordinary molecules. This is `perturb` for bare terms, so
+    /// it accepts an iterator such as `read_inputs()` or `BTreeGen::generate_n(n)` directly.
     pub fn add_lambda_expressions(&mut self, expressions: impl IntoIterator<Item = Term>) {
-        self.expressions
-            .extend(expressions.into_iter().map(|t| LambdaParticle {
-                expr: t,
-                recursive: false,
-            }))
+        self.perturb(expressions.into_iter().map(|t| LambdaParticle {
+            expr: t,
+            recursive: false,
+        }))
     }
 
     pub fn perturb_lambda_expressions<I>(&mut self, nterms: usize, expressions: I)
@@ -355,21 +968,48 @@ impl LambdaSoup {
         I: IntoIterator<Item = Term>,
         <I as IntoIterator>::IntoIter: Clone,
     {
-        if self.maintain_constant_population_size {
-            for _ in 0..nterms {
-                let k = self.rng.gen_range(0..self.expressions.len());
-                self.expressions.swap_remove(k);
-            }
-        }
+        self.evict(nterms);
         self.add_lambda_expressions(expressions.into_iter().cycle().take(nterms))
     }
 
-    pub fn add_test_expressions(&mut self, expressions: impl IntoIterator<Item = Term>) {
-        self.expressions
-            .extend(expressions.into_iter().map(|t| LambdaParticle {
+    /// Inject the expressions of `food`, cycling, at `rate` expressions per collision.
+    pub fn set_food_inflow(&mut self, rate: f64, food: impl IntoIterator<Item = Term>) {
+        let food = food
+            .into_iter()
+            .map(|t| LambdaParticle {
                 expr: t,
-                recursive: true,
-            }))
+                recursive: false,
+            })
+            .collect();
+        self.set_inflow(Some(Inflow::food(rate, food)));
+    }
+
+    /// Inject expressions produced by a generator made with `factory` at `rate` expressions per
+    /// collision. Clones of the soup each make their own generator; see `Inflow::generator`.
+    pub fn set_generated_inflow<G>(
+        &mut self,
+        rate: f64,
+        factory: impl Fn() -> G + Send + Sync + 'static,
+    ) where
+        G: FnMut() -> Term + Send + 'static,
+    {
+        self.set_inflow(Some(Inflow::generator(rate, move || {
+            let mut generator = factory();
+            move || LambdaParticle {
+                expr: generator(),
+                recursive: false,
+            }
+        })));
+    }
+
+    /// Add `expressions` as test expressions, which are told apart from ordinary molecules by
+    /// `LambdaParticle::is_recursive`. Each should take a candidate function and reduce to
+    /// `true` exactly when the candidate passes the test.
+    pub fn add_test_expressions(&mut self, expressions: impl IntoIterator<Item = Term>) {
+        self.perturb(expressions.into_iter().map(|t| LambdaParticle {
+            expr: t,
+            recursive: true,
+        }))
     }
 
     pub fn perturb_test_expressions<I>(&mut self, nterms: usize, expressions: I)
@@ -377,22 +1017,949 @@ impl LambdaSoup {
         I: IntoIterator<Item = Term>,
         <I as IntoIterator>::IntoIter: Clone,
     {
-        if self.maintain_constant_population_size {
-            for _ in 0..nterms {
-                let k = self.rng.gen_range(0..self.expressions.len());
-                self.expressions.swap_remove(k);
-            }
-        }
+        self.evict(nterms);
         self.add_test_expressions(expressions.into_iter().cycle().take(nterms))
     }
 
     pub fn lambda_expressions(&self) -> impl Iterator<Item = &Term> {
-        self.expressions.iter().map(|e| e.get_underlying_term())
+        self.expressions().map(|e| e.get_underlying_term())
+    }
+
+    /// The ordinary expressions in the soup, leaving out test expressions.
+    pub fn molecules(&self) -> impl Iterator<Item = &Term> {
+        self.expressions()
+            .filter(|e| !e.is_recursive())
+            .map(|e| e.get_underlying_term())
+    }
+
+    /// The test expressions in the soup.
+    pub fn test_expressions(&self) -> impl Iterator<Item = &Term> {
+        self.expressions()
+            .filter(|e| e.is_recursive())
+            .map(|e| e.get_underlying_term())
+    }
+
+    /// Remove the test expressions that `candidate` passes, that is, those that reduce to
+    /// `true` when applied to it, within the limits used for test reactions. Returns the number
+    /// of tests removed.
+    pub fn remove_tests_satisfied_by(&mut self, candidate: &Term) -> usize {
+        let limits = self.collider.limits(32000, 16000);
+        let before = self.len();
+        self.expressions.retain(|e| {
+            if !e.particle.recursive {
+                return true;
+            }
+            let mut expr = app!(e.particle.expr.clone(), candidate.clone());
+            let outcome = reduce_within(&mut expr, &limits);
+            !(outcome.is_normalized()
+                && expr.is_isomorphic_to(&lambda_calculus::data::boolean::tru()))
+        });
+        before - self.len()
+    }
+
+    /// Combine the expressions of `self` and `other` into one soup. Both soups must share the same
+    /// reaction rules and configuration. The merged soup keeps the RNG of `self`, and starts
+    /// counting collisions from zero.
+    pub fn merge(self, other: LambdaSoup) -> Result<LambdaSoup, MergeError> {
+        if self.collider.reaction_rules != other.collider.reaction_rules {
+            return Err(MergeError::MismatchedReactionRules);
+        }
+        if self.collider != other.collider
+            || self.maintain_constant_population_size != other.maintain_constant_population_size
+            || self.discard_parents != other.discard_parents
+            || self.protect_new_products != other.protect_new_products
+            || self.eviction_policy != other.eviction_policy
+            || self.selection_strategy != other.selection_strategy
+        {
+            return Err(MergeError::MismatchedConfig);
+        }
+
+        let mut merged = Self {
+            expressions: Population::new(),
+            total_collisions: 0,
+            successful_collisions: 0,
+            decayed: 0,
+            tape: self.tape.as_ref().map(|_| ReactionTape::new()),
+            lineage: self.lineage.as_ref().map(|l| l.emptied()),
+            genealogy: self.genealogy.as_ref().map(|_| GenealogyTree::new()),
+            removed_ages: self.removed_ages.emptied(),
+            since_poll: ReductionWork::default(),
+            next_id: 0,
+            cursor: 0,
+            ..self
+        };
+        merged.perturb(
+            self.expressions
+                .into_iter()
+                .chain(other.expressions)
+                .map(|e| Arc::unwrap_or_clone(e.particle)),
+        );
+        Ok(merged)
+    }
+
+    /// Combine the expressions of `self` and `other` into a fresh soup built from `cfg`.
+    pub fn merge_with_config(self, other: LambdaSoup, cfg: &config::Reactor) -> LambdaSoup {
+        let mut merged = LambdaSoup::from_config(cfg);
+        merged.perturb(
+            self.expressions
+                .into_iter()
+                .chain(other.expressions)
+                .map(|e| Arc::unwrap_or_clone(e.particle)),
+        );
+        merged
+    }
+
+    /// Discard all future reaction products matched by `filter`. The filters installed by the
+    /// `discard_identity` and `discard_free_variable_expressions` config options are
+    /// `Filter::IsIdentity` and `Filter::HasFreeVariables`.
+    pub fn add_filter(&mut self, filter: Filter) {
+        self.collider.filters.push(filter);
+    }
+
+    /// Remove all filters, including the ones installed from the config. Copy actions are still
+    /// discarded if `discard_copy_actions` is set.
+    pub fn clear_filters(&mut self) {
+        self.collider.filters.clear();
+    }
+
+    /// The filters that reaction products must pass to enter the soup.
+    pub fn filters(&self) -> &[Filter] {
+        &self.collider.filters
+    }
+
+    /// The reaction rules of the soup, in the order they are applied.
+    pub fn rules(&self) -> &[Term] {
+        &self.collider.reaction_rules
+    }
+
+    /// Parse `rule` in `Classic` notation and add it after the existing reaction rules. The
+    /// new rule is always applied, and has weight `1.0` if the rules are weighted. Takes effect
+    /// from the next simulation run. If `rule` does not parse, the soup is unchanged, and the
+    /// error holds `rule` and the index it would have had.
+    pub fn add_rule(&mut self, rule: &str) -> Result<(), InvalidRule> {
+        let collider = &mut self.collider;
+        let term = lambda_calculus::parse(rule, lambda_calculus::Classic).map_err(|error| {
+            InvalidRule {
+                index: collider.reaction_rules.len(),
+                rule: rule.to_string(),
+                error,
+            }
+        })?;
+        collider.reaction_rules.push(term);
+        collider.config.rules.push(config::ReactionRule::new(rule));
+        if let Some(weights) = &mut collider.config.rule_weights {
+            weights.push(1.0);
+        }
+        collider.rule_probabilities.push(1.0);
+        collider.rule_orders.push(None);
+        collider.rule_index = OnceLock::new();
+        collider.statistics.push(ReactionStats::default());
+        Ok(())
+    }
+
+    /// Remove the reaction rule at `index`, along with its weight, probability, reduction order
+    /// and statistics, and return it. Later rules move down by one. Panics if `index` is out of
+    /// bounds.
+    pub fn remove_rule(&mut self, index: usize) -> Term {
+        let collider = &mut self.collider;
+        let rule = collider.reaction_rules.remove(index);
+        collider.config.rules.remove(index);
+        if let Some(weights) = &mut collider.config.rule_weights {
+            weights.remove(index);
+        }
+        collider.rule_probabilities.remove(index);
+        collider.rule_orders.remove(index);
+        collider.rule_index = OnceLock::new();
+        collider.statistics.remove(index);
+        // Cached reductions are keyed by rule index, which has just shifted
+        if let Some(memo) = &mut collider.memo {
+            memo.clear();
+        }
+        rule
+    }
+
+    /// Get each reaction rule along with the statistics of its applications so far.
+    pub fn rule_statistics(&self) -> Vec<(Term, ReactionStats)> {
+        let collider = &self.collider;
+        collider
+            .reaction_rules
+            .iter()
+            .cloned()
+            .zip(collider.statistics.iter().copied())
+            .collect()
+    }
+
+    /// The config of the soup as it is now: the one it was made from, with the reaction rules
+    /// added and removed since, and the settings changed with `set_require_distinct_reactants`,
+    /// `set_max_population` and `set_selection_strategy`. Filters added with `add_filter` and
+    /// inflows have no place in a config, and are left out.
+    pub fn config(&self) -> config::Reactor {
+        config::Reactor {
+            require_distinct_reactants: self.require_distinct_reactants,
+            max_population: self.max_population,
+            selection_strategy: self.selection_strategy,
+            ..self.collider.config.clone()
+        }
+    }
+
+    /// Reset the statistics of every reaction rule, and of the collision cache, to zero.
+    pub fn reset_statistics(&mut self) {
+        self.collider.statistics.fill(ReactionStats::default());
+        if let Some(memo) = &mut self.collider.memo {
+            memo.stats = CacheStats::default();
+        }
+    }
+
+    /// Empty the soup and start it over, as if it had just been made from its config. The
+    /// expressions, collision counters, tape, lineage, ages of removed expressions and rule
+    /// statistics are cleared. The reaction rules, filters, limits and the rest of the
+    /// configuration are kept, and the RNG carries on from where it was.
+    pub fn reset(&mut self) {
+        let rng = self.rng.clone();
+        *self = self.empty_copy(rng);
+        self.reset_statistics();
+    }
+
+    /// Like `reset`, but also reseed the RNG, so the soup behaves exactly like a new soup made
+    /// with `seed`. A blank seed picks a random one.
+    pub fn reset_with_seed(&mut self, seed: ConfigSeed) {
+        *self = self.empty_copy(ChaCha8Rng::from_seed(seed.get()));
+        self.reset_statistics();
+    }
+
+    /// Hits and misses of the collision cache so far, or `None` if the soup has no cache. See
+    /// `config::Reactor::collision_cache_size`.
+    pub fn collision_cache_statistics(&self) -> Option<CacheStats> {
+        self.collider.memo.as_ref().map(|m| m.stats)
+    }
+
+    /// How each distinct expression made in the soup was first made, if `record_genealogy` is
+    /// set in the config.
+    pub fn genealogy(&self) -> Option<&GenealogyTree<LambdaParticle>> {
+        self.genealogy.as_ref()
+    }
+
+    /// Trace the ancestry of an expression in the soup isomorphic to `target`, back to the
+    /// expressions that were added to the soup from outside. Requires `record_lineage` to be
+    /// set in the config.
+    pub fn lineage_of(&self, target: &Term) -> Option<Rc<LineageTree<LambdaParticle>>> {
+        self.lineage_where(|p| p.expr.is_isomorphic_to(target))
+    }
+
+    /// Keep only the expressions for which `f` returns `true`, like `Vec::retain`. Can be called
+    /// between simulation runs to intervene on the soup.
+    pub fn retain(&mut self, mut f: impl FnMut(&Term) -> bool) {
+        self.expressions.retain(|e| f(&e.particle.expr))
+    }
+
+    /// Remove every expression isomorphic to `target`, and return how many were removed.
+    pub fn remove_isomorphic_to(&mut self, target: &Term) -> usize {
+        let before = self.len();
+        self.retain(|t| !t.is_isomorphic_to(target));
+        before - self.len()
+    }
+}
+
+/// The S, K and I combinators, over and over, for tests to fill soups with.
+#[cfg(test)]
+pub(crate) fn ski_cycle() -> impl Iterator<Item = Term> + Clone + Send {
+    [r"\x.\y.\z.x z (y z)", r"\x.\y.x", r"\x.x"]
+        .into_iter()
+        .cycle()
+        .map(|s| lambda_calculus::parse(s, lambda_calculus::Classic).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    use lambda_calculus::{parse, Classic, Term};
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::{
+        reduce_within, ski_cycle, term_size, AlchemyCollider, Filter, LambdaCollisionError,
+        LambdaParticle, LambdaSoup, MergeError, ReactionStats, ReductionLimits, ReductionOutcome,
+    };
+    use crate::config::{
+        self, ConfigError, ConfigSeed, EvictionPolicy, ReactionRule, RuleApplication,
+    };
+    use crate::supercollider::Collider;
+
+    fn particle(s: &str) -> LambdaParticle {
+        LambdaParticle {
+            expr: parse(s, Classic).unwrap(),
+            recursive: false,
+        }
+    }
+
+    #[test]
+    fn random_rule_application_fires_every_rule() {
+        let mut collider = AlchemyCollider::from_config(&config::Reactor {
+            rules: vec![
+                ReactionRule::new(r"\x.\y.x y"),
+                ReactionRule::new(r"\x.\y.y x"),
+            ],
+            rule_application: RuleApplication::Random,
+            discard_copy_actions: false,
+            discard_identity: false,
+            ..config::Reactor::new()
+        });
+        let mut rng = ChaCha8Rng::from_seed([0; 32]);
+        let (left, right) = (particle(r"\x.\y.x"), particle(r"\x.x x"));
+
+        let products = (0..64)
+            .map(|_| {
+                let result = collider.collide(&left, &right, &mut rng).unwrap();
+                assert_eq!(result.results.len(), 1);
+                result.results[0].expr.clone()
+            })
+            .collect::<HashSet<Term>>();
+        assert_eq!(products.len(), 2);
+    }
+
+    #[test]
+    fn unweighted_rule_choice_draws_like_choose() {
+        let mut collider = AlchemyCollider::from_config(&config::Reactor {
+            rules: vec![
+                ReactionRule::new(r"\x.\y.x y"),
+                ReactionRule::new(r"\x.\y.y x"),
+                ReactionRule::new(r"\x.\y.x"),
+            ],
+            rule_application: RuleApplication::Random,
+            discard_copy_actions: false,
+            discard_identity: false,
+            ..config::Reactor::new()
+        });
+        let mut rng = ChaCha8Rng::from_seed([0; 32]);
+        let mut mirror = ChaCha8Rng::from_seed([0; 32]);
+        let (left, right) = (particle(r"\x.\y.x"), particle(r"\x.x x"));
+
+        for _ in 0..64 {
+            let result = collider.collide(&left, &right, &mut rng).unwrap();
+            let expected = *[0, 1, 2].choose(&mut mirror).unwrap();
+            assert_eq!(result.rules, [expected]);
+        }
+    }
+
+    #[test]
+    fn weighted_rules_bias_rule_choice() {
+        let mut collider = AlchemyCollider::from_config(&config::Reactor {
+            rules: vec![
+                ReactionRule::new(r"\x.\y.x y"),
+                ReactionRule::new(r"\x.\y.y x"),
+            ],
+            rule_application: RuleApplication::Random,
+            rule_weights: Some(vec![9.0, 1.0]),
+            discard_copy_actions: false,
+            discard_identity: false,
+            ..config::Reactor::new()
+        });
+        let mut rng = ChaCha8Rng::from_seed([0; 32]);
+        let (left, right) = (particle(r"\x.\y.x"), particle(r"\x.x x"));
+
+        let n_first = (0..1000)
+            .filter(|_| {
+                let result = collider.collide(&left, &right, &mut rng).unwrap();
+                result.rules == [0]
+            })
+            .count();
+        assert!((850..950).contains(&n_first));
     }
 
-    pub fn population_of(&self, item: &Term) -> usize {
-        self.lambda_expressions()
-            .filter(|p| p.is_isomorphic_to(item))
-            .count()
+    #[test]
+    fn rule_weights_must_fit_the_rules() {
+        let two_rules = config::Reactor {
+            rules: vec![
+                ReactionRule::new(r"\x.\y.x y"),
+                ReactionRule::new(r"\x.\y.y x"),
+            ],
+            rule_application: RuleApplication::Random,
+            ..config::Reactor::new()
+        };
+        for (application, weights) in [
+            (RuleApplication::Random, vec![1.0]),
+            (RuleApplication::Random, vec![1.0, -1.0]),
+            (RuleApplication::All, vec![1.0, 1.0]),
+        ] {
+            let cfg = config::Reactor {
+                rule_application: application,
+                rule_weights: Some(weights),
+                ..two_rules.clone()
+            };
+            assert!(matches!(
+                AlchemyCollider::try_from_config(&cfg),
+                Err(ConfigError::InvalidRuleWeights(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn first_success_keeps_one_product() {
+        let mut collider = AlchemyCollider::from_config(&config::Reactor {
+            rules: vec![
+                ReactionRule::new(r"\x.\y.x y"),
+                ReactionRule::new(r"\x.\y.y x"),
+            ],
+            rule_application: RuleApplication::FirstSuccess,
+            ..config::Reactor::new()
+        });
+        let mut rng = ChaCha8Rng::from_seed([0; 32]);
+        let result = collider
+            .collide(&particle(r"\x.\y.x"), &particle(r"\x.x x"), &mut rng)
+            .unwrap();
+        assert_eq!(result.results.len(), 1);
+        assert!(result.results[0]
+            .expr
+            .is_isomorphic_to(&parse(r"\y.\x.x x", Classic).unwrap()));
+    }
+
+    #[test]
+    fn collisions_report_reductions_and_sizes_of_their_products() {
+        let mut collider = AlchemyCollider::from_config(&config::Reactor {
+            rules: vec![ReactionRule::new(r"\x.\y.x y")],
+            discard_copy_actions: false,
+            ..config::Reactor::new()
+        });
+        let mut rng = ChaCha8Rng::from_seed([0; 32]);
+        // ((\x.\y.x y) I) S -> (\y.I y) S -> I S -> S
+        let (left, right) = (particle(r"\x.x"), particle(r"\x.\y.\z.x z (y z)"));
+        let result = collider.collide(&left, &right, &mut rng).unwrap();
+        assert_eq!(result.reductions, [3]);
+        assert_eq!(result.sizes, [10]);
+        assert_eq!(result.depths, [right.expr.max_depth()]);
+        assert_eq!((result.left_size, result.right_size), (2, 10));
+        assert_eq!(
+            (result.left_depth, result.right_depth),
+            (left.expr.max_depth(), right.expr.max_depth())
+        );
+        assert_eq!(result.results, [right]);
+    }
+
+    #[test]
+    fn protected_products_survive_their_reaction() {
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            discard_copy_actions: false,
+            discard_identity: false,
+            protect_new_products: true,
+            seed: ConfigSeed::new([0; 32]),
+            ..config::Reactor::new()
+        });
+        soup.add_lambda_expressions(ski_cycle().take(60));
+
+        for _ in 0..1000 {
+            if let Some(Ok(result)) = soup.react() {
+                for product in &result.results {
+                    assert!(soup.expressions().any(|e| e == product));
+                }
+            }
+            assert_eq!(soup.len(), 60);
+        }
+    }
+
+    #[test]
+    fn eviction_policies_pick_their_victims() {
+        let (s, k, i) = (r"\x.\y.\z.x z (y z)", r"\x.\y.x", r"\x.x");
+        let soup_of = |policy, exprs: &[&str]| {
+            let mut soup = LambdaSoup::from_config(&config::Reactor {
+                eviction_policy: policy,
+                ..config::Reactor::new()
+            });
+            soup.add_lambda_expressions(exprs.iter().map(|e| parse(e, Classic).unwrap()));
+            soup
+        };
+        let remaining = |soup: &LambdaSoup| {
+            let mut exprs = soup
+                .expressions()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>();
+            exprs.sort();
+            exprs
+        };
+
+        let mut oldest = soup_of(EvictionPolicy::Oldest, &[s, k]);
+        oldest.total_collisions = 5;
+        oldest.perturb_lambda_expressions(2, [parse(i, Classic).unwrap()]);
+        assert_eq!(
+            remaining(&oldest),
+            remaining(&soup_of(EvictionPolicy::Random, &[i, i]))
+        );
+
+        // Everything is born at step 0, so the first added goes first, even once `swap_remove`
+        // has moved the last one to the front
+        let mut first_added = soup_of(EvictionPolicy::Oldest, &[s, k, i]);
+        first_added.evict(2);
+        assert_eq!(
+            remaining(&first_added),
+            remaining(&soup_of(EvictionPolicy::Random, &[i]))
+        );
+
+        let mut largest = soup_of(EvictionPolicy::Largest, &[i, s, k, s]);
+        largest.evict(2);
+        assert_eq!(
+            remaining(&largest),
+            remaining(&soup_of(EvictionPolicy::Random, &[i, k]))
+        );
+
+        let mut most_common = soup_of(EvictionPolicy::MostCommon, &[s, k, i, k]);
+        most_common.evict(1);
+        assert_eq!(
+            remaining(&most_common),
+            remaining(&soup_of(EvictionPolicy::Random, &[s, i, k]))
+        );
+    }
+
+    #[test]
+    fn remove_isomorphic_to_culls_a_whole_class() {
+        let mut soup = LambdaSoup::new();
+        let (k, i) = (r"\x.\y.x", r"\x.x");
+        soup.add_lambda_expressions([k, i, k, k].iter().map(|e| parse(e, Classic).unwrap()));
+
+        let k = parse(k, Classic).unwrap();
+        assert_eq!(soup.remove_isomorphic_to(&k), 3);
+        assert_eq!(soup.remove_isomorphic_to(&k), 0);
+        assert_eq!(soup.population_of(&k), 0);
+        assert_eq!(soup.len(), 1);
+    }
+
+    #[test]
+    fn merge_requires_matching_rules() {
+        let (k, i) = (r"\x.\y.x", r"\x.x");
+        let soup_of = |rules: &[&str], exprs: &[&str]| {
+            let mut soup = LambdaSoup::from_config(&config::Reactor {
+                rules: rules.iter().map(|r| ReactionRule::new(*r)).collect(),
+                ..config::Reactor::new()
+            });
+            soup.add_lambda_expressions(exprs.iter().map(|e| parse(e, Classic).unwrap()));
+            soup
+        };
+
+        let mut left = soup_of(&[r"\x.\y.x y"], &[k, k, i]);
+        left.simulate_for(5, false);
+        let merged = left.merge(soup_of(&[r"\x.\y.x y"], &[i])).unwrap();
+        assert_eq!(merged.collisions(), 0);
+        assert_eq!(merged.len(), 4);
+
+        let mismatched = soup_of(&[r"\x.\y.x y"], &[k]).merge(soup_of(&[r"\x.\y.y x"], &[i]));
+        assert_eq!(mismatched.unwrap_err(), MergeError::MismatchedReactionRules);
+    }
+
+    #[test]
+    fn filters_reject_matching_products() {
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            discard_copy_actions: false,
+            ..config::Reactor::new()
+        });
+        let mut rng = ChaCha8Rng::from_seed([0; 32]);
+        let (left, right) = (particle(r"\x.\y.x"), particle(r"\x.x x"));
+        let collide =
+            |soup: &mut LambdaSoup, rng: &mut ChaCha8Rng| soup.collider.collide(&left, &right, rng);
+        assert_eq!(
+            soup.filters(),
+            [Filter::IsIdentity, Filter::HasFreeVariables]
+        );
+        assert!(collide(&mut soup, &mut rng).is_ok());
+
+        soup.add_filter(Filter::SizeLargerThan(4));
+        assert_eq!(
+            collide(&mut soup, &mut rng),
+            Err(LambdaCollisionError::Filtered)
+        );
+
+        soup.clear_filters();
+        soup.add_filter(Filter::custom(|t| t.has_free_variables()));
+        assert!(collide(&mut soup, &mut rng).is_ok());
+    }
+
+    #[test]
+    fn rule_statistics_count_outcomes() {
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            rules: vec![
+                ReactionRule::new(r"\x.\y.x y"),
+                ReactionRule::new(r"\x.\y.y x"),
+            ],
+            ..config::Reactor::new()
+        });
+        let mut rng = ChaCha8Rng::from_seed([0; 32]);
+        let (k, u, i) = (particle(r"\x.\y.x"), particle(r"\x.x x"), particle(r"\x.x"));
+        assert!(soup.collider.collide(&k, &u, &mut rng).is_ok());
+        // The first rule makes the identity, so the second is never tried
+        assert!(soup.collider.collide(&i, &i, &mut rng).is_err());
+
+        let stats = soup.rule_statistics();
+        assert_eq!(stats[0].0, parse(r"\x.\y.x y", Classic).unwrap());
+        assert_eq!((stats[0].1.attempts, stats[0].1.successes), (2, 1));
+        assert_eq!(stats[0].1.failed_identity, 1);
+        assert_eq!((stats[1].1.attempts, stats[1].1.successes), (1, 1));
+
+        soup.reset_statistics();
+        assert!(soup
+            .rule_statistics()
+            .iter()
+            .all(|(_, s)| *s == ReactionStats::default()));
+    }
+
+    #[test]
+    fn rules_apply_with_their_probability() {
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            rules: vec![
+                ReactionRule::new(r"\x.\y.x y"),
+                ReactionRule {
+                    probability: 0.0,
+                    ..ReactionRule::new(r"\x.\y.y x")
+                },
+            ],
+            ..config::Reactor::new()
+        });
+        let mut rng = ChaCha8Rng::from_seed([0; 32]);
+        for _ in 0..20 {
+            let (k, u) = (particle(r"\x.\y.x"), particle(r"\x.x x"));
+            let result = soup.collider.collide(&k, &u, &mut rng).unwrap();
+            assert_eq!(result.rules, [0]);
+        }
+        assert_eq!(soup.rule_statistics()[1].1.attempts, 0);
+    }
+
+    #[test]
+    fn collisions_without_a_present_rule_fail() {
+        for rule_application in [
+            RuleApplication::All,
+            RuleApplication::Random,
+            RuleApplication::FirstSuccess,
+        ] {
+            let mut soup = LambdaSoup::from_config(&config::Reactor {
+                rules: vec![ReactionRule {
+                    probability: 0.0,
+                    ..ReactionRule::new(r"\x.\y.x y")
+                }],
+                rule_application,
+                ..config::Reactor::new()
+            });
+            soup.add_lambda_expressions(
+                [r"\x.\y.x", r"\x.x x"].map(|e| parse(e, Classic).unwrap()),
+            );
+            for _ in 0..10 {
+                let result = soup.react().unwrap();
+                assert_eq!(result.unwrap_err(), LambdaCollisionError::NoRuleApplied);
+            }
+            assert_eq!(soup.successful_collisions(), 0);
+            assert_eq!(soup.len(), 2);
+        }
+    }
+
+    #[test]
+    fn rules_reduce_with_their_own_order() {
+        // Applicative order evaluates `x x` before `y` can drop it, which diverges for `\x.x x`
+        let rule = r"\x.\y.y (x x)";
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            rules: vec![
+                ReactionRule::new(rule),
+                ReactionRule {
+                    reduction_order: Some("NOR".parse().unwrap()),
+                    ..ReactionRule::new(rule)
+                },
+            ],
+            rule_application: RuleApplication::FirstSuccess,
+            ..config::Reactor::new()
+        });
+        let mut rng = ChaCha8Rng::from_seed([0; 32]);
+        let (u, drop) = (particle(r"\x.x x"), particle(r"\a.\b.\c.c"));
+        let result = soup.collider.collide(&u, &drop, &mut rng).unwrap();
+        assert_eq!(result.rules, [1]);
+        assert_eq!(result.results, [particle(r"\b.\c.c")]);
+        assert_eq!(soup.rule_statistics()[0].1.successes, 0);
+    }
+
+    #[test]
+    fn rules_can_be_added_and_removed_between_runs() {
+        let term = |s: &str| parse(s, Classic).unwrap();
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            rule_application: RuleApplication::All,
+            ..config::Reactor::new()
+        });
+        assert!(soup.add_rule(r"\x.\y.y x").is_ok());
+        let error = soup.add_rule(r"\x.(").unwrap_err();
+        assert_eq!((error.index, error.rule.as_str()), (2, r"\x.("));
+        assert!(error.to_string().starts_with(r"rule 2 `\x.(`: "));
+        assert_eq!(soup.rules(), [r"\x.\y.x y", r"\x.\y.y x"].map(term));
+
+        let mut rng = ChaCha8Rng::from_seed([0; 32]);
+        let (k, u) = (particle(r"\x.\y.x"), particle(r"\x.x x"));
+        let result = soup.collider.collide(&k, &u, &mut rng).unwrap();
+        assert_eq!(result.rules, [0, 1]);
+
+        assert_eq!(soup.remove_rule(0), term(r"\x.\y.x y"));
+        let result = soup.collider.collide(&k, &u, &mut rng).unwrap();
+        assert_eq!(result.rules, [0]);
+        assert_eq!(soup.rule_statistics()[0].0, term(r"\x.\y.y x"));
+        assert_eq!(soup.rule_statistics()[0].1.attempts, 2);
+    }
+
+    #[test]
+    fn malformed_rules_are_named_in_the_error() {
+        let cfg = config::Reactor {
+            rules: vec![
+                ReactionRule::new(r"\x.\y.x y"),
+                ReactionRule::new(r"\x.\y.(x"),
+                ReactionRule::new(r"\x.\y.y x"),
+            ],
+            ..config::Reactor::new()
+        };
+        let Err(ConfigError::InvalidRules(invalid)) = LambdaSoup::try_from_config(&cfg) else {
+            panic!("a malformed rule was accepted");
+        };
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(
+            (invalid[0].index, invalid[0].rule.as_str()),
+            (1, r"\x.\y.(x")
+        );
+        let err = LambdaSoup::try_from_config(&cfg).err().unwrap();
+        assert!(err.to_string().contains(r"rule 1 `\x.\y.(x`"));
+    }
+
+    #[test]
+    fn decay_probability_must_be_a_probability() {
+        for p in [1.5, -0.5, f64::NAN] {
+            let cfg = config::Reactor {
+                decay_probability: p,
+                ..config::Reactor::new()
+            };
+            assert!(matches!(
+                LambdaSoup::try_from_config(&cfg),
+                Err(ConfigError::InvalidDecayProbability(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn max_population_must_leave_room_for_a_reaction() {
+        for cap in [0, 1] {
+            let cfg = config::Reactor {
+                max_population: Some(cap),
+                ..config::Reactor::new()
+            };
+            assert!(matches!(
+                LambdaSoup::try_from_config(&cfg),
+                Err(ConfigError::InvalidMaxPopulation(n)) if n == cap
+            ));
+        }
+    }
+
+    #[test]
+    fn genealogy_records_parents_of_products() {
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            record_genealogy: true,
+            seed: ConfigSeed::new([0; 32]),
+            ..config::Reactor::new()
+        });
+        let ski = [r"\x.\y.\z.x z (y z)", r"\x.\y.x", r"\x.x x"];
+        soup.add_lambda_expressions(
+            ski.iter()
+                .cycle()
+                .take(30)
+                .map(|s| parse(s, Classic).unwrap()),
+        );
+        soup.simulate_for(300, false);
+        assert!(LambdaSoup::new().genealogy().is_none());
+
+        let genealogy = soup.genealogy().unwrap();
+        let made = soup
+            .expressions()
+            .find(|p| genealogy.derivation(p).is_some())
+            .unwrap();
+        let derivation = genealogy.derivation(made).unwrap();
+        let parents = genealogy.ancestors_of(&made.expr, 1);
+        assert_eq!(parents[0], derivation.left.expr);
+        assert!(parents.contains(&derivation.right.expr));
+        assert!(genealogy.ancestors_of(&made.expr, 0).is_empty());
+        assert!(genealogy.ancestors_of(&made.expr, 10).len() >= parents.len());
+    }
+
+    #[test]
+    fn cached_collisions_still_pass_through_filters() {
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            collision_cache_size: Some(8),
+            ..config::Reactor::new()
+        });
+        let mut rng = ChaCha8Rng::from_seed([0; 32]);
+        let (k, u) = (particle(r"\x.\y.x"), particle(r"\x.x x"));
+        let first = soup.collider.collide(&k, &u, &mut rng).unwrap();
+        let second = soup.collider.collide(&k, &u, &mut rng).unwrap();
+        assert_eq!(first, second);
+
+        soup.add_filter(Filter::custom(|t| {
+            t.is_isomorphic_to(&parse(r"\y.\x.x x", Classic).unwrap())
+        }));
+        assert_eq!(
+            soup.collider.collide(&k, &u, &mut rng),
+            Err(LambdaCollisionError::Filtered)
+        );
+        let stats = soup.collision_cache_statistics().unwrap();
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+        assert!(LambdaSoup::new().collision_cache_statistics().is_none());
+    }
+
+    #[test]
+    fn cached_and_uncached_soups_follow_the_same_trajectory() {
+        let run = |collision_cache_size| {
+            let mut soup = LambdaSoup::from_config(&config::Reactor {
+                collision_cache_size,
+                seed: ConfigSeed::new([3; 32]),
+                ..config::Reactor::new()
+            });
+            soup.add_lambda_expressions(ski_cycle().take(30));
+            let successes = soup.simulate_for(1000, false);
+            let expressions = soup.expressions().cloned().collect::<Vec<_>>();
+            (successes, expressions, soup.collision_cache_statistics())
+        };
+
+        // A small cache also exercises eviction
+        let (successes, expressions, _) = run(None);
+        let (cached_successes, cached_expressions, stats) = run(Some(16));
+        assert_eq!(successes, cached_successes);
+        assert_eq!(expressions, cached_expressions);
+        let stats = stats.unwrap();
+        assert!(stats.hits > 0 && stats.misses > 16);
+    }
+
+    #[test]
+    fn reset_soups_start_over_with_the_same_rules() {
+        let cfg = |seed| config::Reactor {
+            rules: vec![ReactionRule::new(r"\x.\y.y x")],
+            discard_identity: false,
+            record_tape: true,
+            seed: ConfigSeed::new([seed; 32]),
+            ..config::Reactor::new()
+        };
+        let run = |soup: &mut LambdaSoup| {
+            soup.add_lambda_expressions(ski_cycle().take(30));
+            soup.simulate_for(300, false);
+            soup.expressions().cloned().collect::<Vec<_>>()
+        };
+
+        let mut fresh = LambdaSoup::from_config(&cfg(5));
+        let expected = run(&mut fresh);
+
+        let mut soup = LambdaSoup::from_config(&cfg(6));
+        run(&mut soup);
+        soup.reset();
+        assert_eq!((soup.len(), soup.collisions()), (0, 0));
+        assert!(soup.tape().unwrap().reactions.is_empty());
+        assert_eq!(soup.term_lifetime_statistics().removed.count, 0);
+        assert_eq!(soup.rule_statistics()[0].1, ReactionStats::default());
+        assert_eq!(soup.collider, fresh.collider);
+
+        soup.reset_with_seed(ConfigSeed::new([5; 32]));
+        assert_eq!(run(&mut soup), expected);
+    }
+
+    #[test]
+    fn products_are_attributed_to_rules() {
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            rules: vec![
+                ReactionRule::new(r"\x.\y.x y"),
+                ReactionRule::new(r"\x.\y.y x"),
+            ],
+            rule_application: RuleApplication::All,
+            ..config::Reactor::new()
+        });
+        let mut rng = ChaCha8Rng::from_seed([0; 32]);
+        let (k, u) = (particle(r"\x.\y.x"), particle(r"\x.x x"));
+        let result = soup.collider.collide(&k, &u, &mut rng).unwrap();
+
+        let products = result.products().collect::<Vec<_>>();
+        assert_eq!(products.len(), 2);
+        assert_eq!(
+            products[0],
+            (Some(0), &parse(r"\y.\x.x x", Classic).unwrap())
+        );
+        assert_eq!(products[1].0, Some(1));
+        assert!(result.to_string().starts_with("rule 0 made "));
+    }
+
+    #[test]
+    fn reduction_times_out() {
+        let omega = parse(r"(\x.x x) (\x.x x)", Classic).unwrap();
+        let reduce = |timeout| {
+            let limits = ReductionLimits {
+                timeout,
+                ..ReductionLimits::new(lambda_calculus::HAP, 1000, 100)
+            };
+            reduce_within(&mut omega.clone(), &limits)
+        };
+        assert_eq!(
+            reduce(None),
+            ReductionOutcome::StepLimit {
+                steps: 1000,
+                size: term_size(&omega)
+            }
+        );
+        assert!(matches!(
+            reduce(Some(Duration::ZERO)),
+            ReductionOutcome::Timeout { .. }
+        ));
+
+        let mut i = parse(r"(\x.x) (\x.x)", Classic).unwrap();
+        assert_eq!(
+            reduce_within(
+                &mut i,
+                &ReductionLimits::new(lambda_calculus::HAP, 1000, 100)
+            ),
+            ReductionOutcome::Normalized { steps: 1 }
+        );
+    }
+
+    #[test]
+    fn normal_form_on_the_last_allowed_step_is_not_cut_off() {
+        let expr = parse(r"(\x.x) ((\x.x) (\x.x))", Classic).unwrap();
+        let reduce = |steps| {
+            reduce_within(
+                &mut expr.clone(),
+                &ReductionLimits::new(lambda_calculus::HAP, steps, 100),
+            )
+        };
+        assert_eq!(reduce(2), ReductionOutcome::Normalized { steps: 2 });
+        assert!(matches!(
+            reduce(1),
+            ReductionOutcome::StepLimit { steps: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn exploding_term_hits_the_size_limit() {
+        let mut expr = parse(r"(\x.x x x) (\x.x x x)", Classic).unwrap();
+        let limits = ReductionLimits {
+            check_interval: 10,
+            ..ReductionLimits::new(lambda_calculus::HAP, 100000, 1000)
+        };
+        let ReductionOutcome::SizeLimit { steps, size } = reduce_within(&mut expr, &limits) else {
+            panic!("reduction should hit the size limit");
+        };
+        // Each step adds a copy of `\x.x x x`, so ten steps can only add a few dozen nodes
+        assert!(size < 1200 && steps < 1000);
+        assert_eq!(size, term_size(&expr));
+    }
+
+    #[test]
+    fn satisfied_tests_can_be_removed() {
+        let mut soup = LambdaSoup::new();
+        let (i, k) = (r"\x.x", r"\x.\y.x");
+        soup.add_lambda_expressions([i, k].iter().map(|e| parse(e, Classic).unwrap()));
+        // Pass `true` and `false` to the candidate, and check whether it returns `true`
+        let tests = [r"\f. f (\a.\b.a)", r"\f. f (\a.\b.b)"];
+        soup.add_test_expressions(tests.iter().map(|e| parse(e, Classic).unwrap()));
+
+        assert_eq!(soup.molecules().count(), 2);
+        assert_eq!(soup.test_expressions().count(), 2);
+
+        assert_eq!(
+            soup.remove_tests_satisfied_by(&parse(i, Classic).unwrap()),
+            1
+        );
+        let remaining = soup.test_expressions().cloned().collect::<Vec<_>>();
+        assert_eq!(remaining, [parse(tests[1], Classic).unwrap()]);
+        assert_eq!(soup.molecules().count(), 2);
     }
 }
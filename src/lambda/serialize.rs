@@ -0,0 +1,93 @@
+//! Serde adapters for `lambda_calculus::Term`, which does not implement serde itself. Terms are
+//! written out in their de Bruijn form, so free variables survive a round trip unchanged. Use with
+//! `#[serde(with = "crate::lambda::serialize")]`, or `crate::lambda::serialize::vec` for
+//! `Vec<Term>` fields.
+
+use lambda_calculus::Term;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+struct TermRef<'a>(&'a Term);
+
+impl Serialize for TermRef<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.0 {
+            Term::Var(i) => serializer.serialize_newtype_variant("Term", 0, "Var", i),
+            Term::Abs(body) => {
+                serializer.serialize_newtype_variant("Term", 1, "Abs", &TermRef(body))
+            }
+            Term::App(boxed) => {
+                let (ref lhs, ref rhs) = **boxed;
+                serializer.serialize_newtype_variant(
+                    "Term",
+                    2,
+                    "App",
+                    &(TermRef(lhs), TermRef(rhs)),
+                )
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Term")]
+enum TermOwned {
+    Var(usize),
+    Abs(Box<TermOwned>),
+    App(Box<(TermOwned, TermOwned)>),
+}
+
+impl From<TermOwned> for Term {
+    fn from(t: TermOwned) -> Self {
+        match t {
+            TermOwned::Var(i) => Term::Var(i),
+            TermOwned::Abs(body) => Term::Abs(Box::new(Term::from(*body))),
+            TermOwned::App(boxed) => {
+                let (lhs, rhs) = *boxed;
+                Term::App(Box::new((Term::from(lhs), Term::from(rhs))))
+            }
+        }
+    }
+}
+
+pub fn serialize<S>(term: &Term, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    TermRef(term).serialize(serializer)
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Term, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    TermOwned::deserialize(deserializer).map(Term::from)
+}
+
+/// The same adapter, for `Vec<Term>`.
+pub mod vec {
+    use super::{TermOwned, TermRef};
+    use lambda_calculus::Term;
+    use serde::{ser::SerializeSeq, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(terms: &[Term], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(terms.len()))?;
+        for term in terms {
+            seq.serialize_element(&TermRef(term))?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Term>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let terms = Vec::<TermOwned>::deserialize(deserializer)?;
+        Ok(terms.into_iter().map(Term::from).collect())
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use lambda_calculus::Term;
+
+use crate::config;
+use crate::lambda::recursive::{LambdaParticle, LambdaSoup};
+
+/// An immutable capture of the expressions in a `LambdaSoup` at one point in time. Each
+/// expression is held behind an `Arc` shared with the soup, and the list of them behind an `Arc`
+/// shared with clones of the snapshot.
+#[derive(Debug, Clone)]
+pub struct SoupSnapshot {
+    particles: Arc<[Arc<LambdaParticle>]>,
+    step: usize,
+    config: config::Reactor,
+}
+
+/// The difference between two snapshots of a soup. Expressions are counted with multiplicity:
+/// if a term had three copies in the earlier snapshot and one in the later, then one copy
+/// persisted and two were removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    pub added: Vec<Term>,
+    pub removed: Vec<Term>,
+    pub persisted: Vec<Term>,
+}
+
+impl SoupSnapshot {
+    /// The expressions in the soup when the snapshot was taken, in the order the soup held them.
+    pub fn terms(&self) -> impl Iterator<Item = &Term> {
+        self.particles.iter().map(|p| &p.expr)
+    }
+
+    /// The number of collisions the soup had gone through when the snapshot was taken.
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// The config of the soup when the snapshot was taken. See `LambdaSoup::config`.
+    pub fn config(&self) -> &config::Reactor {
+        &self.config
+    }
+
+    /// Compare this snapshot with a `later` one. Terms in each list appear in the order they
+    /// are stored in the snapshot they come from.
+    pub fn diff(&self, later: &SoupSnapshot) -> SnapshotDiff {
+        let mut diff = SnapshotDiff::default();
+
+        let mut unmatched = counts(later.terms());
+        for term in self.terms() {
+            match unmatched.get_mut(term) {
+                Some(n) if *n > 0 => {
+                    *n -= 1;
+                    diff.persisted.push(term.clone());
+                }
+                _ => diff.removed.push(term.clone()),
+            }
+        }
+
+        let mut unmatched = counts(self.terms());
+        for term in later.terms() {
+            match unmatched.get_mut(term) {
+                Some(n) if *n > 0 => *n -= 1,
+                _ => diff.added.push(term.clone()),
+            }
+        }
+
+        diff
+    }
+}
+
+fn counts<'a>(terms: impl Iterator<Item = &'a Term>) -> HashMap<&'a Term, usize> {
+    let mut map = HashMap::new();
+    for term in terms {
+        *map.entry(term).or_default() += 1;
+    }
+    map
+}
+
+impl LambdaSoup {
+    /// Capture the current expressions, collision count and config of the soup. The soup and
+    /// its snapshots share the expressions, so a snapshot copies none of them. Only the first
+    /// snapshot after the soup changes builds a new list of the shared expressions; until the
+    /// next change, further snapshots reuse it.
+    pub fn snapshot(&self) -> SoupSnapshot {
+        SoupSnapshot {
+            particles: self.expressions.shared(),
+            step: self.collisions(),
+            config: self.config(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use lambda_calculus::{parse, Classic, Term};
+
+    use crate::lambda::recursive::LambdaSoup;
+
+    fn term(s: &str) -> Term {
+        parse(s, Classic).unwrap()
+    }
+
+    #[test]
+    fn diff_counts_copies() {
+        let (k, i, s) = (r"\x.\y.x", r"\x.x", r"\x.\y.\z.x z (y z)");
+        let mut soup = LambdaSoup::new();
+        soup.add_lambda_expressions([k, k, i].map(term));
+        let before = soup.snapshot();
+
+        soup.remove_isomorphic_to(&term(k));
+        soup.add_lambda_expressions([k, s].map(term));
+        let diff = before.diff(&soup.snapshot());
+
+        assert_eq!(diff.persisted, [k, i].map(term));
+        assert_eq!(diff.removed, [term(k)]);
+        assert_eq!(diff.added, [term(s)]);
+    }
+
+    #[test]
+    fn snapshots_share_expressions_with_the_soup() {
+        let mut soup = LambdaSoup::new();
+        soup.add_lambda_expressions([r"\x.x", r"\x.\y.x"].map(term));
+        let (a, b) = (soup.snapshot(), soup.snapshot());
+        assert!(Arc::ptr_eq(&a.particles, &b.particles));
+
+        soup.add_rule(r"\x.\y.y x").unwrap();
+        soup.add_lambda_expressions([term(r"\x.x")]);
+        let c = soup.snapshot();
+        assert!(!Arc::ptr_eq(&a.particles, &c.particles));
+        assert!(Arc::ptr_eq(&a.particles[0], &c.particles[0]));
+        assert_eq!((a.terms().count(), c.terms().count()), (2, 3));
+        assert_eq!(a.config().rules.len() + 1, c.config().rules.len());
+    }
+}
@@ -10,11 +10,20 @@ pub mod generators;
 /// Main AlChemy simulation module
 pub mod supercollider;
 
+/// Ancestry of the expressions in a soup
+pub mod lineage;
+
 /// Experimental stuff
 pub mod experiments;
 
 /// Utilities
 pub mod utils;
 
+/// CSV import and export of expressions and simulation results
+pub mod export;
+
+/// Reaction networks built from recorded reactions
+pub mod network;
+
 /// Lambda-calculus stuff
 pub mod lambda;
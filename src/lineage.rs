@@ -0,0 +1,217 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+/// The reaction that made an expression: the ids of its parents, and the index of the rule
+/// that combined them, if the collider reports one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Origin {
+    pub left: usize,
+    pub right: usize,
+    pub rule: Option<usize>,
+}
+
+/// What a soup remembers about an expression it has held.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LineageRecord<P> {
+    pub particle: P,
+
+    /// Number of collisions the soup had gone through when the expression was added
+    pub birth: usize,
+
+    /// `None` for expressions that were added from outside the soup rather than made in it
+    pub origin: Option<Origin>,
+}
+
+/// Side table of lineage records, keyed by expression id. Records outlive the expressions they
+/// describe, so the ancestry of a living expression can be traced through evicted ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Lineage<P> {
+    records: HashMap<usize, LineageRecord<P>>,
+
+    /// Keep only records within this many generations of a living expression
+    max_depth: Option<usize>,
+
+    /// Size of the table right after it was last pruned
+    pruned_size: usize,
+}
+
+/// The ancestry of an expression. Ancestors shared between several branches of the tree are
+/// shared between them in memory as well.
+#[derive(Debug, Clone)]
+pub struct LineageTree<P> {
+    pub id: usize,
+    pub particle: P,
+    pub birth: usize,
+    pub origin: Option<Origin>,
+
+    /// The trees of the left and right parents. `None` if the expression was added from outside
+    /// the soup, or if the records of its parents have been pruned.
+    pub parents: Option<Parents<P>>,
+}
+
+/// The left and right parents of an expression.
+pub type Parents<P> = (Rc<LineageTree<P>>, Rc<LineageTree<P>>);
+
+impl<P: Clone> Lineage<P> {
+    pub(crate) fn new(max_depth: Option<usize>) -> Self {
+        Lineage {
+            records: HashMap::new(),
+            max_depth,
+            pruned_size: 0,
+        }
+    }
+
+    /// An empty table with the same settings.
+    pub(crate) fn emptied(&self) -> Self {
+        Lineage::new(self.max_depth)
+    }
+
+    pub(crate) fn record(&mut self, id: usize, record: LineageRecord<P>) {
+        self.records.insert(id, record);
+    }
+
+    /// Drop the records that are more than `max_depth` generations away from every expression
+    /// in `living`, once the table has doubled in size since it was last pruned. This keeps the
+    /// amortized cost of pruning constant per record.
+    pub(crate) fn prune(&mut self, living: impl Iterator<Item = usize>) {
+        let Some(max_depth) = self.max_depth else {
+            return;
+        };
+        if self.records.len() < 2 * self.pruned_size.max(64) {
+            return;
+        }
+
+        let mut keep = HashSet::new();
+        let mut frontier = living.collect::<Vec<_>>();
+        for _ in 0..=max_depth {
+            let mut next = Vec::new();
+            for id in frontier {
+                if !keep.insert(id) {
+                    continue;
+                }
+                if let Some(Origin { left, right, .. }) =
+                    self.records.get(&id).and_then(|r| r.origin)
+                {
+                    next.extend([left, right]);
+                }
+            }
+            frontier = next;
+        }
+
+        self.records.retain(|id, _| keep.contains(id));
+        self.pruned_size = self.records.len();
+    }
+
+    /// Build the ancestry of the expression with the given `id`, if there is a record of it.
+    pub(crate) fn tree(&self, id: usize) -> Option<Rc<LineageTree<P>>> {
+        self.tree_h(id, &mut HashMap::new())
+    }
+
+    fn tree_h(
+        &self,
+        id: usize,
+        built: &mut HashMap<usize, Rc<LineageTree<P>>>,
+    ) -> Option<Rc<LineageTree<P>>> {
+        if let Some(tree) = built.get(&id) {
+            return Some(tree.clone());
+        }
+        let record = self.records.get(&id)?;
+        let parents = record.origin.and_then(|origin| {
+            let left = self.tree_h(origin.left, built)?;
+            let right = self.tree_h(origin.right, built)?;
+            Some((left, right))
+        });
+        let tree = Rc::new(LineageTree {
+            id,
+            particle: record.particle.clone(),
+            birth: record.birth,
+            origin: record.origin,
+            parents,
+        });
+        built.insert(id, tree.clone());
+        Some(tree)
+    }
+}
+
+/// How an expression was first made: its two parents, and the index of the rule that combined
+/// them, if the collider reports one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Derivation<P> {
+    pub left: P,
+    pub right: P,
+    pub rule: Option<usize>,
+}
+
+/// The first derivation of every distinct expression a soup has made. Unlike `Lineage`, this is
+/// keyed by the expression itself rather than by a particular copy of it, so every copy of an
+/// expression shares one record, and records are never pruned. Expressions that were only ever
+/// added from outside the soup have no record.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenealogyTree<P> {
+    #[serde(bound(deserialize = "P: Deserialize<'de> + Hash + Eq"))]
+    derivations: HashMap<P, Derivation<P>>,
+}
+
+impl<P: Hash + Eq + Clone> GenealogyTree<P> {
+    pub(crate) fn new() -> Self {
+        GenealogyTree {
+            derivations: HashMap::new(),
+        }
+    }
+
+    /// Record that `child` was made from `left` and `right`, unless it was made before, or is a
+    /// copy of one of its parents.
+    pub(crate) fn record(&mut self, child: &P, left: &P, right: &P, rule: Option<usize>) {
+        if child == left || child == right || self.derivations.contains_key(child) {
+            return;
+        }
+        let derivation = Derivation {
+            left: left.clone(),
+            right: right.clone(),
+            rule,
+        };
+        self.derivations.insert(child.clone(), derivation);
+    }
+
+    /// How `particle` was first made, or `None` if the soup never made it.
+    pub fn derivation(&self, particle: &P) -> Option<&Derivation<P>> {
+        self.derivations.get(particle)
+    }
+
+    /// The number of distinct expressions with a recorded derivation.
+    pub fn len(&self) -> usize {
+        self.derivations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.derivations.is_empty()
+    }
+
+    /// The distinct ancestors of `particle` up to `depth` generations back, nearest first: its
+    /// parents, then their parents, and so on. `particle` itself is left out, even if it is
+    /// its own ancestor.
+    pub fn ancestors(&self, particle: &P, depth: usize) -> Vec<P> {
+        let mut seen = HashSet::from([particle]);
+        let mut ancestors = Vec::new();
+        let mut frontier = vec![particle];
+        for _ in 0..depth {
+            let mut next = Vec::new();
+            for p in frontier {
+                let Some(d) = self.derivations.get(p) else {
+                    continue;
+                };
+                for parent in [&d.left, &d.right] {
+                    if seen.insert(parent) {
+                        ancestors.push(parent.clone());
+                        next.push(parent);
+                    }
+                }
+            }
+            frontier = next;
+        }
+        ancestors
+    }
+}
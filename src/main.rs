@@ -6,7 +6,8 @@ use experiments::{
 use generators::BTreeGen;
 use lambda_calculus::Term;
 use std::fs::{read_to_string, File};
-use std::io::Write;
+use std::io::{self, Write};
+use std::path::Path;
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 pub enum Experiment {
@@ -14,6 +15,7 @@ pub enum Experiment {
     EntropyAndFailures,
     SyncEntropyAndFailures,
     EntropyTimeSeries,
+    EntropyTimeSeriesWithInflow,
 
     // search_by_behavior.rs
     XorsetSearch,
@@ -21,11 +23,14 @@ pub enum Experiment {
 
     // distribution.rs
     DistributionTimeSeries,
+    SizeDistributionTimeSeries,
 
     // magic_test_function.rs
     AddSearchNoTest,
     AddSearchWithTest,
     SuccSearchWithTest,
+    MulSearchWithTest,
+    FactSearchWithTest,
 
     // kinetics.rs
     SuccKinetics,
@@ -64,8 +69,9 @@ struct Cli {
     #[arg(short, long)]
     run_limit: Option<usize>,
 
-    /// Explicit path to configuration file
-    #[arg(short, long)]
+    /// Explicit path to configuration file. Files ending in `.toml` are read as TOML, and all
+    /// others as JSON.
+    #[arg(short, long, visible_alias = "config")]
     config_file: Option<String>,
 
     /// Dump out the current config and exit
@@ -95,8 +101,13 @@ struct Cli {
 
 fn get_config(cli: &Cli) -> std::io::Result<config::Config> {
     let mut config = if let Some(filename) = &cli.config_file {
-        let contents = read_to_string(filename)?;
-        config::Config::from_config_str(&contents)
+        let path = Path::new(filename);
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            config::Config::from_toml_file(path).map_err(config_error)?
+        } else {
+            let contents = read_to_string(filename)?;
+            config::Config::from_config_str(&contents)
+        }
     } else {
         config::Config::new()
     };
@@ -117,12 +128,29 @@ fn get_config(cli: &Cli) -> std::io::Result<config::Config> {
     Ok(config)
 }
 
-pub fn generate_expressions_and_seed_soup(cfg: &config::Config) -> lambda::recursive::LambdaSoup {
+/// Report a config that can't be read or used as an I/O error, with the message of the config
+/// error.
+fn config_error(error: config::ConfigError) -> io::Error {
+    match error {
+        config::ConfigError::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+    }
+}
+
+/// Build a soup from `cfg`, and seed it with `cfg.sample_size` generated expressions. Fails if the
+/// reactor config can't make a soup, such as when a reaction rule does not parse.
+pub fn generate_expressions_and_seed_soup(
+    cfg: &config::Config,
+) -> Result<lambda::recursive::LambdaSoup, config::ConfigError> {
     let expressions = match &cfg.generator_config {
         config::Generator::BTree(gen_cfg) => {
             let mut gen = generators::BTreeGen::from_config(gen_cfg);
             gen.generate_n(cfg.sample_size)
         }
+        config::Generator::SKI(gen_cfg) => {
+            let mut gen = generators::SKIGen::from_config(gen_cfg);
+            gen.generate_n(cfg.sample_size)
+        }
         config::Generator::Fontana(gen_cfg) => {
             let gen = generators::FontanaGen::from_config(gen_cfg);
             std::iter::from_fn(move || gen.generate())
@@ -130,9 +158,9 @@ pub fn generate_expressions_and_seed_soup(cfg: &config::Config) -> lambda::recur
                 .collect::<Vec<Term>>()
         }
     };
-    let mut soup = lambda::recursive::LambdaSoup::from_config(&cfg.reactor_config);
+    let mut soup = lambda::recursive::LambdaSoup::try_from_config(&cfg.reactor_config)?;
     soup.add_lambda_expressions(expressions);
-    soup
+    Ok(soup)
 }
 
 fn main() -> std::io::Result<()> {
@@ -160,19 +188,33 @@ fn main() -> std::io::Result<()> {
         return Ok(());
     }
 
-    if let Some(e) = cli.experiment {
+    // An experiment named on the command line takes precedence over one in the config file
+    let experiment = match (cli.experiment, &config.experiment) {
+        (Some(e), _) => Some(e),
+        (None, Some(section)) => Some(
+            Experiment::from_str(&section.name, true)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        ),
+        (None, None) => None,
+    };
+
+    if let Some(e) = experiment {
         match e {
             Experiment::EntropyAndFailures => entropy::entropy_and_failures(),
             Experiment::SyncEntropyAndFailures => entropy::sync_entropy_and_failures(),
             Experiment::EntropyTimeSeries => entropy::entropy_time_series(),
+            Experiment::EntropyTimeSeriesWithInflow => entropy::entropy_time_series_with_inflow(),
 
             Experiment::XorsetSearch => search_by_behavior::look_for_xorset(),
             Experiment::NotXorsetSearch => search_by_behavior::look_for_not_xorset(),
 
             Experiment::DistributionTimeSeries => distribution::one_sample_with_dist(),
+            Experiment::SizeDistributionTimeSeries => distribution::one_sample_with_size_dist(),
 
             Experiment::AddSearchWithTest => magic_test_function::add_search_with_test(),
             Experiment::SuccSearchWithTest => magic_test_function::succ_search_with_test(),
+            Experiment::MulSearchWithTest => magic_test_function::mul_search_with_test(),
+            Experiment::FactSearchWithTest => magic_test_function::fact_search_with_test(),
             Experiment::AddSearchNoTest => magic_test_function::add_search_no_test(),
 
             Experiment::SuccKinetics => kinetics::kinetic_succ_experiment(),
@@ -219,12 +261,13 @@ fn main() -> std::io::Result<()> {
     }
 
     let mut soup = if cli.read_stdin {
-        let mut soup = lambda::recursive::LambdaSoup::from_config(&config.reactor_config);
+        let mut soup = lambda::recursive::LambdaSoup::try_from_config(&config.reactor_config)
+            .map_err(config_error)?;
         let expressions = utils::read_inputs();
         soup.add_lambda_expressions(expressions);
         soup
     } else {
-        generate_expressions_and_seed_soup(&config)
+        generate_expressions_and_seed_soup(&config).map_err(config_error)?
     };
 
     if let Some(polling_interval) = config.polling_interval {
@@ -0,0 +1,448 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io;
+
+use lambda_calculus::{app, Term};
+
+use crate::lambda::canonical::canonical_hash;
+use crate::lambda::recursive::{reduce_within, LambdaCollisionOk, LambdaParticle, LambdaSoup};
+use crate::supercollider::ReactionTape;
+
+/// An alpha-equivalence class of expressions in a reaction network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReactionNode {
+    /// Canonical form of the class
+    pub term: Term,
+
+    /// Number of expressions in the class when the graph was built from a soup, or `0` if it
+    /// was built from a tape alone
+    pub population: usize,
+
+    /// Index of the first recorded collision the class took part in, as reactant or product
+    pub first_seen: usize,
+}
+
+/// A reaction that fired at least once: two reactants that made a product, by node index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReactionEdge {
+    pub left: usize,
+    pub right: usize,
+    pub product: usize,
+
+    /// Index of the reaction rule that made the product, or `None` for a test expression
+    pub rule: Option<usize>,
+
+    /// Number of times the reaction fired
+    pub count: usize,
+}
+
+/// The reaction network of a soup, built from its tape. Nodes are alpha-equivalence classes
+/// of expressions, and each edge joins a pair of reactants to one of their products.
+#[derive(Debug, Clone, Default)]
+pub struct ReactionGraph {
+    nodes: Vec<ReactionNode>,
+    index: HashMap<Term, usize>,
+    edges: Vec<ReactionEdge>,
+}
+
+impl ReactionGraph {
+    /// Build the network of the reactions on `tape`. Nodes are numbered in order of first
+    /// appearance, and edges in order of first firing. The same product made from the same
+    /// reactants by two different rules gives two edges.
+    pub fn from_tape(tape: &ReactionTape<LambdaParticle, LambdaCollisionOk>) -> Self {
+        let mut graph = ReactionGraph::default();
+        let mut edge_index = HashMap::<(usize, usize, usize, Option<usize>), usize>::new();
+        for record in &tape.reactions {
+            let left = graph.node(record.left.get_underlying_term(), record.step);
+            let right = graph.node(record.right.get_underlying_term(), record.step);
+            for (rule, term) in record.result.products() {
+                let product = graph.node(term, record.step);
+                let key = (left, right, product, rule);
+                let i = *edge_index.entry(key).or_insert_with(|| {
+                    graph.edges.push(ReactionEdge {
+                        left,
+                        right,
+                        product,
+                        rule,
+                        count: 0,
+                    });
+                    graph.edges.len() - 1
+                });
+                graph.edges[i].count += 1;
+            }
+        }
+        graph
+    }
+
+    fn node(&mut self, term: &Term, step: usize) -> usize {
+        if let Some(&i) = self.index.get(term) {
+            return i;
+        }
+        self.nodes.push(ReactionNode {
+            term: term.clone(),
+            population: 0,
+            first_seen: step,
+        });
+        self.index.insert(term.clone(), self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    pub fn nodes(&self) -> &[ReactionNode] {
+        &self.nodes
+    }
+
+    pub fn edges(&self) -> &[ReactionEdge] {
+        &self.edges
+    }
+
+    /// The index of the node of the class of `term`, if it took part in any reaction.
+    pub fn node_of(&self, term: &Term) -> Option<usize> {
+        self.index.get(term).copied()
+    }
+
+    /// Remove the edges of reactions that fired fewer than `min_count` times. Nodes are kept,
+    /// so node indices stay valid.
+    pub fn prune(&mut self, min_count: usize) {
+        self.edges.retain(|e| e.count >= min_count);
+    }
+
+    /// The strongly connected components of the graph in which each reactant points to its
+    /// products. A component of more than one node, or a node that helps make itself, is a
+    /// candidate autocatalytic core. Components are listed so that every edge between two
+    /// components points from a later one to an earlier one; nodes within a component are
+    /// sorted.
+    pub fn strongly_connected_components(&self) -> Vec<Vec<usize>> {
+        let mut successors = vec![Vec::new(); self.nodes.len()];
+        for e in &self.edges {
+            successors[e.left].push(e.product);
+            successors[e.right].push(e.product);
+        }
+        Tarjan::new(&successors).run()
+    }
+
+    /// The graph in Graphviz DOT format; see `write_dot`.
+    pub fn to_dot(&self) -> String {
+        let mut dot = Vec::new();
+        self.write_dot(&mut dot)
+            .expect("writing to a Vec cannot fail");
+        String::from_utf8(dot).expect("DOT output is UTF-8")
+    }
+
+    /// Write the graph in Graphviz DOT format. Each class is a box labelled with its
+    /// canonical form, population and first appearance. Each reaction is a point joined to
+    /// its reactants, from which an edge to the product is labelled with the rule and how often
+    /// it fired. Nodes without edges are left out, so pruning the graph first also trims the
+    /// drawing.
+    pub fn write_dot(&self, writer: &mut dyn io::Write) -> io::Result<()> {
+        let mut used = vec![false; self.nodes.len()];
+        for e in &self.edges {
+            used[e.left] = true;
+            used[e.right] = true;
+            used[e.product] = true;
+        }
+
+        writeln!(writer, "digraph reactions {{")?;
+        for (i, node) in self.nodes.iter().enumerate().filter(|(i, _)| used[*i]) {
+            let label = format!(
+                "{}\\npopulation {}\\nfirst seen {}",
+                escape(&node.term.to_string()),
+                node.population,
+                node.first_seen
+            );
+            writeln!(writer, "  n{i} [shape=box, label=\"{label}\"];")?;
+        }
+        for (k, e) in self.edges.iter().enumerate() {
+            let rule = match e.rule {
+                Some(r) => format!("rule {r}"),
+                None => String::from("test"),
+            };
+            writeln!(writer, "  r{k} [shape=point];")?;
+            writeln!(writer, "  n{} -> r{k} [arrowhead=none];", e.left)?;
+            writeln!(writer, "  n{} -> r{k} [arrowhead=none];", e.right)?;
+            writeln!(
+                writer,
+                "  r{k} -> n{} [label=\"{rule}, fired {}\"];",
+                e.product, e.count
+            )?;
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Tarjan's strongly connected components algorithm, with an explicit stack so deep graphs
+/// don't overflow the call stack.
+struct Tarjan<'a> {
+    successors: &'a [Vec<usize>],
+    index: Vec<Option<usize>>,
+    lowlink: Vec<usize>,
+    on_stack: Vec<bool>,
+    stack: Vec<usize>,
+    next_index: usize,
+    components: Vec<Vec<usize>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn new(successors: &'a [Vec<usize>]) -> Self {
+        let n = successors.len();
+        Tarjan {
+            successors,
+            index: vec![None; n],
+            lowlink: vec![0; n],
+            on_stack: vec![false; n],
+            stack: Vec::new(),
+            next_index: 0,
+            components: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Vec<Vec<usize>> {
+        for v in 0..self.successors.len() {
+            if self.index[v].is_none() {
+                self.visit(v);
+            }
+        }
+        self.components
+    }
+
+    fn discover(&mut self, v: usize) {
+        self.index[v] = Some(self.next_index);
+        self.lowlink[v] = self.next_index;
+        self.next_index += 1;
+        self.stack.push(v);
+        self.on_stack[v] = true;
+    }
+
+    fn visit(&mut self, root: usize) {
+        // Each frame is a node and the position of the next successor to look at
+        let mut frames = vec![(root, 0)];
+        self.discover(root);
+        while let Some(&mut (v, ref mut next)) = frames.last_mut() {
+            if let Some(&w) = self.successors[v].get(*next) {
+                *next += 1;
+                match self.index[w] {
+                    None => {
+                        self.discover(w);
+                        frames.push((w, 0));
+                    }
+                    Some(i) if self.on_stack[w] => self.lowlink[v] = self.lowlink[v].min(i),
+                    Some(_) => {}
+                }
+                continue;
+            }
+
+            frames.pop();
+            if let Some(&(parent, _)) = frames.last() {
+                self.lowlink[parent] = self.lowlink[parent].min(self.lowlink[v]);
+            }
+            if Some(self.lowlink[v]) == self.index[v] {
+                let mut component = Vec::new();
+                while let Some(w) = self.stack.pop() {
+                    self.on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                component.sort_unstable();
+                self.components.push(component);
+            }
+        }
+    }
+}
+
+impl LambdaSoup {
+    /// The reaction network recorded on the tape of the soup, with the current population of
+    /// each class. Returns `None` if the soup does not record a tape; see
+    /// `config::Reactor::record_tape`.
+    pub fn reaction_graph(&self) -> Option<ReactionGraph> {
+        let mut graph = ReactionGraph::from_tape(self.tape()?);
+        for (term, count) in self.expression_counts() {
+            if let Some(i) = graph.node_of(&term) {
+                graph.nodes[i].population = count as usize;
+            }
+        }
+        Some(graph)
+    }
+
+    /// Write how the expressions of the soup derive from each other, as a Graphviz DOT digraph.
+    /// If the soup records a tape, this is its `reaction_graph` drawn by
+    /// `ReactionGraph::write_dot`. Without a tape, the classes of the current population are
+    /// drawn as unconnected nodes, most common first.
+    pub fn reaction_network_to_dot(&self, writer: &mut dyn io::Write) -> io::Result<()> {
+        if let Some(graph) = self.reaction_graph() {
+            return graph.write_dot(writer);
+        }
+        writeln!(writer, "digraph reactions {{")?;
+        for (i, term) in self.k_most_frequent_exprs(usize::MAX).iter().enumerate() {
+            writeln!(writer, "  n{i} [label=\"{}\"];", escape(&term.to_string()))?;
+        }
+        writeln!(writer, "}}")
+    }
+
+    /// Draw who reacts with whom among the `k` most common expressions, as a Graphviz DOT
+    /// digraph. Every ordered pair `(a, b)` is collided under the first reaction rule, within
+    /// the soup's reduction limits, and an edge from `a` to `b` is labelled with the product if
+    /// the product is one of the `k`. Self-loops, where an expression makes a known species
+    /// from a copy of itself, are drawn in red. Nodes are labelled with a short hash of their
+    /// class, and carry the full term as a tooltip. Statistics of the soup are not touched.
+    pub fn interaction_graph_dot(&self, k: usize) -> String {
+        let species = self.k_most_frequent_exprs(k);
+        let index = species
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t, i))
+            .collect::<HashMap<_, _>>();
+
+        let mut dot = String::from("digraph interactions {\n");
+        for (i, term) in species.iter().enumerate() {
+            let tooltip = escape(&term.to_string());
+            let label = short_hash(term);
+            writeln!(dot, "  n{i} [label=\"{label}\", tooltip=\"{tooltip}\"];").unwrap();
+        }
+        if let Some(rule) = self.rules().first() {
+            let limits = self.collider.rule_limits(0);
+            for (i, a) in species.iter().enumerate() {
+                for (j, b) in species.iter().enumerate() {
+                    let mut expr = app!(rule.clone(), a.clone(), b.clone());
+                    if !reduce_within(&mut expr, &limits).is_normalized() {
+                        continue;
+                    }
+                    let Some(&p) = index.get(&expr) else {
+                        continue;
+                    };
+                    let label = short_hash(&species[p]);
+                    let style = if i == j {
+                        ", color=red, penwidth=2"
+                    } else {
+                        ""
+                    };
+                    writeln!(dot, "  n{i} -> n{j} [label=\"{label}\"{style}];").unwrap();
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// The first eight hex digits of the canonical hash of `term`.
+fn short_hash(term: &Term) -> String {
+    format!("{:08x}", canonical_hash(term) >> 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_calculus::{parse, Classic};
+
+    use super::ReactionGraph;
+    use crate::lambda::recursive::{LambdaCollisionOk, LambdaParticle, LambdaSoup};
+    use crate::supercollider::{ReactionRecord, ReactionTape};
+
+    fn particle(s: &str) -> LambdaParticle {
+        LambdaParticle::from(parse(s, Classic).unwrap())
+    }
+
+    fn reaction(
+        step: usize,
+        left: &LambdaParticle,
+        right: &LambdaParticle,
+        products: &[&LambdaParticle],
+    ) -> ReactionRecord<LambdaParticle, LambdaCollisionOk> {
+        let n = products.len();
+        ReactionRecord {
+            step,
+            left: left.clone(),
+            right: right.clone(),
+            result: LambdaCollisionOk {
+                results: products.iter().map(|&p| p.clone()).collect(),
+                rules: vec![0; n],
+                reductions: vec![1; n],
+                sizes: vec![1; n],
+                depths: vec![1; n],
+                left_size: 1,
+                right_size: 1,
+                left_depth: 1,
+                right_depth: 1,
+            },
+        }
+    }
+
+    /// A tape on which `a` and `b` make each other, and also `c`, which makes nothing.
+    fn tape() -> ReactionTape<LambdaParticle, LambdaCollisionOk> {
+        let (a, b, c) = (particle(r"\x.\y.x"), particle(r"\x.x"), particle(r"\x.x x"));
+        ReactionTape {
+            reactions: vec![
+                reaction(0, &a, &b, &[&b]),
+                reaction(1, &b, &a, &[&a, &c]),
+                reaction(2, &a, &b, &[&b]),
+                reaction(3, &c, &c, &[]),
+            ],
+        }
+    }
+
+    #[test]
+    fn reactions_are_counted_and_pruned() {
+        let mut graph = ReactionGraph::from_tape(&tape());
+        assert_eq!(graph.nodes().len(), 3);
+        assert_eq!(graph.nodes()[2].first_seen, 1);
+        let counts = graph.edges().iter().map(|e| e.count).collect::<Vec<_>>();
+        assert_eq!(counts, [2, 1, 1]);
+
+        assert!(graph.to_dot().contains("n2 [shape=box"));
+        graph.prune(2);
+        assert_eq!(graph.edges().len(), 1);
+        assert!(!graph.to_dot().contains("n2 [shape=box"));
+    }
+
+    #[test]
+    fn reaction_network_falls_back_to_the_population() {
+        let terms = [r"\x.x", r"\x.\y.x", r"\a.a"].map(|s| parse(s, Classic).unwrap());
+        let mut soup = LambdaSoup::new();
+        soup.add_lambda_expressions(terms);
+        let mut dot = Vec::new();
+        soup.reaction_network_to_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert_eq!(dot.matches("label=").count(), 2);
+        assert!(!dot.contains("->"));
+
+        soup.tape = Some(tape());
+        let mut dot = Vec::new();
+        soup.reaction_network_to_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert_eq!(dot.matches("shape=point").count(), 3);
+        assert_eq!(dot, soup.reaction_graph().unwrap().to_dot());
+        assert!(dot.contains("[label=\"rule 0, fired 2\"]"));
+        assert_eq!(dot.matches("[label=\"rule 0, fired 1\"]").count(), 2);
+        assert!(dot.contains(&format!(
+            "n2 [shape=box, label=\"{}\\npopulation 0",
+            parse(r"\x.x x", Classic).unwrap()
+        )));
+    }
+
+    #[test]
+    fn mutual_producers_share_a_component() {
+        let graph = ReactionGraph::from_tape(&tape());
+        let components = graph.strongly_connected_components();
+        assert_eq!(components, [vec![2], vec![0, 1]]);
+    }
+
+    #[test]
+    fn interaction_graph_links_products_among_the_top_species() {
+        let mut soup = LambdaSoup::new();
+        let (i, k, u) = (r"\x.x", r"\x.\y.x", r"\x.x x");
+        let terms = [i, i, i, k, k, u].map(|s| parse(s, Classic).unwrap());
+        soup.add_lambda_expressions(terms);
+
+        // Under `\x.\y.x y`, I I is I and I K is K, while K I and K K make new species
+        let dot = soup.interaction_graph_dot(2);
+        assert_eq!(dot.matches("->").count(), 2);
+        assert!(dot.contains("n0 -> n0"));
+        assert!(dot.contains("n0 -> n1"));
+        assert_eq!(dot.matches("color=red").count(), 1);
+        assert!(!dot.contains("n2"));
+    }
+}
@@ -1,22 +1,50 @@
 use std::{
+    cmp::Reverse,
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
     fmt::{Debug, Display},
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::{self, BufReader, BufWriter, Write},
     marker::PhantomData,
+    ops::Deref,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{Arc, Mutex, OnceLock},
 };
 
-use rand::Rng;
+use futures::{future, stream, Stream};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::config::{EvictionPolicy, SelectionStrategy};
+use crate::lineage::{GenealogyTree, Lineage, LineageRecord, LineageTree, Origin};
 
 pub trait Particle {
     fn compose(&self, other: &Self) -> Self;
 
     fn is_isomorphic_to(&self, other: &Self) -> bool;
+
+    /// The number of nodes in the particle.
+    fn size(&self) -> usize;
+
+    /// The length of the longest path from the root of the particle to a leaf.
+    fn depth(&self) -> u32;
 }
 
 pub trait Collider<P, T, E>
 where
     P: Particle,
 {
-    fn collide(&self, left: P, right: P) -> Result<T, E>;
+    /// Collide `left` with `right`. The particles are borrowed, so a soup never has to copy its
+    /// reactants; colliders clone whatever parts of them they keep.
+    fn collide(&mut self, left: &P, right: &P, rng: &mut ChaCha8Rng) -> Result<T, E>;
+
+    /// Whether the collider can be written out by `Soup::save_to_path`. Colliders that hold
+    /// closures can't be.
+    fn is_saveable(&self) -> bool {
+        true
+    }
 }
 
 pub trait Residue<P>
@@ -25,85 +53,1341 @@ where
 {
     fn particles(&self) -> impl Iterator<Item = P>;
     fn count(&self) -> usize;
+
+    /// Index of the reaction rule that made the `i`th particle, if known.
+    fn rule_of(&self, _i: usize) -> Option<usize> {
+        None
+    }
+
+    /// The number of reduction steps it took to make the particles. Residues of colliders that
+    /// don't reduce their products report `0`.
+    fn reductions(&self) -> usize {
+        0
+    }
 }
 
+/// How many times a soup that requires distinct reactants redraws the second reactant before
+/// giving up on a reaction. In a soup where a fraction `f` of the expressions differ from the
+/// first reactant, a reaction is wrongly given up with probability `(1 - f)^100`; a soup with
+/// only one species always gives up.
+pub const DISTINCT_REACTANT_ATTEMPTS: usize = 100;
+
 /// The principal AlChemy object. The `Soup` struct contains a set of
 /// lambda expressions, and rules for composing and filtering them.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Soup<P, C, T, E> {
     // All of these pub(crate)s here are hacky
-    pub(crate) expressions: Vec<P>,
-    pub(crate) n_collisions: usize,
+    #[serde(bound(deserialize = "P: Deserialize<'de> + Particle + Clone + Hash + Eq"))]
+    pub(crate) expressions: Population<P>,
+    pub(crate) total_collisions: usize,
+    pub(crate) successful_collisions: usize,
     pub(crate) collider: C,
 
     pub(crate) maintain_constant_population_size: bool,
     pub(crate) discard_parents: bool,
+    pub(crate) protect_new_products: bool,
+    pub(crate) require_distinct_reactants: bool,
+    pub(crate) selection_strategy: SelectionStrategy,
+
+    /// Position of the next reactant under `SelectionStrategy::RoundRobin`
+    pub(crate) cursor: usize,
+    pub(crate) eviction_policy: EvictionPolicy,
+    pub(crate) decay_probability: f64,
+    pub(crate) decayed: usize,
+    pub(crate) max_population: Option<usize>,
+
+    pub(crate) rng: ChaCha8Rng,
+
+    pub(crate) tape: Option<ReactionTape<P, T>>,
+    pub(crate) inflow: Option<Inflow<P>>,
+
+    /// Events queued with `schedule_injection` and `schedule_removal`
+    pub(crate) schedule: Vec<ScheduledEvent<P>>,
+
+    /// When to end a simulation early, set with `halt_when_stable`
+    #[serde(skip, default = "Option::default")]
+    pub(crate) convergence: Option<Convergence<Soup<P, C, T, E>>>,
+    pub(crate) lineage: Option<Lineage<P>>,
+    #[serde(bound(deserialize = "P: Deserialize<'de> + Hash + Eq"))]
+    pub(crate) genealogy: Option<GenealogyTree<P>>,
+
+    /// Ages of the expressions most recently displaced from the soup
+    pub(crate) removed_ages: AgeBuffer,
+
+    /// Work done since the last poll, or the last `reset_reduction_stats`
+    pub(crate) since_poll: ReductionWork,
+
+    /// Id of the next expression added to the soup
+    pub(crate) next_id: usize,
+
+    // TODO: Figure out how to get rid of these horrible phantomdatas
+    pub(crate) t: PhantomData<T>,
+    pub(crate) e: PhantomData<E>,
+}
+
+/// A steady supply of fresh expressions into a soup, such as a food set.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Inflow<P> {
+    rate: f64,
+    source: InflowSource<P>,
+
+    /// Fractional injections carried over to the next collision
+    pending: f64,
+}
+
+/// Makes the generators of a generated inflow.
+type GeneratorFactory<P> = Arc<dyn Fn() -> Box<dyn FnMut() -> P + Send> + Send + Sync>;
+
+/// Where the expressions of an `Inflow` come from.
+#[derive(Serialize, Deserialize)]
+pub enum InflowSource<P> {
+    /// Cycle through a fixed set of expressions. `next` is the index of the next one to inject.
+    Food { food: Vec<P>, next: usize },
+
+    /// Call a generator, made by `factory`, for each expression. Each clone of the inflow gets a
+    /// fresh generator of its own from `factory`, so clones of a soup never draw from the same
+    /// generator. A soup with this inflow can't be saved to disk; `Soup::save_to_path` rejects
+    /// it.
+    #[serde(skip)]
+    Generator {
+        factory: GeneratorFactory<P>,
+        generator: Mutex<Box<dyn FnMut() -> P + Send>>,
+    },
+}
+
+impl<P: Clone> Clone for InflowSource<P> {
+    fn clone(&self) -> Self {
+        match self {
+            InflowSource::Food { food, next } => InflowSource::Food {
+                food: food.clone(),
+                next: *next,
+            },
+            InflowSource::Generator { factory, .. } => InflowSource::Generator {
+                factory: Arc::clone(factory),
+                generator: Mutex::new(factory()),
+            },
+        }
+    }
+}
+
+impl<P> Inflow<P> {
+    /// Inject the expressions of `food` in order, cycling, at `rate` expressions per collision.
+    /// A rate of `0.1` injects one expression every ten collisions.
+    pub fn food(rate: f64, food: Vec<P>) -> Self {
+        assert!(!food.is_empty(), "food set must not be empty");
+        Self::from_source(rate, InflowSource::Food { food, next: 0 })
+    }
+
+    /// Inject expressions produced by a generator made with `factory`, at `rate` expressions per
+    /// collision. Clones of the inflow, and so of its soup, each make their own generator with
+    /// `factory`, which starts over from the beginning.
+    pub fn generator<G>(rate: f64, factory: impl Fn() -> G + Send + Sync + 'static) -> Self
+    where
+        G: FnMut() -> P + Send + 'static,
+    {
+        let factory: GeneratorFactory<P> =
+            Arc::new(move || Box::new(factory()) as Box<dyn FnMut() -> P + Send>);
+        let generator = Mutex::new(factory());
+        Self::from_source(rate, InflowSource::Generator { factory, generator })
+    }
+
+    fn from_source(rate: f64, source: InflowSource<P>) -> Self {
+        assert!(rate >= 0.0, "inflow rate must not be negative");
+        Inflow {
+            rate,
+            source,
+            pending: 0.0,
+        }
+    }
+
+    /// Expected number of expressions injected per collision.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Keep one of `k` equal shares of the inflow, for one of `k` soups split from its soup.
+    fn share(&mut self, k: usize) {
+        self.rate /= k as f64;
+        self.pending /= k as f64;
+    }
+
+    /// Advance the inflow by one collision, and return the expressions to inject.
+    fn tick(&mut self) -> Vec<P>
+    where
+        P: Clone,
+    {
+        self.pending += self.rate;
+        let n = self.pending.floor();
+        self.pending -= n;
+        (0..n as usize)
+            .map(|_| match &mut self.source {
+                InflowSource::Food { food, next } => {
+                    let expr = food[*next].clone();
+                    *next = (*next + 1) % food.len();
+                    expr
+                }
+                InflowSource::Generator { generator, .. } => (generator.get_mut().unwrap())(),
+            })
+            .collect()
+    }
+}
+
+impl<P: Debug> Debug for Inflow<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = match &self.source {
+            InflowSource::Food { food, .. } => format!("{} food expressions", food.len()),
+            InflowSource::Generator { .. } => String::from("generator"),
+        };
+        f.debug_struct("Inflow")
+            .field("rate", &self.rate)
+            .field("source", &source)
+            .finish()
+    }
+}
+
+/// A criterion for ending a simulation early: the standard deviation of `measure` over the last
+/// `window` samples has fallen below `tolerance`.
+pub struct Convergence<S> {
+    measure: fn(&S) -> f32,
+    window: usize,
+    tolerance: f32,
+
+    /// The last `window` samples of `measure`, oldest first
+    recent: VecDeque<f32>,
+}
+
+impl<S> Convergence<S> {
+    /// Record a sample of the measure of `soup`, and return whether the measure has converged.
+    fn sample(&mut self, soup: &S) -> bool {
+        if self.recent.len() == self.window {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((self.measure)(soup));
+        if self.recent.len() < self.window {
+            return false;
+        }
+        let n = self.window as f32;
+        let mean = self.recent.iter().sum::<f32>() / n;
+        let variance = self.recent.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / n;
+        variance.sqrt() < self.tolerance
+    }
+}
+
+/// Clones start with no samples.
+impl<S> Clone for Convergence<S> {
+    fn clone(&self) -> Self {
+        Convergence {
+            measure: self.measure,
+            window: self.window,
+            tolerance: self.tolerance,
+            recent: VecDeque::new(),
+        }
+    }
+}
+
+impl<S> Debug for Convergence<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Convergence")
+            .field("window", &self.window)
+            .field("tolerance", &self.tolerance)
+            .field("recent", &self.recent)
+            .finish()
+    }
+}
+
+/// Why a simulation ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    /// The measure set with `halt_when_stable` converged after this many collisions.
+    Converged { collisions: usize },
+
+    /// The simulation ran for all the collisions it was asked to.
+    Exhausted,
+}
+
+/// The ages, in collisions, of the last `capacity` expressions removed from a soup, oldest
+/// removal first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AgeBuffer {
+    capacity: usize,
+    ages: VecDeque<usize>,
+}
+
+impl AgeBuffer {
+    pub(crate) fn new(capacity: usize) -> Self {
+        AgeBuffer {
+            capacity,
+            ages: VecDeque::new(),
+        }
+    }
+
+    /// An empty buffer with the same capacity.
+    pub(crate) fn emptied(&self) -> Self {
+        AgeBuffer::new(self.capacity)
+    }
+
+    fn push(&mut self, age: usize) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.ages.len() == self.capacity {
+            self.ages.pop_front();
+        }
+        self.ages.push_back(age);
+    }
+}
+
+/// Summary of the ages of a set of expressions, in collisions. Every field is `0` for an empty
+/// set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AgeDistribution {
+    pub count: usize,
+    pub min: usize,
+    pub max: usize,
+    pub mean: f64,
+    pub median: f64,
+
+    /// Number of ages in each power-of-two bucket: `histogram[0]` counts ages of `0`, and
+    /// `histogram[k]` counts ages in `[2^(k-1), 2^k)`. Ends at the bucket of `max`.
+    pub histogram: Vec<usize>,
+}
+
+impl AgeDistribution {
+    fn from_ages(mut ages: Vec<usize>) -> Self {
+        if ages.is_empty() {
+            return AgeDistribution::default();
+        }
+        ages.sort_unstable();
+        let count = ages.len();
+        let median = if count % 2 == 1 {
+            ages[count / 2] as f64
+        } else {
+            (ages[count / 2 - 1] + ages[count / 2]) as f64 / 2.0
+        };
+        let max = ages[count - 1];
+        let mut histogram = vec![0; Self::bucket(max) + 1];
+        for &age in &ages {
+            histogram[Self::bucket(age)] += 1;
+        }
+        AgeDistribution {
+            count,
+            min: ages[0],
+            max,
+            mean: ages.iter().sum::<usize>() as f64 / count as f64,
+            median,
+            histogram,
+        }
+    }
+
+    fn bucket(age: usize) -> usize {
+        (usize::BITS - age.leading_zeros()) as usize
+    }
+
+    /// Fraction of the ages that fall in each bucket of `histogram` or an earlier one.
+    pub fn cumulative(&self) -> Vec<f64> {
+        let mut total = 0;
+        self.histogram
+            .iter()
+            .map(|&n| {
+                total += n;
+                total as f64 / self.count as f64
+            })
+            .collect()
+    }
+}
+
+/// How long expressions survive in a soup. See `Soup::term_lifetime_statistics`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TermLifetimeStats {
+    /// Current ages of the expressions in the soup
+    pub live: AgeDistribution,
+
+    /// Ages at which the most recently displaced expressions were removed, as many as
+    /// `config::Reactor::removed_age_buffer` keeps
+    pub removed: AgeDistribution,
+}
+
+/// The work done by the reactions of a soup over a window of collisions. See
+/// `Soup::reduction_stats_since_last_poll`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReductionWork {
+    pub collisions: usize,
+
+    /// Collisions that produced a result
+    pub successes: usize,
+
+    /// Expressions produced by the successful collisions
+    pub products: usize,
+
+    /// Reduction steps that went into the products, as reported by `Residue::reductions`
+    pub reductions: usize,
+}
+
+impl ReductionWork {
+    /// Mean number of reduction steps per product, or `0.0` if nothing was produced.
+    pub fn mean_reductions(&self) -> f64 {
+        if self.products == 0 {
+            return 0.0;
+        }
+        self.reductions as f64 / self.products as f64
+    }
+}
+
+/// Something to do to a soup at a given step of the next simulation.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScheduledEvent<P> {
+    step: usize,
+    action: ScheduledAction<P>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+enum ScheduledAction<P> {
+    Inject(Vec<P>),
+
+    /// A soup with a scheduled removal can't be saved to disk; see `Soup::save_to_path`.
+    #[serde(skip)]
+    Remove(Arc<dyn Fn(&P) -> bool + Send + Sync>),
+}
+
+impl<P> Debug for ScheduledEvent<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let action = match &self.action {
+            ScheduledAction::Inject(particles) => format!("inject {} expressions", particles.len()),
+            ScheduledAction::Remove(_) => String::from("remove matching expressions"),
+        };
+        f.debug_struct("ScheduledEvent")
+            .field("step", &self.step)
+            .field("action", &action)
+            .finish()
+    }
+}
+
+/// An expression in a soup, together with the number of collisions the soup had seen when the
+/// expression was added, and an id unique within the soup. Only those three are saved; the
+/// cached fields are recomputed whenever an entry is deserialized.
+///
+/// The expression sits behind an `Arc`, so snapshots of the soup can share it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    from = "SavedEntry<P>",
+    bound(
+        serialize = "P: Serialize",
+        deserialize = "P: Deserialize<'de> + Particle + Hash"
+    )
+)]
+pub(crate) struct Entry<P> {
+    #[serde(serialize_with = "serialize_shared")]
+    pub(crate) particle: Arc<P>,
+    pub(crate) birth: usize,
+    pub(crate) id: usize,
+
+    /// `Particle::size` of the expression, computed once when it is added
+    #[serde(skip)]
+    pub(crate) size: usize,
+
+    /// `Particle::depth` of the expression, computed once when it is added
+    #[serde(skip)]
+    pub(crate) depth: u32,
+
+    /// Hash of the expression, computed once when it is added. Not saved with the soup, since
+    /// `DefaultHasher` may change between Rust releases.
+    #[serde(skip)]
+    pub(crate) hash: u64,
+}
+
+impl<P: Particle + Hash> Entry<P> {
+    fn new(particle: P, birth: usize, id: usize) -> Self {
+        let mut hasher = DefaultHasher::new();
+        particle.hash(&mut hasher);
+        Entry {
+            size: particle.size(),
+            depth: particle.depth(),
+            hash: hasher.finish(),
+            particle: Arc::new(particle),
+            birth,
+            id,
+        }
+    }
+}
+
+/// Serialize a shared expression as the expression itself.
+fn serialize_shared<P: Serialize, S: serde::Serializer>(
+    particle: &Arc<P>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    P::serialize(particle, serializer)
+}
+
+/// The saved fields of an `Entry`.
+#[derive(Deserialize)]
+struct SavedEntry<P> {
+    particle: P,
+    birth: usize,
+    id: usize,
+}
+
+impl<P: Particle + Hash> From<SavedEntry<P>> for Entry<P> {
+    fn from(saved: SavedEntry<P>) -> Self {
+        Entry::new(saved.particle, saved.birth, saved.id)
+    }
+}
+
+/// The expressions of a soup, with the copies of each distinct expression kept track of as
+/// expressions are added and removed, so counting them doesn't need a pass over the whole soup.
+/// Reads go through the slice of entries; every change goes through the methods here, which
+/// update the copies.
+///
+/// Only the entries are saved. The copies are rebuilt from them whenever a population is
+/// deserialized, however that happens.
+///
+/// Knowing where the copies of each distinct expression sit also lets weighted selection draw an
+/// expression by its count and then one of its copies without grouping the soup first.
+///
+/// The particles can also be handed out as one shared slice, for snapshots. The slice is built
+/// the first time it is asked for, and dropped at the next change. It holds the same `Arc`s as
+/// the entries, so building it copies no expression.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(
+    from = "Vec<Entry<P>>",
+    bound(deserialize = "P: Deserialize<'de> + Particle + Clone + Hash + Eq")
+)]
+pub(crate) struct Population<P> {
+    entries: Vec<Entry<P>>,
+
+    /// Positions of the copies of each distinct expression, whose number is the count of the
+    /// expression. The order depends only on the sequence of changes, so draws from it are
+    /// reproducible for a given seed.
+    species: Vec<Vec<usize>>,
+
+    /// Indices into `species` of the distinct expressions with each hash, as cached on their
+    /// entries, so no expression is cloned or hashed again. Expressions whose hashes collide are
+    /// told apart by comparing them, as `Soup::groups` does.
+    kinds: HashMap<u64, Vec<usize>>,
+
+    /// For each entry, its index into `species`, and its position in that list of copies
+    slots: Vec<(usize, usize)>,
+
+    shared: OnceLock<Arc<[Arc<P>]>>,
+}
+
+impl<P: Serialize> Serialize for Population<P> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.entries.serialize(serializer)
+    }
+}
+
+impl<P: Particle + Clone + Hash + Eq> From<Vec<Entry<P>>> for Population<P> {
+    fn from(entries: Vec<Entry<P>>) -> Self {
+        let mut population = Population::new();
+        population.extend(entries);
+        population
+    }
+}
+
+impl<P: Particle + Clone + Hash + Eq> Population<P> {
+    pub(crate) fn new() -> Self {
+        Population {
+            entries: Vec::new(),
+            species: Vec::new(),
+            kinds: HashMap::new(),
+            slots: Vec::new(),
+            shared: OnceLock::new(),
+        }
+    }
+
+    /// Each distinct expression, with the number of copies of it.
+    pub(crate) fn counts(&self) -> impl Iterator<Item = (&P, usize)> {
+        self.species
+            .iter()
+            .map(|copies| (&*self.entries[copies[0]].particle, copies.len()))
+    }
+
+    /// The particles, in order. Until the population next changes, every call returns the
+    /// same slice.
+    pub(crate) fn shared(&self) -> Arc<[Arc<P>]> {
+        let particles = self.shared.get_or_init(|| {
+            self.entries
+                .iter()
+                .map(|e| Arc::clone(&e.particle))
+                .collect()
+        });
+        Arc::clone(particles)
+    }
+
+    pub(crate) fn push(&mut self, entry: Entry<P>) {
+        let k = self.entries.len();
+        let s = self.species_of(&entry).unwrap_or_else(|| {
+            self.species.push(Vec::new());
+            let s = self.species.len() - 1;
+            self.kinds.entry(entry.hash).or_default().push(s);
+            s
+        });
+        self.species[s].push(k);
+        self.slots.push((s, self.species[s].len() - 1));
+        self.entries.push(entry);
+        self.shared.take();
+    }
+
+    pub(crate) fn swap_remove(&mut self, k: usize) -> Entry<P> {
+        self.detach(k);
+        let entry = self.entries.swap_remove(k);
+        self.slots.swap_remove(k);
+        if let Some(&(s, m)) = self.slots.get(k) {
+            self.species[s][m] = k;
+        }
+        self.shared.take();
+        entry
+    }
+
+    pub(crate) fn swap(&mut self, i: usize, j: usize) {
+        self.entries.swap(i, j);
+        self.slots.swap(i, j);
+        for k in [i, j] {
+            let (s, m) = self.slots[k];
+            self.species[s][m] = k;
+        }
+        self.shared.take();
+    }
+
+    pub(crate) fn retain(&mut self, mut f: impl FnMut(&Entry<P>) -> bool) {
+        let mut entries = std::mem::take(&mut self.entries);
+        entries.retain(|e| f(e));
+        *self = Population::from(entries);
+    }
+
+    /// Draw the position of an entry, with each entry weighted by `weight` of the number of
+    /// copies of its expression, leaving out the entry at `skip`. The expression is drawn first,
+    /// weighted by its copies times their weight, then one of its copies uniformly. Returns
+    /// `None` if no entry has a positive weight.
+    pub(crate) fn sample_weighted(
+        &self,
+        weight: impl Fn(usize) -> f64,
+        skip: Option<usize>,
+        rng: &mut impl Rng,
+    ) -> Option<usize> {
+        let skipped = skip.map(|k| self.slots[k]);
+        let eligible = |s: usize| match skipped {
+            Some((t, _)) if t == s => self.species[s].len() - 1,
+            _ => self.species[s].len(),
+        };
+        let species_weight = |s: usize| eligible(s) as f64 * weight(self.species[s].len());
+        let total = (0..self.species.len()).map(species_weight).sum::<f64>();
+        if !(total > 0.0 && total.is_finite()) {
+            return None;
+        }
+        let mut x = rng.gen_range(0.0..total);
+        // Rounding may leave `x` just past the last weight, so fall back to the last species
+        // that can be drawn
+        let s = (0..self.species.len())
+            .filter(|&s| species_weight(s) > 0.0)
+            .find(|&s| {
+                x -= species_weight(s);
+                x < 0.0
+            })
+            .or_else(|| (0..self.species.len()).rfind(|&s| species_weight(s) > 0.0))?;
+        let mut m = rng.gen_range(0..eligible(s));
+        if let Some((t, n)) = skipped {
+            if t == s && m >= n {
+                m += 1;
+            }
+        }
+        Some(self.species[s][m])
+    }
+
+    /// Position of the first copy of the most common expression, or of the one whose first
+    /// copy comes first if several are equally common.
+    pub(crate) fn first_of_most_common(&self) -> Option<usize> {
+        let most = self.species.iter().map(Vec::len).max()?;
+        self.species
+            .iter()
+            .filter(|copies| copies.len() == most)
+            .filter_map(|copies| copies.iter().min().copied())
+            .min()
+    }
+
+    /// Index into `species` of the expression of `entry`, if the population holds a copy of it.
+    fn species_of(&self, entry: &Entry<P>) -> Option<usize> {
+        self.kinds
+            .get(&entry.hash)?
+            .iter()
+            .copied()
+            .find(|&s| self.entries[self.species[s][0]].particle == entry.particle)
+    }
+
+    /// Take the entry at `k` out of its species, dropping the species once it has no copies
+    /// left. The entry itself stays in place.
+    fn detach(&mut self, k: usize) {
+        let (s, m) = self.slots[k];
+        let copies = &mut self.species[s];
+        copies.swap_remove(m);
+        if let Some(&moved) = copies.get(m) {
+            self.slots[moved].1 = m;
+        }
+        if !copies.is_empty() {
+            return;
+        }
+        self.reindex(self.entries[k].hash, s, None);
+        self.species.swap_remove(s);
+        if let Some(copies) = self.species.get(s) {
+            let moved = self.species.len();
+            self.reindex(self.entries[copies[0]].hash, moved, Some(s));
+            for &c in &self.species[s] {
+                self.slots[c].0 = s;
+            }
+        }
+    }
+
+    /// Move the species indexed under `hash` at `from` to `to`, or drop it from the index if
+    /// `to` is `None`.
+    fn reindex(&mut self, hash: u64, from: usize, to: Option<usize>) {
+        let kinds = self.kinds.get_mut(&hash).expect("species must be indexed");
+        let i = kinds
+            .iter()
+            .position(|&s| s == from)
+            .expect("species must be indexed");
+        match to {
+            Some(to) => kinds[i] = to,
+            None => {
+                kinds.swap_remove(i);
+                if kinds.is_empty() {
+                    self.kinds.remove(&hash);
+                }
+            }
+        }
+    }
+}
+
+impl<P> Deref for Population<P> {
+    type Target = [Entry<P>];
+
+    fn deref(&self) -> &[Entry<P>] {
+        &self.entries
+    }
+}
+
+impl<P: Particle + Clone + Hash + Eq> Extend<Entry<P>> for Population<P> {
+    fn extend<I: IntoIterator<Item = Entry<P>>>(&mut self, entries: I) {
+        for entry in entries {
+            self.push(entry);
+        }
+    }
+}
+
+impl<P> IntoIterator for Population<P> {
+    type Item = Entry<P>;
+    type IntoIter = std::vec::IntoIter<Entry<P>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a, P> IntoIterator for &'a Population<P> {
+    type Item = &'a Entry<P>;
+    type IntoIter = std::slice::Iter<'a, Entry<P>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+/// Periodic snapshots of a soup, produced by `Soup::simulate_and_record`.
+pub struct Tape<P, C, T, E> {
+    soup: Soup<P, C, T, E>,
+    history: Vec<Soup<P, C, T, E>>,
+    polling_interval: usize,
+}
+
+/// A log of every successful reaction in a soup, in the order they happened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReactionTape<P, T> {
+    pub reactions: Vec<ReactionRecord<P, T>>,
+}
+
+/// A single successful reaction: the two parents, and the residue of their collision, which
+/// holds the products and the rules that made them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReactionRecord<P, T> {
+    /// Index of the collision within the lifetime of the soup.
+    pub step: usize,
+    pub left: P,
+    pub right: P,
+    pub result: T,
+}
+
+impl<P, T> ReactionTape<P, T> {
+    pub fn new() -> Self {
+        ReactionTape {
+            reactions: Vec::new(),
+        }
+    }
+}
+
+impl<P, T> Default for ReactionTape<P, T> {
+    fn default() -> Self {
+        ReactionTape::new()
+    }
+}
+
+impl<P, C, T, E> Soup<P, C, T, E>
+where
+    P: Particle + Display + Clone + Hash + Eq,
+    C: Collider<P, T, E> + Clone,
+    T: Display + Clone + Residue<P>,
+    E: Display + Clone + std::error::Error,
+{
+    /// Introduce all expressions in `expressions` into the soup, without
+    /// reduction. Takes any iterator by value, so generated or streamed expressions can be
+    /// added without collecting them first. Nothing is evicted to make room, even if the soup
+    /// maintains a constant population size.
+    pub fn perturb(&mut self, expressions: impl IntoIterator<Item = P>) {
+        for particle in expressions {
+            self.insert(particle, None);
+        }
+    }
+
+    fn insert(&mut self, particle: P, origin: Option<Origin>) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let birth = self.total_collisions;
+        if let Some(lineage) = &mut self.lineage {
+            let record = LineageRecord {
+                particle: particle.clone(),
+                birth,
+                origin,
+            };
+            lineage.record(id, record);
+        }
+        self.expressions.push(Entry::new(particle, birth, id));
+    }
+
+    /// Add the products of a reaction between `left` and `right`.
+    fn add_products(&mut self, result: &T, left: &Entry<P>, right: &Entry<P>) {
+        for (i, particle) in result.particles().enumerate() {
+            let rule = result.rule_of(i);
+            if let Some(genealogy) = &mut self.genealogy {
+                genealogy.record(&particle, &left.particle, &right.particle, rule);
+            }
+            let origin = Origin {
+                left: left.id,
+                right: right.id,
+                rule,
+            };
+            self.insert(particle, Some(origin));
+        }
+        if let Some(lineage) = &mut self.lineage {
+            lineage.prune(self.expressions.iter().map(|e| e.id));
+        }
+    }
+
+    /// Move all expressions of `other` into this soup, which keeps its own rules and
+    /// configuration. If `deduplicate` is set, expressions isomorphic to one already in the soup
+    /// are dropped instead; this is quadratic in the size of the soup.
+    pub fn absorb(&mut self, other: Self, deduplicate: bool) {
+        for entry in other.expressions {
+            if deduplicate
+                && self
+                    .expressions()
+                    .any(|p| p.is_isomorphic_to(&entry.particle))
+            {
+                continue;
+            }
+            self.perturb([Arc::unwrap_or_clone(entry.particle)]);
+        }
+    }
+
+    /// Deal the expressions of the soup randomly into `k` new soups with the same rules and
+    /// configuration, whose sizes differ by at most one. Each new soup gets its own RNG seeded
+    /// from `rng`, and starts counting collisions from zero.
+    ///
+    /// An inflow is divided evenly between the new soups, so together they take in as much as
+    /// the soup did. Each gets `1 / k` of the rate, and a generated inflow gets a generator of
+    /// its own, as clones of the inflow do.
+    pub fn split(self, k: usize, rng: &mut impl Rng) -> Vec<Self> {
+        assert!(k > 0, "cannot split a soup into zero soups");
+        let mut children = (0..k)
+            .map(|_| {
+                let mut child = self.empty_copy(ChaCha8Rng::from_seed(rng.gen()));
+                if let Some(inflow) = &mut child.inflow {
+                    inflow.share(k);
+                }
+                child
+            })
+            .collect::<Vec<_>>();
+        let mut particles = self
+            .expressions
+            .into_iter()
+            .map(|e| Arc::unwrap_or_clone(e.particle))
+            .collect::<Vec<_>>();
+        particles.shuffle(rng);
+        for (n, particle) in particles.into_iter().enumerate() {
+            children[n % k].perturb([particle]);
+        }
+        children
+    }
+
+    /// Remove up to `n` expressions chosen with `rng`, rather than with the soup's own RNG, and
+    /// return them.
+    pub fn take_random(&mut self, n: usize, rng: &mut impl Rng) -> Vec<P> {
+        (0..n.min(self.expressions.len()))
+            .map(|_| {
+                let k = rng.gen_range(0..self.expressions.len());
+                Arc::unwrap_or_clone(self.expressions.swap_remove(k).particle)
+            })
+            .collect()
+    }
+
+    /// An empty soup with the same configuration, fresh counters and the given RNG.
+    pub(crate) fn empty_copy(&self, rng: ChaCha8Rng) -> Self {
+        Soup {
+            expressions: Population::new(),
+            total_collisions: 0,
+            successful_collisions: 0,
+            collider: self.collider.clone(),
+            maintain_constant_population_size: self.maintain_constant_population_size,
+            discard_parents: self.discard_parents,
+            protect_new_products: self.protect_new_products,
+            require_distinct_reactants: self.require_distinct_reactants,
+            selection_strategy: self.selection_strategy,
+            cursor: 0,
+            eviction_policy: self.eviction_policy,
+            decay_probability: self.decay_probability,
+            max_population: self.max_population,
+            decayed: 0,
+            rng,
+            tape: self.tape.as_ref().map(|_| ReactionTape::new()),
+            inflow: self.inflow.clone(),
+            schedule: Vec::new(),
+            convergence: self.convergence.clone(),
+            lineage: self.lineage.as_ref().map(|l| l.emptied()),
+            genealogy: self.genealogy.as_ref().map(|_| GenealogyTree::new()),
+            removed_ages: self.removed_ages.emptied(),
+            since_poll: ReductionWork::default(),
+            next_id: 0,
+            t: PhantomData,
+            e: PhantomData,
+        }
+    }
+
+    /// Produce one atomic reaction on the soup. Returns `None`, leaving the expressions in the
+    /// soup as they were, if the soup requires distinct reactants and could not find a partner
+    /// for the first reactant that isn't isomorphic to it.
+    pub fn react(&mut self) -> Option<Result<T, E>> {
+        let (left, right) = match self.selection_strategy {
+            SelectionStrategy::Random => self.select_random()?,
+            SelectionStrategy::RoundRobin => self.select_round_robin()?,
+            SelectionStrategy::FrequencyWeighted => self.select_weighted(|n| n as f64)?,
+            SelectionStrategy::InverseFrequencyWeighted => {
+                self.select_weighted(|n| 1.0 / n as f64)?
+            }
+        };
+
+        // Add collision results to soup
+        let step = self.total_collisions;
+        self.total_collisions += 1;
+        self.since_poll.collisions += 1;
+
+        let result = self
+            .collider
+            .collide(&left.particle, &right.particle, &mut self.rng);
+
+        if let (Some(tape), Ok(t)) = (&mut self.tape, &result) {
+            tape.reactions.push(ReactionRecord {
+                step,
+                left: P::clone(&left.particle),
+                right: P::clone(&right.particle),
+                result: t.clone(),
+            });
+        }
+
+        if let Ok(ref t) = result {
+            self.successful_collisions += 1;
+            self.since_poll.successes += 1;
+            self.since_poll.products += t.count();
+            self.since_poll.reductions += t.reductions();
+
+            // Remove additional expressions, if required. Protected products are only added once
+            // the victims are gone, so they can't be culled by the reaction that made them.
+            if self.protect_new_products {
+                self.evict(t.count());
+                self.add_products(t, &left, &right);
+            } else {
+                self.add_products(t, &left, &right);
+                self.evict(t.count());
+            }
+        }
+
+        // Add removed parents back into the soup, if necessary
+        if self.discard_parents {
+            self.record_removal(&left);
+            self.record_removal(&right);
+        } else {
+            self.expressions.push(left);
+            self.expressions.push(right);
+        }
+
+        if let Some(inflow) = &mut self.inflow {
+            let injected = inflow.tick();
+            self.inject(injected);
+        }
+
+        // Only touch the RNG if decay is enabled, so runs without decay are unchanged
+        if self.decay_probability > 0.0
+            && self.expressions.len() > 2
+            && self.rng.gen_bool(self.decay_probability)
+        {
+            let k = self.rng.gen_range(0..self.expressions.len());
+            let entry = self.expressions.swap_remove(k);
+            self.record_removal(&entry);
+            self.decayed += 1;
+        }
+
+        if let Some(cap) = self.max_population {
+            while self.expressions.len() > cap {
+                let k = self.rng.gen_range(0..self.expressions.len());
+                let entry = self.expressions.swap_remove(k);
+                self.record_removal(&entry);
+            }
+        }
+
+        Some(result)
+    }
+
+    /// Remove two distinct expressions randomly from the soup.
+    fn select_random(&mut self) -> Option<(Entry<P>, Entry<P>)> {
+        let n_expr = self.expressions.len();
+        let i = self.rng.gen_range(0..n_expr);
+        let left = self.expressions.swap_remove(i);
+
+        let j = if self.require_distinct_reactants {
+            let Some(j) = self.distinct_partner(&left.particle) else {
+                // Undo the `swap_remove`, so the order of the soup is unchanged
+                self.expressions.push(left);
+                let last = self.expressions.len() - 1;
+                self.expressions.swap(i, last);
+                return None;
+            };
+            j
+        } else {
+            self.rng.gen_range(0..n_expr - 1)
+        };
+        let right = self.expressions.swap_remove(j);
+        Some((left, right))
+    }
+
+    /// Remove the expression under the cursor and the one after it. If the soup requires
+    /// distinct reactants, the partner is the first expression after the cursor that isn't
+    /// isomorphic to it, looking at most `DISTINCT_REACTANT_ATTEMPTS` positions ahead. If there
+    /// is none, the cursor moves on by one and nothing is removed.
+    fn select_round_robin(&mut self) -> Option<(Entry<P>, Entry<P>)> {
+        let n_expr = self.expressions.len();
+        let i = self.cursor % n_expr;
+        let lookahead = if self.require_distinct_reactants {
+            DISTINCT_REACTANT_ATTEMPTS
+        } else {
+            1
+        };
+        let partner = (1..n_expr)
+            .take(lookahead)
+            .map(|k| (i + k) % n_expr)
+            .find(|&j| {
+                !self.require_distinct_reactants
+                    || !self.expressions[j]
+                        .particle
+                        .is_isomorphic_to(&self.expressions[i].particle)
+            });
+        let Some(j) = partner else {
+            self.cursor = i + 1;
+            return None;
+        };
+        self.cursor = i + 2;
+        Some(self.remove_pair(i, j))
+    }
+
+    /// Remove two expressions, each drawn with a weight of `weight(n)`, where `n` is the number
+    /// of copies of it in the soup. The draw reads the copies the population keeps track of,
+    /// so nothing is rebuilt per reaction. If the soup requires distinct reactants, the
+    /// partner is redrawn up to `DISTINCT_REACTANT_ATTEMPTS` times.
+    fn select_weighted(&mut self, weight: impl Fn(usize) -> f64) -> Option<(Entry<P>, Entry<P>)> {
+        let i = self
+            .expressions
+            .sample_weighted(&weight, None, &mut self.rng)
+            .expect("soup must hold at least two expressions to react");
+
+        let attempts = if self.require_distinct_reactants {
+            DISTINCT_REACTANT_ATTEMPTS
+        } else {
+            1
+        };
+        let left = &self.expressions[i].particle;
+        let j = (0..attempts)
+            .map(|_| {
+                self.expressions
+                    .sample_weighted(&weight, Some(i), &mut self.rng)
+                    .expect("soup must hold at least two expressions to react")
+            })
+            .find(|&j| {
+                !self.require_distinct_reactants
+                    || !self.expressions[j].particle.is_isomorphic_to(left)
+            })?;
+        Some(self.remove_pair(i, j))
+    }
+
+    /// Remove the expressions at positions `i` and `j`, which must differ.
+    fn remove_pair(&mut self, i: usize, j: usize) -> (Entry<P>, Entry<P>) {
+        // Remove the later position first, so the earlier one still holds the same expression
+        if i < j {
+            let right = self.expressions.swap_remove(j);
+            let left = self.expressions.swap_remove(i);
+            (left, right)
+        } else {
+            let left = self.expressions.swap_remove(i);
+            let right = self.expressions.swap_remove(j);
+            (left, right)
+        }
+    }
+
+    /// Draw the index of an expression that is not isomorphic to `left`, trying at most
+    /// `DISTINCT_REACTANT_ATTEMPTS` times.
+    fn distinct_partner(&mut self, left: &P) -> Option<usize> {
+        (0..DISTINCT_REACTANT_ATTEMPTS)
+            .map(|_| self.rng.gen_range(0..self.expressions.len()))
+            .find(|&j| !self.expressions[j].particle.is_isomorphic_to(left))
+    }
+
+    /// Only collide expressions that are not isomorphic to each other. See
+    /// `config::Reactor::require_distinct_reactants`.
+    pub fn set_require_distinct_reactants(&mut self, require: bool) {
+        self.require_distinct_reactants = require;
+    }
+
+    /// Bound the population of the soup from above, or remove the bound with `None`. Panics if
+    /// the cap is below two, which would leave too few expressions to react. See
+    /// `config::Reactor::max_population`.
+    pub fn set_max_population(&mut self, cap: Option<usize>) {
+        assert!(
+            cap.is_none_or(|cap| cap >= 2),
+            "max population must be at least 2"
+        );
+        self.max_population = cap;
+    }
+
+    /// Change how the soup picks reactants. See `config::SelectionStrategy`.
+    pub fn set_selection_strategy(&mut self, strategy: SelectionStrategy) {
+        self.selection_strategy = strategy;
+    }
+
+    /// Add `particles` to the soup from outside, evicting as many expressions to make room if
+    /// the soup maintains a constant population size.
+    fn inject(&mut self, particles: Vec<P>) {
+        self.evict(particles.len());
+        self.perturb(particles);
+    }
+
+    /// Queue `expressions` to be injected right before the collision at `step` of the next
+    /// simulation, counting from the start of the `simulate_*` call. Events at steps the next
+    /// simulation doesn't reach stay queued, and are counted again from the start of the one
+    /// after. Injection evicts to make room, like inflow does.
+    pub fn schedule_injection(
+        &mut self,
+        step: usize,
+        expressions: impl IntoIterator<Item = impl Into<P>>,
+    ) {
+        let particles = expressions.into_iter().map(Into::into).collect();
+        self.schedule.push(ScheduledEvent {
+            step,
+            action: ScheduledAction::Inject(particles),
+        });
+    }
+
+    /// Queue the removal of every expression matching `predicate` right before the collision at
+    /// `step` of the next simulation. Steps are counted as for `schedule_injection`.
+    pub fn schedule_removal(
+        &mut self,
+        step: usize,
+        predicate: impl Fn(&P) -> bool + Send + Sync + 'static,
+    ) {
+        self.schedule.push(ScheduledEvent {
+            step,
+            action: ScheduledAction::Remove(Arc::new(predicate)),
+        });
+    }
+
+    /// End `simulate_for` and `simulate_and_poll` early once the standard deviation of
+    /// `measure` over the last `window` samples falls below `tolerance`. See
+    /// `simulate_for_until_stable` and `simulate_and_poll_until_stable` for when the measure is
+    /// sampled. Samples are not carried over from one simulation to the next.
+    pub fn halt_when_stable(&mut self, measure: fn(&Self) -> f32, window: usize, tolerance: f32) {
+        assert!(
+            window >= 2,
+            "convergence window must hold at least two samples"
+        );
+        self.convergence = Some(Convergence {
+            measure,
+            window,
+            tolerance,
+            recent: VecDeque::new(),
+        });
+    }
+
+    /// Remove the convergence criterion, so simulations always run to the end.
+    pub fn never_halt(&mut self) {
+        self.convergence = None;
+    }
+
+    fn reset_convergence(&mut self) {
+        if let Some(convergence) = &mut self.convergence {
+            convergence.recent.clear();
+        }
+    }
 
-    pub(crate) rng: ChaCha8Rng,
+    /// Sample the convergence measure, if there is one, and return whether it has converged.
+    fn has_converged(&mut self) -> bool {
+        let Some(mut convergence) = self.convergence.take() else {
+            return false;
+        };
+        let converged = convergence.sample(self);
+        self.convergence = Some(convergence);
+        converged
+    }
 
-    // TODO: Figure out how to get rid of these horrible phantomdatas
-    pub(crate) t: PhantomData<T>,
-    pub(crate) e: PhantomData<E>,
-}
+    /// Carry out the events scheduled for `step`, in the order they were queued.
+    fn run_schedule(&mut self, step: usize) {
+        if self.schedule.is_empty() {
+            return;
+        }
+        let (due, pending) = std::mem::take(&mut self.schedule)
+            .into_iter()
+            .partition(|e| e.step == step);
+        self.schedule = pending;
+        for event in due {
+            match event.action {
+                ScheduledAction::Inject(particles) => self.inject(particles),
+                ScheduledAction::Remove(predicate) => {
+                    self.expressions.retain(|e| !predicate(&e.particle))
+                }
+            }
+        }
+    }
 
-pub struct Tape<P, C, T, E> {
-    soup: Soup<P, C, T, E>,
-    history: Vec<Soup<P, C, T, E>>,
-    polling_interval: usize,
-}
+    /// Remove `n` expressions from the soup, chosen by its eviction policy, if it maintains a
+    /// constant population size, or as many as there are if the soup has fewer than `n`.
+    pub(crate) fn evict(&mut self, n: usize) {
+        if !self.maintain_constant_population_size {
+            return;
+        }
+        for _ in 0..n.min(self.expressions.len()) {
+            let k = self.eviction_candidate();
+            let entry = self.expressions.swap_remove(k);
+            self.record_removal(&entry);
+        }
+    }
 
-impl<P, C, T, E> Soup<P, C, T, E>
-where
-    P: Particle + Display + Clone,
-    C: Collider<P, T, E> + Clone,
-    T: Display + Clone + Residue<P>,
-    E: Display + Clone + std::error::Error,
-{
-    /// Introduce all expressions in `expressions` into the soup, without
-    /// reduction.
-    pub fn perturb(&mut self, expressions: impl IntoIterator<Item = P>) {
-        self.expressions.extend(expressions)
+    /// Remember the age of an expression displaced from the soup.
+    fn record_removal(&mut self, entry: &Entry<P>) {
+        self.removed_ages.push(self.total_collisions - entry.birth);
     }
 
-    /// Produce one atomic reaction on the soup.
-    pub fn react(&mut self) -> Result<T, E> {
-        let n_expr = self.expressions.len();
+    /// The distribution of the current ages of the expressions in the soup, and of the ages at
+    /// which recently displaced expressions were removed. An expression's age is the number of
+    /// collisions since it was added.
+    pub fn term_lifetime_statistics(&self) -> TermLifetimeStats {
+        let live = self
+            .expressions
+            .iter()
+            .map(|e| self.total_collisions - e.birth)
+            .collect();
+        TermLifetimeStats {
+            live: AgeDistribution::from_ages(live),
+            removed: AgeDistribution::from_ages(self.removed_ages.ages.iter().copied().collect()),
+        }
+    }
 
-        // Remove two distinct expressions randomly from the soup
-        let i = self.rng.gen_range(0..n_expr);
-        let left = self.expressions.swap_remove(i);
+    /// The collisions, products and reduction steps since the last poll, or since the soup was
+    /// made. The `simulate_and_*` methods start a new window right after each poll, so a poller
+    /// that reads this every `n` collisions sees the work of those `n` collisions alone. Outside
+    /// of them, start a window with `reset_reduction_stats`. A soup whose products take no
+    /// reduction steps has stopped computing, even if its entropy still moves.
+    pub fn reduction_stats_since_last_poll(&self) -> ReductionWork {
+        self.since_poll
+    }
 
-        let j = self.rng.gen_range(0..n_expr - 1);
-        let right = self.expressions.swap_remove(j);
+    /// Start a new window for `reduction_stats_since_last_poll`.
+    pub fn reset_reduction_stats(&mut self) {
+        self.since_poll = ReductionWork::default();
+    }
 
-        // Add collision results to soup
-        let result = self.collider.collide(left.clone(), right.clone());
+    /// Index of the next expression to evict. Must not be called on an empty soup. `Oldest`
+    /// breaks ties by id, and the other deterministic policies by the lowest index; since
+    /// `swap_remove` only moves the last expression, this stays reproducible for a given seed.
+    fn eviction_candidate(&mut self) -> usize {
+        let entries = self.expressions.iter().enumerate();
+        match self.eviction_policy {
+            EvictionPolicy::Random => self.rng.gen_range(0..self.expressions.len()),
+            EvictionPolicy::Oldest => entries.min_by_key(|(_, e)| (e.birth, e.id)).unwrap().0,
+            EvictionPolicy::Largest => entries.min_by_key(|(_, e)| Reverse(e.size)).unwrap().0,
+            EvictionPolicy::MostCommon => self.expressions.first_of_most_common().unwrap(),
+        }
+    }
 
-        if let Ok(ref t) = result {
-            self.perturb(t.particles());
+    /// Sort the expressions into groups of equal ones, using the hashes cached on insertion so
+    /// no expression is hashed again. Returns the group of each expression, and the size of each
+    /// group. Groups are numbered in order of their first expression.
+    fn groups(&self) -> (Vec<usize>, Vec<usize>) {
+        let mut buckets = HashMap::<u64, Vec<usize>>::new();
+        let mut groups = Vec::with_capacity(self.expressions.len());
+        let mut counts = Vec::new();
+        let mut first = Vec::<usize>::new();
+        for (i, entry) in self.expressions.iter().enumerate() {
+            let bucket = buckets.entry(entry.hash).or_default();
+            let found = bucket
+                .iter()
+                .copied()
+                .find(|&g| self.expressions[first[g]].particle == entry.particle);
+            let group = found.unwrap_or_else(|| {
+                bucket.push(counts.len());
+                first.push(i);
+                counts.push(0);
+                counts.len() - 1
+            });
+            counts[group] += 1;
+            groups.push(group);
+        }
+        (groups, counts)
+    }
 
-            // Remove additional expressions, if required.
-            if self.maintain_constant_population_size {
-                for _ in 0..t.count() {
-                    let k = self.rng.gen_range(0..self.expressions.len());
-                    self.expressions.swap_remove(k);
-                }
+    /// The distinct expressions in the soup, each with the number of copies of it, in order of
+    /// first appearance. Cheaper than hashing every expression into a map, since it uses the
+    /// hashes cached when the expressions were added.
+    pub(crate) fn expression_multiplicities(&self) -> Vec<(&P, usize)> {
+        let (groups, counts) = self.groups();
+        let mut seen = vec![false; counts.len()];
+        let mut multiplicities = Vec::with_capacity(counts.len());
+        for (entry, group) in self.expressions.iter().zip(groups) {
+            if !seen[group] {
+                seen[group] = true;
+                multiplicities.push((&*entry.particle, counts[group]));
             }
         }
+        multiplicities
+    }
 
-        // Add removed parents back into the soup, if necessary
-        if !self.discard_parents {
-            self.expressions.push(left);
-            self.expressions.push(right);
+    /// Whether to poll the soup after the `i`th of `n` collisions. Polls land every
+    /// `polling_interval` collisions from the first one on, so every full interval of the run
+    /// holds one. Only a run shorter than one interval is polled after its last collision instead.
+    fn should_poll(i: usize, n: usize, polling_interval: usize) -> bool {
+        match polling_interval {
+            0 => true,
+            k if k > n => i == n - 1,
+            k => i % k == 0,
         }
-
-        result.clone()
     }
 
-    fn log_message_from_reaction(reaction: &Result<T, E>) -> String {
+    fn log_message_from_reaction(reaction: &Option<Result<T, E>>) -> String {
         match reaction {
-            Ok(result) => format!("successful with {}", result),
-            Err(message) => format!("failed because {}", message),
+            Some(Ok(result)) => format!("successful with {}", result),
+            Some(Err(message)) => format!("failed because {}", message),
+            None => String::from("skipped for lack of distinct reactants"),
         }
     }
 
@@ -111,10 +1395,21 @@ where
     /// out a log message for each reaction. Returns the number of successful reactions
     /// (the fraction of failed reactions).
     pub fn simulate_for(&mut self, n: usize, log: bool) -> usize {
+        self.simulate_for_until_stable(n, log).0
+    }
+
+    /// Like `simulate_for`, but also say why the simulation ended. If a convergence criterion
+    /// is set with `halt_when_stable`, its measure is sampled once per generation, that is,
+    /// after as many collisions as there are expressions in the soup, and the simulation ends
+    /// as soon as it converges.
+    pub fn simulate_for_until_stable(&mut self, n: usize, log: bool) -> (usize, Termination) {
+        self.reset_convergence();
         let mut n_successes = 0;
+        let mut since_sample = 0;
         for i in 0..n {
+            self.run_schedule(i);
             let reaction = self.react();
-            if reaction.is_ok() {
+            if matches!(reaction, Some(Ok(_))) {
                 n_successes += 1;
             }
 
@@ -122,10 +1417,66 @@ where
                 let message = Self::log_message_from_reaction(&reaction);
                 println!("reaction {:?} {}", i, message)
             }
+
+            since_sample += 1;
+            if since_sample >= self.expressions.len().max(1) {
+                since_sample = 0;
+                if self.has_converged() {
+                    return (n_successes, Termination::Converged { collisions: i + 1 });
+                }
+            }
+        }
+        (n_successes, Termination::Exhausted)
+    }
+
+    /// Like `simulate_for`, but return the outcome of every collision, in order, rather than
+    /// counting the successful ones. For a `LambdaSoup`, each successful outcome holds the
+    /// products, reduction counts and sizes of the reaction.
+    pub fn simulate_and_collect(&mut self, n: usize, log: bool) -> Vec<Option<Result<T, E>>> {
+        let mut reactions = Vec::with_capacity(n);
+        for i in 0..n {
+            self.run_schedule(i);
+            let reaction = self.react();
+            if log {
+                let message = Self::log_message_from_reaction(&reaction);
+                println!("reaction {:?} {}", i, message)
+            }
+            reactions.push(reaction);
+        }
+        reactions
+    }
+
+    /// Simulate the soup for `n` collisions, adding the expressions returned by `source` after
+    /// every `every` collisions, and return the number of successful reactions. An `every` of
+    /// `0` never injects. If the soup maintains a constant population size, as many
+    /// expressions are evicted as are injected, so the population stays the same.
+    pub fn simulate_with_injection<I>(
+        &mut self,
+        n: usize,
+        every: usize,
+        mut source: impl FnMut() -> I,
+    ) -> usize
+    where
+        I: IntoIterator,
+        I::Item: Into<P>,
+    {
+        let mut n_successes = 0;
+        for i in 1..=n {
+            self.run_schedule(i - 1);
+            if matches!(self.react(), Some(Ok(_))) {
+                n_successes += 1;
+            }
+            if every > 0 && i % every == 0 {
+                self.inject(source().into_iter().map(Into::into).collect());
+            }
         }
         n_successes
     }
 
+    /// Simulate the soup for `n` collisions, calling `poller` on the soup every `polling_interval`
+    /// collisions and collecting the results. A `polling_interval` of `0` polls after every
+    /// collision, and one larger than `n` polls once, after the last collision. If `log` is set,
+    /// then print out a log message for each reaction.
     pub fn simulate_and_poll<F, R>(
         &mut self,
         n: usize,
@@ -136,20 +1487,86 @@ where
     where
         F: Fn(&Self) -> R,
     {
+        self.simulate_and_poll_until_stable(n, polling_interval, log, poller)
+            .0
+    }
+
+    /// Like `simulate_and_poll`, but also say why the simulation ended. If a convergence
+    /// criterion is set with `halt_when_stable`, its measure is sampled at every poll, and the
+    /// simulation ends right after the poll at which it converges.
+    pub fn simulate_and_poll_until_stable<F, R>(
+        &mut self,
+        n: usize,
+        polling_interval: usize,
+        log: bool,
+        poller: F,
+    ) -> (Vec<R>, Termination)
+    where
+        F: Fn(&Self) -> R,
+    {
+        self.reset_convergence();
         let mut data: Vec<R> = Vec::new();
         for i in 0..n {
+            self.run_schedule(i);
             let reaction = self.react();
-            if (i % polling_interval) == 0 {
-                data.push(poller(self))
-            }
             if log {
                 let message = Self::log_message_from_reaction(&reaction);
                 println!("reaction {:?} {}", i, message)
             }
+            if Self::should_poll(i, n, polling_interval) {
+                data.push(poller(self));
+                self.reset_reduction_stats();
+                if self.has_converged() {
+                    return (data, Termination::Converged { collisions: i + 1 });
+                }
+            }
         }
-        data
+        (data, Termination::Exhausted)
+    }
+
+    /// Like `simulate_and_poll`, but return the samples as a stream instead of collecting them.
+    /// The stream is lazy: the soup only runs up to the next poll when the next sample is
+    /// requested, so samples can be processed and dropped as they arrive, and a consumer that
+    /// stops early stops the simulation with it.
+    pub fn simulate_and_poll_stream<'a, F, R>(
+        &'a mut self,
+        n: usize,
+        polling_interval: usize,
+        log: bool,
+        poller: F,
+    ) -> impl Stream<Item = R> + 'a
+    where
+        F: Fn(&Self) -> R + 'a,
+        R: 'a,
+    {
+        stream::unfold((self, 0), move |(soup, mut i)| {
+            let sample = loop {
+                if i >= n {
+                    break None;
+                }
+                soup.run_schedule(i);
+                let reaction = soup.react();
+                let sample = Self::should_poll(i, n, polling_interval).then(|| {
+                    let sample = poller(soup);
+                    soup.reset_reduction_stats();
+                    sample
+                });
+                if log {
+                    let message = Self::log_message_from_reaction(&reaction);
+                    println!("reaction {:?} {}", i, message)
+                }
+                i += 1;
+                if sample.is_some() {
+                    break sample;
+                }
+            };
+            future::ready(sample.map(|r| (r, (soup, i))))
+        })
     }
 
+    /// Like `simulate_and_poll`, but `killpoller` also returns whether to stop the simulation.
+    /// The simulation stops right after the first poll that returns `true`, and the sample from
+    /// that poll is the last element of the returned vector.
     pub fn simulate_and_poll_with_killer<F, R>(
         &mut self,
         n: usize,
@@ -162,9 +1579,11 @@ where
     {
         let mut data: Vec<R> = Vec::new();
         for i in 0..n {
+            self.run_schedule(i);
             let reaction = self.react();
-            if (i % polling_interval) == 0 {
+            if Self::should_poll(i, n, polling_interval) {
                 let (datum, should_kill) = killpoller(self);
+                self.reset_reduction_stats();
                 data.push(datum);
                 if should_kill {
                     return data;
@@ -189,9 +1608,11 @@ where
     ) -> Tape<P, C, T, E> {
         let mut history: Vec<Self> = Vec::new();
         for i in 0..n {
+            self.run_schedule(i);
             let reaction = self.react();
-            if (i % polling_interval) == 0 {
-                history.push(self.clone())
+            if Self::should_poll(i, n, polling_interval) {
+                history.push(self.clone());
+                self.reset_reduction_stats();
             }
             if log {
                 let message = Self::log_message_from_reaction(&reaction);
@@ -208,14 +1629,14 @@ where
 
     /// Print out all expressions within the soup. Defaults to Church notation.
     pub fn print(&self) {
-        for expression in &self.expressions {
+        for expression in self.expressions() {
             println!("{}", expression)
         }
     }
 
     /// Get an iterator over all expressions.
     pub fn expressions(&self) -> impl Iterator<Item = &P> {
-        self.expressions.iter()
+        self.expressions.iter().map(|e| &*e.particle)
     }
 
     /// Get the number of expressions in the soup.
@@ -223,9 +1644,142 @@ where
         self.expressions.len()
     }
 
-    /// Get the number of successful collisions
+    /// Each distinct expression in the soup, with the number of copies of it, in no particular
+    /// order. The copies are kept track of as the soup changes, so this takes time in the number
+    /// of distinct expressions only, unlike `expression_multiplicities`.
+    pub fn live_counts(&self) -> impl Iterator<Item = (&P, usize)> {
+        self.expressions.counts()
+    }
+
+    /// The `k` most common distinct expressions in the soup with their counts, most common
+    /// first. Ties are broken by the `Display` form of the expressions, then by their `Debug`
+    /// form, which tells apart particles that display the same, such as a test expression and
+    /// a molecule with the same term. A population thus gives the same order however it was
+    /// built. See `LambdaSoup::k_most_frequent_exprs` for the same over bare terms.
+    pub fn k_most_frequent(&self, k: usize) -> Vec<(P, usize)>
+    where
+        P: Debug,
+    {
+        let mut counts = self
+            .live_counts()
+            .map(|(p, n)| (p.clone(), n))
+            .collect::<Vec<_>>();
+        counts.sort_by_cached_key(|(p, n)| (Reverse(*n), p.to_string(), format!("{p:?}")));
+        counts.truncate(k);
+        counts
+    }
+
+    /// Set the inflow of fresh expressions into the soup, or remove it with `None`. Inflow is
+    /// applied after every collision. If the soup maintains a constant population size, then
+    /// one expression is evicted for each injected one.
+    pub fn set_inflow(&mut self, inflow: Option<Inflow<P>>) {
+        self.inflow = inflow;
+    }
+
+    /// Trace the ancestry of the first expression in the soup for which `matches` returns
+    /// `true`. Returns `None` if lineage isn't being recorded, or no expression matches.
+    pub fn lineage_where(&self, matches: impl Fn(&P) -> bool) -> Option<Rc<LineageTree<P>>> {
+        let lineage = self.lineage.as_ref()?;
+        let entry = self.expressions.iter().find(|e| matches(&e.particle))?;
+        lineage.tree(entry.id)
+    }
+
+    /// Get the number of collisions so far, successful or not
     pub fn collisions(&self) -> usize {
-        self.n_collisions
+        self.total_collisions
+    }
+
+    /// Get the number of expressions removed by random decay so far
+    pub fn decayed(&self) -> usize {
+        self.decayed
+    }
+
+    /// Get the number of successful collisions so far
+    pub fn successful_collisions(&self) -> usize {
+        self.successful_collisions
+    }
+
+    /// Get the log of reactions, if this soup records one.
+    pub fn tape(&self) -> Option<&ReactionTape<P, T>> {
+        self.tape.as_ref()
+    }
+}
+
+impl<P, C, T, E> Soup<P, C, T, E>
+where
+    P: Particle + Serialize + DeserializeOwned,
+    C: Collider<P, T, E> + Serialize + DeserializeOwned,
+    T: Serialize + DeserializeOwned,
+{
+    /// Write the full state of the soup, including its RNG state and scheduled injections, to
+    /// the file at `path`. A soup restored with `load_from_path` continues along exactly the
+    /// same trajectory as the original.
+    ///
+    /// Closures are not saved. A soup with a scheduled removal, a generated inflow or a collider
+    /// holding closures, such as a custom filter, is rejected with `io::ErrorKind::InvalidInput`
+    /// and nothing is written. The convergence criterion set with
+    /// `halt_when_stable` is dropped, so set it again on the restored soup.
+    ///
+    /// The soup is first written to a `.partial` file next to `path`, which only replaces `path`
+    /// once the write has succeeded, so a failed save leaves an existing file untouched.
+    pub fn save_to_path(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        if let Some(reason) = self.unsaveable_reason() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, reason));
+        }
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+
+        let written = File::create(&partial).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            bincode::serialize_into(&mut writer, self).map_err(|e| bincode_to_io(*e))?;
+            writer.flush()
+        });
+        match written {
+            Ok(()) => fs::rename(&partial, path),
+            Err(e) => {
+                let _ = fs::remove_file(&partial);
+                Err(e)
+            }
+        }
+    }
+
+    /// Why `save_to_path` would reject the soup, if it would. Closures can't be written out.
+    fn unsaveable_reason(&self) -> Option<&'static str> {
+        let removal = |e: &ScheduledEvent<P>| matches!(e.action, ScheduledAction::Remove(_));
+        if self.schedule.iter().any(removal) {
+            return Some("a soup with a scheduled removal can't be saved");
+        }
+        if let Some(Inflow {
+            source: InflowSource::Generator { .. },
+            ..
+        }) = self.inflow
+        {
+            return Some("a soup with a generated inflow can't be saved");
+        }
+        if !self.collider.is_saveable() {
+            return Some("a soup whose collider holds closures can't be saved");
+        }
+        None
+    }
+
+    /// Restore a soup previously written out with `save_to_path`. Errors are reported as by
+    /// `save_to_path`: I/O errors as they are, and anything else as `io::ErrorKind::InvalidData`.
+    pub fn load_from_path(path: impl AsRef<Path>) -> io::Result<Self>
+    where
+        P: Clone + Hash + Eq,
+    {
+        let file = File::open(path)?;
+        bincode::deserialize_from(BufReader::new(file)).map_err(|e| bincode_to_io(*e))
+    }
+}
+
+/// Pass on the I/O errors of bincode as they are, and report the rest as invalid data.
+fn bincode_to_io(error: bincode::ErrorKind) -> io::Error {
+    match error {
+        bincode::ErrorKind::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
     }
 }
 
@@ -248,3 +1802,636 @@ where
         self.polling_interval
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io;
+
+    use async_std::task::block_on;
+    use futures::StreamExt;
+    use lambda_calculus::{parse, Classic};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::{Collider, Entry, Inflow, Particle, Population, ReductionWork, Residue};
+    use crate::config::{self, ConfigSeed, SelectionStrategy};
+    use crate::lambda::recursive::{ski_cycle, Filter, LambdaParticle, LambdaSoup};
+    use crate::lineage::LineageTree;
+
+    fn ski_soup() -> LambdaSoup {
+        ski_soup_from_config(config::Reactor::new())
+    }
+
+    fn ski_soup_from_config(cfg: config::Reactor) -> LambdaSoup {
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            seed: ConfigSeed::new([0; 32]),
+            ..cfg
+        });
+        soup.add_lambda_expressions(ski_cycle().take(30));
+        soup
+    }
+
+    #[test]
+    fn simulate_and_poll_samples_every_interval() {
+        let polls = ski_soup().simulate_and_poll(100, 10, false, |s| s.collisions());
+        assert_eq!(polls, (1..=91).step_by(10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn polling_stream_matches_simulate_and_poll() {
+        let polls = ski_soup().simulate_and_poll(1000, 100, false, |s| s.unique_expressions());
+        let mut soup = ski_soup();
+        let stream = soup.simulate_and_poll_stream(1000, 100, false, |s| s.unique_expressions());
+        assert_eq!(block_on(stream.collect::<Vec<_>>()), polls);
+        assert_eq!(soup.collisions(), 1000);
+
+        let mut soup = ski_soup();
+        let first = block_on(
+            soup.simulate_and_poll_stream(1000, 100, false, |s| s.collisions())
+                .take(2)
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(first, [1, 101]);
+        assert_eq!(soup.collisions(), 101);
+    }
+
+    #[test]
+    fn simulate_and_poll_with_zero_interval_samples_every_step() {
+        let polls = ski_soup().simulate_and_poll(20, 0, false, |s| s.collisions());
+        assert_eq!(polls, (1..=20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn simulate_and_poll_with_long_interval_samples_once_at_end() {
+        let polls = ski_soup().simulate_and_poll(20, 50, false, |s| s.collisions());
+        assert_eq!(polls, vec![20]);
+    }
+
+    #[test]
+    fn killer_that_never_fires_runs_to_completion() {
+        let mut soup = ski_soup();
+        let polls = soup.simulate_and_poll_with_killer(50, 10, false, |s| (s.collisions(), false));
+        assert_eq!(polls, vec![1, 11, 21, 31, 41]);
+        assert_eq!(soup.collisions(), 50);
+    }
+
+    #[test]
+    fn killer_that_fires_on_first_poll_keeps_its_sample() {
+        let mut soup = ski_soup();
+        let polls = soup.simulate_and_poll_with_killer(50, 10, false, |s| (s.collisions(), true));
+        assert_eq!(polls, vec![1]);
+        assert_eq!(soup.collisions(), 1);
+    }
+
+    #[test]
+    fn food_inflow_cycles_at_its_rate() {
+        let mut slow = Inflow::food(0.25, vec!['a', 'b']);
+        let injected = (0..8).flat_map(|_| slow.tick()).collect::<String>();
+        assert_eq!(injected, "ab");
+
+        let mut fast = Inflow::food(1.5, vec!['a', 'b', 'c']);
+        let per_tick = (0..4).map(|_| fast.tick().len()).collect::<Vec<_>>();
+        assert_eq!(per_tick, vec![1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn cloned_generator_inflows_are_independent() {
+        let counter = || {
+            let mut n = 0;
+            move || {
+                n += 1;
+                n
+            }
+        };
+        let mut inflow = Inflow::generator(1.0, counter);
+        let mut clone = inflow.clone();
+        let drawn = (0..3).flat_map(|_| inflow.tick()).collect::<Vec<_>>();
+        assert_eq!(drawn, vec![1, 2, 3]);
+        assert_eq!(clone.tick(), vec![1]);
+        assert_eq!(inflow.tick(), vec![4]);
+    }
+
+    #[test]
+    fn decay_stops_at_two_expressions() {
+        let mut soup = ski_soup_from_config(config::Reactor {
+            decay_probability: 1.0,
+            ..config::Reactor::new()
+        });
+        soup.simulate_for(100, false);
+        assert_eq!(soup.len(), 2);
+        assert_eq!(soup.decayed(), 28);
+    }
+
+    #[test]
+    fn split_deals_every_expression_deterministically() {
+        let split = || ski_soup().split(4, &mut ChaCha8Rng::from_seed([1; 32]));
+        let (first, second) = (split(), split());
+        let sizes = first.iter().map(|s| s.len()).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![8, 8, 7, 7]);
+        for (a, b) in first.iter().zip(&second) {
+            assert!(a.expressions().eq(b.expressions()));
+        }
+    }
+
+    #[test]
+    fn split_divides_a_generated_inflow_between_independent_soups() {
+        let split = || {
+            let mut soup = ski_soup();
+            soup.set_generated_inflow(0.8, || {
+                let mut ski = ski_cycle().skip(1);
+                move || ski.next().unwrap()
+            });
+            soup.split(4, &mut ChaCha8Rng::from_seed([1; 32]))
+        };
+        let (mut first, mut second) = (split(), split());
+        for child in &first {
+            assert_eq!(child.inflow.as_ref().unwrap().rate(), 0.2);
+        }
+
+        // Running one child must not change what another draws from its inflow
+        first[0].simulate_for(100, false);
+        first[1].simulate_for(100, false);
+        second[1].simulate_for(100, false);
+        assert!(first[1].expressions().eq(second[1].expressions()));
+    }
+
+    #[test]
+    fn lineage_traces_back_to_the_initial_population() {
+        let mut soup = ski_soup_from_config(config::Reactor {
+            record_lineage: true,
+            ..config::Reactor::new()
+        });
+        soup.simulate_for(2000, false);
+
+        fn check(tree: &LineageTree<LambdaParticle>) -> bool {
+            match &tree.parents {
+                None => tree.origin.is_none() && tree.birth == 0,
+                Some((left, right)) => {
+                    left.birth < tree.birth
+                        && right.birth < tree.birth
+                        && check(left)
+                        && check(right)
+                }
+            }
+        }
+        let trees = soup
+            .expressions()
+            .map(|p| soup.lineage_where(|q| q == p).unwrap())
+            .collect::<Vec<_>>();
+        assert!(trees.iter().all(|t| check(t)));
+        assert!(trees.iter().any(|t| t.parents.is_some()));
+        assert!(ski_soup().lineage_where(|_| true).is_none());
+    }
+
+    #[test]
+    fn injection_respects_a_constant_population() {
+        let omega = parse(r"\x.x x", Classic).unwrap();
+        let mut soup = ski_soup();
+        let mut calls = 0;
+        soup.simulate_with_injection(100, 10, || {
+            calls += 1;
+            vec![omega.clone(); 2]
+        });
+        assert_eq!(calls, 10);
+        assert_eq!(soup.len(), 30);
+
+        let mut soup = ski_soup();
+        soup.simulate_with_injection(100, 0, || -> Vec<LambdaParticle> { unreachable!() });
+        assert_eq!(soup.len(), 30);
+    }
+
+    #[test]
+    fn scheduled_events_happen_at_their_step() {
+        let marker = parse(r"\a.\b.\c.\d.d", Classic).unwrap();
+        let mut soup = ski_soup_from_config(config::Reactor {
+            maintain_constant_population_size: false,
+            ..config::Reactor::new()
+        });
+        soup.schedule_injection(5, vec![marker.clone(); 3]);
+        soup.schedule_injection(50, [marker.clone()]);
+        let pops = soup.simulate_and_poll(10, 0, false, |s| s.population_of(&marker));
+        assert!(pops[..5].iter().all(|&n| n == 0));
+        assert!(pops[5] >= 3);
+        assert_eq!(soup.schedule.len(), 1);
+
+        let target = marker.clone();
+        soup.schedule_removal(2, move |p| p.get_underlying_term() == &target);
+        let pops = soup.simulate_and_poll(4, 0, false, |s| s.population_of(&marker));
+        assert!(pops[0] >= 3);
+        assert_eq!(pops[2..], [0, 0]);
+    }
+
+    #[test]
+    fn population_never_exceeds_the_cap() {
+        let mut soup = ski_soup_from_config(config::Reactor {
+            maintain_constant_population_size: false,
+            max_population: Some(40),
+            ..config::Reactor::new()
+        });
+        let mut peak = 0;
+        for _ in 0..500 {
+            soup.react();
+            peak = peak.max(soup.len());
+        }
+        assert_eq!(peak, 40);
+
+        let mut soup = ski_soup_from_config(config::Reactor {
+            max_population: Some(20),
+            ..config::Reactor::new()
+        });
+        soup.react();
+        assert_eq!(soup.len(), 20);
+        soup.simulate_for(100, false);
+        assert_eq!(soup.len(), 20);
+
+        soup.set_max_population(Some(2));
+        soup.simulate_for(100, false);
+        assert_eq!(soup.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be at least 2")]
+    fn population_cap_must_leave_room_for_a_reaction() {
+        ski_soup().set_max_population(Some(1));
+    }
+
+    #[test]
+    fn distinct_reactants_are_never_isomorphic() {
+        let (k, i) = (r"\x.\y.x", r"\x.\y.y");
+        let mut soup = LambdaSoup::from_config(&config::Reactor {
+            require_distinct_reactants: true,
+            maintain_constant_population_size: false,
+            record_tape: true,
+            seed: ConfigSeed::new([0; 32]),
+            ..config::Reactor::new()
+        });
+        soup.add_lambda_expressions(
+            [k, i]
+                .iter()
+                .cycle()
+                .take(10)
+                .map(|s| parse(s, Classic).unwrap()),
+        );
+        for _ in 0..100 {
+            assert!(soup.react().is_some());
+        }
+        let tape = soup.tape().unwrap();
+        assert!(!tape.reactions.is_empty());
+        assert!(tape
+            .reactions
+            .iter()
+            .all(|r| !r.left.is_isomorphic_to(&r.right)));
+
+        let mut monoculture = ski_soup_from_config(config::Reactor {
+            require_distinct_reactants: true,
+            ..config::Reactor::new()
+        });
+        monoculture.retain(|t| *t == parse(k, Classic).unwrap());
+        let before = monoculture.expressions().cloned().collect::<Vec<_>>();
+        assert!(monoculture.react().is_none());
+        assert_eq!(monoculture.collisions(), 0);
+        assert!(monoculture.expressions().eq(before.iter()));
+    }
+
+    #[test]
+    fn round_robin_selection_ignores_the_seed() {
+        let run = |seed| {
+            let mut soup = ski_soup_from_config(config::Reactor {
+                selection_strategy: SelectionStrategy::RoundRobin,
+                maintain_constant_population_size: false,
+                record_tape: true,
+                seed: ConfigSeed::new([seed; 32]),
+                ..config::Reactor::new()
+            });
+            let first = soup.expressions().take(2).cloned().collect::<Vec<_>>();
+            soup.simulate_for(200, false);
+            (soup, first)
+        };
+        let (a, first) = run(0);
+        let (b, _) = run(1);
+        assert!(a.expressions().eq(b.expressions()));
+
+        let record = &a.tape().unwrap().reactions[0];
+        assert_eq!(record.step, 0);
+        assert_eq!([record.left.clone(), record.right.clone()], first[..]);
+    }
+
+    #[test]
+    fn weighted_selection_follows_the_counts() {
+        let (rare, common) = (
+            parse(r"\x.x", Classic).unwrap(),
+            parse(r"\x.\y.x", Classic).unwrap(),
+        );
+        let mut soup = LambdaSoup::new();
+        soup.add_lambda_expressions(std::iter::repeat_n(rare.clone(), 10));
+        soup.add_lambda_expressions(std::iter::repeat_n(common, 90));
+
+        let mut rare_draws = |weight: fn(usize) -> f64| {
+            (0..2000)
+                .filter(|_| {
+                    let (left, right) = soup.select_weighted(weight).unwrap();
+                    let drawn = left.particle.get_underlying_term() == &rare;
+                    soup.expressions.extend([left, right]);
+                    drawn
+                })
+                .count()
+        };
+        // The rare expression is picked first with probability 1/82 when weighted by
+        // frequency, and 1/2 when weighted by inverse frequency.
+        assert!(rare_draws(|n| n as f64) < 100);
+        assert!((800..1200).contains(&rare_draws(|n| 1.0 / n as f64)));
+    }
+
+    #[test]
+    fn recorded_reactions_replay_on_a_fresh_collider() {
+        let mut soup = ski_soup_from_config(config::Reactor {
+            record_tape: true,
+            ..config::Reactor::new()
+        });
+        soup.simulate_for(500, false);
+
+        let mut collider = ski_soup().collider.clone();
+        let mut rng = ChaCha8Rng::from_seed([0; 32]);
+        let reactions = &soup.tape().unwrap().reactions;
+        assert!(!reactions.is_empty());
+        for record in reactions {
+            let result = collider.collide(&record.left, &record.right, &mut rng);
+            assert_eq!(result.as_ref(), Ok(&record.result));
+        }
+    }
+
+    #[test]
+    fn collected_reactions_match_the_success_count() {
+        let successes = ski_soup().simulate_for(500, false);
+        let reactions = ski_soup().simulate_and_collect(500, false);
+        assert_eq!(reactions.len(), 500);
+        let ok = reactions.iter().flatten().flatten().collect::<Vec<_>>();
+        assert_eq!(ok.len(), successes);
+        for result in ok {
+            assert!(result.left_size > 0 && result.right_size > 0);
+            assert_eq!(result.sizes.len(), result.reductions.len());
+        }
+    }
+
+    /// Count the expressions of `soup` from scratch.
+    fn recount(soup: &LambdaSoup) -> HashMap<&LambdaParticle, usize> {
+        let mut counts = HashMap::new();
+        for particle in soup.expressions() {
+            *counts.entry(particle).or_default() += 1;
+        }
+        counts
+    }
+
+    /// The counts the soup keeps track of.
+    fn live_counts(soup: &LambdaSoup) -> HashMap<&LambdaParticle, usize> {
+        soup.live_counts().collect()
+    }
+
+    /// 50,000 collisions in all, spread over every combination of filters and population
+    /// settings.
+    #[test]
+    fn live_counts_match_a_recount() {
+        for flags in 0..32 {
+            let flag = |bit: usize| flags & (1 << bit) != 0;
+            let mut soup = ski_soup_from_config(config::Reactor {
+                discard_copy_actions: flag(0),
+                discard_identity: flag(1),
+                discard_free_variable_expressions: flag(2),
+                maintain_constant_population_size: flag(3),
+                protect_new_products: flag(4),
+                max_population: Some(60),
+                ..config::Reactor::new()
+            });
+            let k = parse(r"\x.\y.x", Classic).unwrap();
+            soup.perturb([LambdaParticle::from(k.clone())]);
+            soup.schedule_removal(800, move |p| *p.get_underlying_term() == k);
+            soup.simulate_for(1563, false);
+            assert_eq!(live_counts(&soup), recount(&soup), "flags {flags:05b}");
+
+            let population = &soup.expressions;
+            let indexed = population.kinds.values().map(Vec::len).sum::<usize>();
+            assert_eq!(population.species.len(), indexed);
+            for (k, entry) in population.iter().enumerate() {
+                let (s, m) = population.slots[k];
+                assert_eq!(population.species[s][m], k);
+                assert_eq!(population.species_of(entry), Some(s));
+            }
+        }
+    }
+
+    #[test]
+    fn expressions_with_colliding_hashes_are_told_apart() {
+        let (k, i) = (
+            LambdaParticle::from(parse(r"\x.\y.x", Classic).unwrap()),
+            LambdaParticle::from(parse(r"\x.x", Classic).unwrap()),
+        );
+        let collide = |particle: &LambdaParticle, id| Entry {
+            hash: 0,
+            ..Entry::new(particle.clone(), 0, id)
+        };
+        let mut population = Population::new();
+        population.extend([collide(&k, 0), collide(&i, 1), collide(&k, 2)]);
+        assert_eq!(population.species.len(), 2);
+        assert_eq!(population.kinds[&0].len(), 2);
+
+        population.swap_remove(0);
+        population.swap_remove(0);
+        let counts = population.counts().collect::<Vec<_>>();
+        assert_eq!(counts, [(&i, 1)]);
+        assert_eq!(population.species_of(&collide(&i, 3)), Some(0));
+        assert_eq!(population.species_of(&collide(&k, 3)), None);
+    }
+
+    #[test]
+    fn lifetimes_of_live_and_removed_expressions() {
+        let mut soup = ski_soup_from_config(config::Reactor {
+            removed_age_buffer: 50,
+            ..config::Reactor::new()
+        });
+        let stats = soup.term_lifetime_statistics();
+        assert_eq!((stats.live.count, stats.live.max), (30, 0));
+        assert_eq!(stats.live.histogram, [30]);
+        assert_eq!(stats.removed.count, 0);
+
+        let successes = soup.simulate_for(200, false);
+        let stats = soup.term_lifetime_statistics();
+        assert_eq!(stats.live.count, soup.len());
+        assert_eq!(stats.live.histogram.iter().sum::<usize>(), soup.len());
+        assert!(stats.live.min as f64 <= stats.live.median);
+        assert!(stats.live.median <= stats.live.max as f64);
+        assert!(stats.live.max <= 200);
+        assert_eq!(stats.removed.count, successes.min(50));
+        assert_eq!(stats.removed.cumulative().last(), Some(&1.0));
+    }
+
+    #[test]
+    fn reduction_work_is_counted_per_poll() {
+        let mut soup = ski_soup_from_config(config::Reactor {
+            record_tape: true,
+            ..config::Reactor::new()
+        });
+        let successes = soup.simulate_for(300, false);
+        let work = soup.reduction_stats_since_last_poll();
+        assert_eq!((work.collisions, work.successes), (300, successes));
+
+        let reactions = &soup.tape().unwrap().reactions;
+        let products = reactions.iter().map(|r| r.result.count()).sum::<usize>();
+        let reductions = reactions
+            .iter()
+            .map(|r| r.result.reductions())
+            .sum::<usize>();
+        assert_eq!((work.products, work.reductions), (products, reductions));
+        assert!(work.reductions > 0);
+        assert_eq!(work.mean_reductions(), reductions as f64 / products as f64);
+
+        assert_eq!(soup.reduction_stats_since_last_poll(), work);
+        soup.reset_reduction_stats();
+        assert_eq!(
+            soup.reduction_stats_since_last_poll(),
+            ReductionWork::default()
+        );
+        soup.simulate_for(10, false);
+        assert_eq!(soup.reduction_stats_since_last_poll().collisions, 10);
+
+        let windows = soup.simulate_and_poll(50, 10, false, |s| {
+            s.reduction_stats_since_last_poll().collisions
+        });
+        assert_eq!(windows, [11, 10, 10, 10, 10]);
+    }
+
+    #[test]
+    fn ties_in_the_most_frequent_are_broken_by_notation() {
+        let soup = ski_soup();
+        let top = soup.k_most_frequent(3);
+        assert_eq!(
+            top.iter().map(|(_, n)| *n).collect::<Vec<_>>(),
+            [10, 10, 10]
+        );
+        let names = top.iter().map(|(p, _)| p.to_string()).collect::<Vec<_>>();
+        assert!(names.windows(2).all(|w| w[0] < w[1]));
+
+        let mut reversed = LambdaSoup::new();
+        let particles = soup.expressions().cloned().collect::<Vec<_>>();
+        reversed.perturb(particles.into_iter().rev());
+        assert_eq!(reversed.k_most_frequent(3), top);
+        assert_eq!(reversed.k_most_frequent(2), top[..2]);
+
+        let mut soup = ski_soup();
+        soup.add_lambda_expressions([parse(r"\x.x", Classic).unwrap()]);
+        assert_eq!(soup.k_most_frequent(1)[0].1, 11);
+        assert_eq!(soup.k_most_frequent(10).len(), 3);
+
+        let id = parse(r"\x.x", Classic).unwrap();
+        let mut tests_first = LambdaSoup::new();
+        tests_first.add_test_expressions([id.clone()]);
+        tests_first.add_lambda_expressions([id.clone()]);
+        let mut molecules_first = LambdaSoup::new();
+        molecules_first.add_lambda_expressions([id.clone()]);
+        molecules_first.add_test_expressions([id]);
+        let top = tests_first.k_most_frequent(2);
+        assert_ne!(top[0].0, top[1].0);
+        assert_eq!(molecules_first.k_most_frequent(2), top);
+    }
+
+    #[test]
+    fn absorb_can_deduplicate() {
+        let mut soup = ski_soup();
+        soup.absorb(ski_soup(), true);
+        assert_eq!(soup.len(), 30);
+        soup.absorb(ski_soup(), false);
+        assert_eq!(soup.len(), 60);
+    }
+
+    #[test]
+    fn restored_soup_continues_the_same_trajectory() {
+        let mut soup = ski_soup();
+        soup.simulate_for(10000, false);
+
+        let path = std::env::temp_dir().join(format!("alchemy-soup-{}.bin", std::process::id()));
+        soup.save_to_path(&path).unwrap();
+        let mut restored = LambdaSoup::load_from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(restored.collisions(), 10000);
+        assert!(restored.expressions().eq(soup.expressions()));
+        assert_eq!(live_counts(&restored), live_counts(&soup));
+        assert_eq!(
+            format!("{:?}", restored.config()),
+            format!("{:?}", soup.config())
+        );
+
+        soup.simulate_for(1000, false);
+        restored.simulate_for(1000, false);
+        assert_eq!(
+            restored.successful_collisions(),
+            soup.successful_collisions()
+        );
+        assert!(restored.expressions().eq(soup.expressions()));
+    }
+
+    #[test]
+    fn saved_soups_resume_their_scheduled_injections() {
+        let path = std::env::temp_dir().join(format!("alchemy-resume-{}.bin", std::process::id()));
+        let k = parse(r"\x.\y.x", Classic).unwrap();
+        let mut soup = ski_soup();
+        soup.schedule_injection(50, [LambdaParticle::from(k.clone())]);
+        soup.save_to_path(&path).unwrap();
+        let mut restored = LambdaSoup::load_from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        soup.simulate_for(100, false);
+        restored.simulate_for(100, false);
+        assert!(restored.expressions().eq(soup.expressions()));
+
+        soup.schedule_removal(10, move |p| *p.get_underlying_term() == k);
+        let error = soup.save_to_path(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+
+        let error = LambdaSoup::load_from_path(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn failed_saves_leave_existing_files_untouched() {
+        let path = std::env::temp_dir().join(format!("alchemy-keep-{}.bin", std::process::id()));
+        let soup = ski_soup();
+        soup.save_to_path(&path).unwrap();
+        let saved = std::fs::read(&path).unwrap();
+
+        let mut removing = ski_soup();
+        removing.simulate_for(10, false);
+        removing.schedule_removal(10, |_| true);
+        let error = removing.save_to_path(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let mut generating = ski_soup();
+        generating.set_generated_inflow(0.5, || || parse(r"\x.x", Classic).unwrap());
+        let error = generating.save_to_path(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let mut filtering = ski_soup();
+        filtering.add_filter(Filter::custom(|_| false));
+        let error = filtering.save_to_path(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        assert_eq!(std::fs::read(&path).unwrap(), saved);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn deserialized_soups_count_their_expressions() {
+        let mut soup = ski_soup();
+        soup.simulate_for(100, false);
+        let bytes = bincode::serialize(&soup).unwrap();
+        let mut restored: LambdaSoup = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(live_counts(&restored), live_counts(&soup));
+        for (a, b) in restored.expressions.iter().zip(soup.expressions.iter()) {
+            assert_eq!((a.size, a.depth, a.hash), (b.size, b.depth, b.hash));
+        }
+
+        restored.simulate_for(100, false);
+        assert_eq!(live_counts(&restored), recount(&restored));
+    }
+}
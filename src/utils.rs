@@ -1,9 +1,10 @@
 use std::cmp::Ord;
 use std::{fmt, num::ParseIntError};
 
-use lambda_calculus::Term;
-use std::fs::OpenOptions;
+use lambda_calculus::{term::Notation, Term};
+use std::fs::{File, OpenOptions};
 use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
 
 // This was shamelessly stolen from
 // https://play.rust-lang.org/?version=stable&mode=debug&edition=2015&gist=e241493d100ecaadac3c99f37d0f766f
@@ -132,6 +133,29 @@ pub fn read_inputs() -> impl Iterator<Item = Term> {
     expressions.into_iter()
 }
 
+/// Read lambda expressions written in `notation` from the file at `path`, one per line, and
+/// return an iterator over them along with the number of lines that failed to parse and were
+/// skipped. Blank lines are ignored, and don't count as failures.
+pub fn read_inputs_from(
+    path: &Path,
+    notation: Notation,
+) -> io::Result<(impl Iterator<Item = Term>, usize)> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut expressions = Vec::new();
+    let mut skipped = 0;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match lambda_calculus::parse(line.trim(), notation) {
+            Ok(term) => expressions.push(term),
+            Err(_) => skipped += 1,
+        }
+    }
+    Ok((expressions.into_iter(), skipped))
+}
+
 pub fn dump_series_to_file<T>(fname: &str, series: &[T], id: &[usize]) -> io::Result<()>
 where
     T: fmt::Debug,